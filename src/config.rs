@@ -6,7 +6,45 @@ pub struct Config {
     pub jwt_secret: String,
     pub jwt_expiration: i64,
     pub db_url: String,
-    pub db_name: String
+    pub db_name: String,
+    pub search_backend: String,
+    pub search_index_path: String,
+    pub scheduler_interval_secs: u64,
+    pub trash_retention_days: i64,
+    pub trending_interval_secs: u64,
+    pub username_change_cooldown_days: i64,
+    pub app_url: String,
+    pub mail_backend: String,
+    pub mail_from: String,
+    pub mail_log_bodies: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: String,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub require_verified_email: bool,
+    pub password_hash_algorithm: String,
+    pub bcrypt_cost: u32,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub verification_ttl_hours: i64,
+    pub password_reset_ttl_minutes: i64,
+    pub email_resend_cooldown_secs: i64,
+    pub trust_proxy_headers: bool,
+    pub login_backoff_threshold: i32,
+    pub login_ip_backoff_threshold: i32,
+    pub login_backoff_base_secs: i64,
+    pub login_backoff_max_secs: i64,
+    pub login_lockout_threshold: i32,
+    pub login_lockout_minutes: i64,
+    pub totp_issuer: String,
+    pub two_factor_challenge_minutes: i64,
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_name: String,
+    pub webauthn_origin: String,
+    pub webauthn_timeout_secs: i64,
+    pub email_max_per_day: i32,
 }
 
 pub fn get_config() -> Config {
@@ -21,6 +59,137 @@ pub fn get_config() -> Config {
             .expect("JWT_EXPIRATION must be set")
             .parse()
             .expect("JWT_EXPIRATION must be an integer"),
-        db_name: env::var("DB_NAME").expect("JWT_SECRET must be set")
+        db_name: env::var("DB_NAME").expect("JWT_SECRET must be set"),
+        // "mongodb" (text indexes) or "local" (embedded tantivy index)
+        search_backend: env::var("SEARCH_BACKEND").unwrap_or_else(|_| "mongodb".to_string()),
+        search_index_path: env::var("SEARCH_INDEX_PATH").unwrap_or_else(|_| "search_index".to_string()),
+        // How often background jobs such as scheduled publishing run
+        scheduler_interval_secs: env::var("SCHEDULER_INTERVAL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30),
+        // Deleted posts, items and tags are purged after this many days
+        trash_retention_days: env::var("TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30),
+        // How often trending tags and posts are recomputed
+        trending_interval_secs: env::var("TRENDING_INTERVAL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300),
+        // Minimum time between two username changes of a user
+        username_change_cooldown_days: env::var("USERNAME_CHANGE_COOLDOWN_DAYS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(30),
+        // Base URL of links sent by email
+        app_url: env::var("APP_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string()),
+        // "smtp", or "log" to print emails instead of sending them
+        mail_backend: env::var("MAIL_BACKEND").unwrap_or_else(|_| "log".to_string()),
+        mail_from: env::var("MAIL_FROM").unwrap_or_else(|_| "no-reply@localhost".to_string()),
+        // Development only: have the log backend print bodies, links and tokens included
+        mail_log_bodies: env::var("MAIL_LOG_BODIES")
+            .map(|value| value == "true")
+            .unwrap_or(false),
+        // Defaults match a local mail catcher
+        smtp_host: env::var("SMTP_HOST").unwrap_or_else(|_| "localhost".to_string()),
+        smtp_port: env::var("SMTP_PORT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1025),
+        // "none", "starttls" or "tls"
+        smtp_tls: env::var("SMTP_TLS").unwrap_or_else(|_| "none".to_string()),
+        smtp_username: env::var("SMTP_USERNAME").ok(),
+        smtp_password: env::var("SMTP_PASSWORD").ok(),
+        // Keep unverified accounts to read-only requests
+        require_verified_email: env::var("REQUIRE_VERIFIED_EMAIL")
+            .map(|value| value == "true")
+            .unwrap_or(false),
+        // "argon2id" or "bcrypt"; hashes made with other settings are upgraded at login
+        password_hash_algorithm: env::var("PASSWORD_HASH_ALGORITHM")
+            .unwrap_or_else(|_| "argon2id".to_string()),
+        bcrypt_cost: env::var("BCRYPT_COST")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(12),
+        argon2_memory_kib: env::var("ARGON2_MEMORY_KIB")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(19456),
+        argon2_iterations: env::var("ARGON2_ITERATIONS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(2),
+        argon2_parallelism: env::var("ARGON2_PARALLELISM")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1),
+        verification_ttl_hours: env::var("VERIFICATION_TTL_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(24),
+        password_reset_ttl_minutes: env::var("PASSWORD_RESET_TTL_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(60),
+        // Limits on account emails (verification, password reset, unlock) per user
+        email_resend_cooldown_secs: env::var("EMAIL_RESEND_COOLDOWN_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(60),
+        email_max_per_day: env::var("EMAIL_MAX_PER_DAY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(5),
+        // Take the client IP from X-Forwarded-For/Forwarded; only behind a proxy
+        trust_proxy_headers: env::var("TRUST_PROXY_HEADERS")
+            .map(|value| value == "true")
+            .unwrap_or(false),
+        // Failed logins allowed before each further one doubles the wait,
+        // per account and per IP address
+        login_backoff_threshold: env::var("LOGIN_BACKOFF_THRESHOLD")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(3),
+        login_ip_backoff_threshold: env::var("LOGIN_IP_BACKOFF_THRESHOLD")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(20),
+        login_backoff_base_secs: env::var("LOGIN_BACKOFF_BASE_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1),
+        login_backoff_max_secs: env::var("LOGIN_BACKOFF_MAX_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(900),
+        // Failed logins that lock an account until it's unlocked by email; the
+        // lockout also sets how long failures are remembered
+        login_lockout_threshold: env::var("LOGIN_LOCKOUT_THRESHOLD")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10),
+        login_lockout_minutes: env::var("LOGIN_LOCKOUT_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(60),
+        // Name authenticator apps list accounts under
+        totp_issuer: env::var("TOTP_ISSUER").unwrap_or_else(|_| "Server".to_string()),
+        // Time to enter the second factor after the password
+        two_factor_challenge_minutes: env::var("TWO_FACTOR_CHALLENGE_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(5),
+        // Passkeys are bound to this domain, and ceremonies must come from the origin
+        webauthn_rp_id: env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| "localhost".to_string()),
+        webauthn_rp_name: env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "Server".to_string()),
+        webauthn_origin: env::var("WEBAUTHN_ORIGIN")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string()),
+        // Time to answer a passkey prompt
+        webauthn_timeout_secs: env::var("WEBAUTHN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300),
     }
 }
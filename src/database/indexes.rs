@@ -0,0 +1,28 @@
+use mongodb::{error::Error, Database};
+
+use crate::repositories::{
+    access_token_repository, bookmark_repository, email_repository, follow_repository,
+    login_attempt_repository, login_throttle_repository, passkey_repository,
+    password_reset_repository, post_repository, repost_repository, revision_repository,
+    tag_repository, tag_subscription_repository, user_repository, webauthn_challenge_repository,
+};
+
+// Create the indexes the repositories rely on, e.g. for idempotent upserts
+pub async fn init_indexes(db: &Database) -> Result<(), Error> {
+    follow_repository::ensure_indexes(&db.collection("follows")).await?;
+    repost_repository::ensure_indexes(&db.collection("reposts")).await?;
+    bookmark_repository::ensure_indexes(&db.collection("bookmarks")).await?;
+    post_repository::ensure_indexes(&db.collection("posts")).await?;
+    revision_repository::ensure_indexes(&db.collection("post_revisions")).await?;
+    tag_repository::ensure_indexes(&db.collection("tags")).await?;
+    tag_subscription_repository::ensure_indexes(&db.collection("tag_subscriptions")).await?;
+    user_repository::ensure_indexes(&db.collection("users")).await?;
+    email_repository::ensure_indexes(&db.collection("email_logs")).await?;
+    password_reset_repository::ensure_indexes(&db.collection("password_resets")).await?;
+    login_attempt_repository::ensure_indexes(&db.collection("login_attempts")).await?;
+    login_throttle_repository::ensure_indexes(&db.collection("login_throttles")).await?;
+    passkey_repository::ensure_indexes(&db.collection("passkeys")).await?;
+    access_token_repository::ensure_indexes(&db.collection("access_tokens")).await?;
+    webauthn_challenge_repository::ensure_indexes(&db.collection("webauthn_challenges")).await?;
+    Ok(())
+}
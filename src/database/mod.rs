@@ -1,3 +1,5 @@
 pub mod mongodb;
+pub mod indexes;
 
-pub use mongodb::get_database;
\ No newline at end of file
+pub use mongodb::get_database;
+pub use indexes::init_indexes;
\ No newline at end of file
@@ -0,0 +1,18 @@
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::get_config;
+
+pub mod purge_trash;
+pub mod scheduled_posts;
+pub mod trending;
+
+// Start the background jobs. Every server instance runs them, so each job has
+// to be safe to run concurrently.
+pub fn spawn_jobs(db: Database) {
+    let interval = Duration::from_secs(get_config().scheduler_interval_secs.max(1));
+    tokio::spawn(scheduled_posts::run(db.clone(), interval));
+    tokio::spawn(purge_trash::run(db.clone(), get_config().trash_retention_days));
+    let interval = Duration::from_secs(get_config().trending_interval_secs.max(1));
+    tokio::spawn(trending::run(db, interval));
+}
@@ -0,0 +1,45 @@
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::item::Item;
+use crate::post::Post;
+use crate::revision::PostRevision;
+use crate::tag::Tag;
+use crate::tag_subscription::TagSubscription;
+use crate::utils::helps::days_ago_rfc3339;
+use crate::{item_service, post_service, tag_service};
+
+// The retention window is counted in days, checking hourly is plenty
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Permanently remove trashed posts, items and tags once the retention window
+// has passed. Deleting is idempotent, so instances running it concurrently is fine.
+pub async fn run(db: Database, retention_days: i64) {
+    let posts = db.collection::<Post>("posts");
+    let revisions = db.collection::<PostRevision>("post_revisions");
+    let items = db.collection::<Item>("items");
+    let tags = db.collection::<Tag>("tags");
+    let subscriptions = db.collection::<TagSubscription>("tag_subscriptions");
+    let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let cutoff = days_ago_rfc3339(retention_days);
+        match post_service::purge_deleted_posts_service(&posts, &revisions, &cutoff).await {
+            Ok(0) => {}
+            Ok(count) => println!("Purged {} deleted posts", count),
+            Err(err) => println!("Failed to purge deleted posts: {}", err),
+        }
+        match item_service::purge_deleted_items_service(&items, &cutoff).await {
+            Ok(result) if result.deleted_count > 0 => {
+                println!("Purged {} deleted items", result.deleted_count)
+            }
+            Ok(_) => {}
+            Err(err) => println!("Failed to purge deleted items: {}", err),
+        }
+        match tag_service::purge_deleted_tags_service(&tags, &posts, &subscriptions, &cutoff).await {
+            Ok(0) => {}
+            Ok(count) => println!("Purged {} deleted tags", count),
+            Err(err) => println!("Failed to purge deleted tags: {}", err),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::follow::Follow;
+use crate::notification::Notification;
+use crate::post::Post;
+use crate::tag::Tag;
+use crate::user::User;
+use crate::post_service;
+
+// Publish scheduled posts once their `publish_at` is reached. Posts are claimed
+// with a conditional update, so concurrent instances never publish one twice.
+pub async fn run(db: Database, interval: Duration) {
+    let posts = db.collection::<Post>("posts");
+    let tags = db.collection::<Tag>("tags");
+    let users = db.collection::<User>("users");
+    let follows = db.collection::<Follow>("follows");
+    let notifications = db.collection::<Notification>("notifications");
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let published = post_service::publish_due_posts_service(
+            &posts,
+            &tags,
+            &users,
+            &follows,
+            &notifications,
+        )
+        .await;
+        match published {
+            Ok(0) => {}
+            Ok(count) => println!("Published {} scheduled posts", count),
+            Err(err) => println!("Failed to publish scheduled posts: {}", err),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::post::Post;
+use crate::trending::TrendingSnapshot;
+use crate::trending_service;
+
+// Recompute trending tags and posts. Requests read the cached snapshots, so the
+// aggregations only run here; concurrent instances just overwrite each other.
+pub async fn run(db: Database, interval: Duration) {
+    let snapshots = db.collection::<TrendingSnapshot>("trending");
+    let posts = db.collection::<Post>("posts");
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = trending_service::refresh_trending_service(&snapshots, &posts).await {
+            println!("Failed to refresh trending: {}", err);
+        }
+    }
+}
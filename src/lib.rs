@@ -7,6 +7,9 @@ pub mod routes;
 pub mod utils;
 pub mod database;
 pub mod config;
+pub mod search_engine;
+pub mod jobs;
+pub mod mailer;
 
 pub use middlewares::*;
 pub use models::*;
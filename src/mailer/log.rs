@@ -0,0 +1,20 @@
+use futures::future::BoxFuture;
+
+use super::{Email, Mailer};
+use crate::get_config;
+
+// Logs emails instead of sending them. Bodies hold live links with tokens, so
+// they're only printed when MAIL_LOG_BODIES is set for development.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            println!("Email ({}) to {}: {}", email.template, email.to, email.subject);
+            if get_config().mail_log_bodies {
+                println!("{}", email.body);
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,82 @@
+pub mod log;
+pub mod smtp;
+
+use futures::future::BoxFuture;
+use std::sync::OnceLock;
+
+use crate::get_config;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    // Which kind of email it is, e.g. "verification", for logs
+    pub template: &'static str,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Delivers account emails. The backend is picked by MAIL_BACKEND; tests and
+// development can swap in anything implementing this.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<(), String>>;
+}
+
+static MAILER: OnceLock<Box<dyn Mailer>> = OnceLock::new();
+
+// Use `mailer` instead of the configured backend. Only works before the first
+// email is sent.
+pub fn set_mailer(mailer: Box<dyn Mailer>) -> Result<(), String> {
+    MAILER.set(mailer).map_err(|_| "A mailer is already in use".to_string())
+}
+
+pub fn mailer() -> &'static dyn Mailer {
+    MAILER
+        .get_or_init(|| match get_config().mail_backend.as_str() {
+            "smtp" => Box::new(smtp::SmtpMailer::from_config().expect("Invalid SMTP configuration")),
+            _ => Box::new(log::LogMailer),
+        })
+        .as_ref()
+}
+
+// Sending failures must not fail the request that triggered the email; the
+// user can ask for it again
+pub async fn send(email: Email) {
+    let to = email.to.clone();
+    if let Err(err) = mailer().send(email).await {
+        println!("Failed to send email to {}: {}", to, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingMailer(Arc<Mutex<Vec<Email>>>);
+
+    impl Mailer for RecordingMailer {
+        fn send(&self, email: Email) -> BoxFuture<'_, Result<(), String>> {
+            self.0.lock().unwrap().push(email);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_through_the_injected_mailer() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        set_mailer(Box::new(RecordingMailer(sent.clone()))).unwrap();
+        assert!(set_mailer(Box::new(log::LogMailer)).is_err());
+
+        send(Email {
+            template: "password_reset",
+            to: "user@example.com".to_string(),
+            subject: "Reset your password".to_string(),
+            body: String::new(),
+        })
+        .await;
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "user@example.com");
+    }
+}
\ No newline at end of file
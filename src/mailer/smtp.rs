@@ -0,0 +1,139 @@
+use futures::future::BoxFuture;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::{Email, Mailer};
+use crate::get_config;
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn from_config() -> Result<Self, String> {
+        let config = get_config();
+        let builder = match config.smtp_tls.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host),
+            // Plain connection, e.g. to a local mail catcher
+            _ => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)),
+        };
+        let mut builder = builder.map_err(|err| err.to_string())?.port(config.smtp_port);
+        if let (Some(username), Some(password)) = (config.smtp_username, config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from: config.mail_from.parse().map_err(|err| format!("Invalid MAIL_FROM: {}", err))?,
+        })
+    }
+
+    // Unencrypted and unauthenticated, for a mail catcher on the same machine
+    pub fn plain(host: &str, port: u16, from: &str) -> Result<Self, String> {
+        Ok(SmtpMailer {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+                .port(port)
+                .build(),
+            from: from.parse().map_err(|err| format!("Invalid sender: {}", err))?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            let to: Mailbox = email.to.parse().map_err(|err| format!("Invalid recipient: {}", err))?;
+            let message = Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject(email.subject)
+                .header(ContentType::TEXT_PLAIN)
+                .body(email.body)
+                .map_err(|err| err.to_string())?;
+            self.transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // Accepts one SMTP session and returns everything sent after DATA
+    async fn catch_one(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 Queued\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let command = line.to_ascii_uppercase();
+            let reply: &[u8] = if command.starts_with("DATA") {
+                in_data = true;
+                b"354 End data with <CR><LF>.<CR><LF>\r\n"
+            } else if command.starts_with("QUIT") {
+                write.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 OK\r\n"
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn delivers_over_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let caught = tokio::spawn(catch_one(listener));
+
+        let mailer = SmtpMailer::plain("127.0.0.1", port, "Crate <noreply@example.com>").unwrap();
+        mailer
+            .send(Email {
+                template: "verification",
+                to: "user@example.com".to_string(),
+                subject: "Verify your email".to_string(),
+                body: "Open the link to verify".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let message = caught.await.unwrap();
+        assert!(message.contains("From: Crate <noreply@example.com>"));
+        assert!(message.contains("To: user@example.com"));
+        assert!(message.contains("Subject: Verify your email"));
+        assert!(message.contains("Open the link to verify"));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_recipients() {
+        let mailer = SmtpMailer::plain("127.0.0.1", 1, "noreply@example.com").unwrap();
+        let email = Email {
+            template: "verification",
+            to: "not an address".to_string(),
+            subject: String::new(),
+            body: String::new(),
+        };
+        assert!(mailer.send(email).await.unwrap_err().starts_with("Invalid recipient"));
+    }
+}
@@ -6,10 +6,36 @@ use session::session_middleware;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let db = get_database().await;
+    tag_service::normalize_tags_service(
+        &db.collection("tags"),
+        &db.collection("posts"),
+        &db.collection("tag_subscriptions"),
+    )
+    .await
+    .expect("Failed to normalize tags");
+    tag_service::resume_tag_merges_service(
+        &db.collection("tags"),
+        &db.collection("posts"),
+        &db.collection("tag_subscriptions"),
+    )
+    .await
+    .expect("Failed to finish tag merges");
+    database::init_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+    user_service::backfill_username_keys_service(&db.collection("users"))
+        .await
+        .expect("Failed to backfill username keys");
+    search_service::init_search_service(&db)
+        .await
+        .expect("Failed to initialize search");
+    jobs::spawn_jobs(db.clone());
+
     // Start Actix Web server
     HttpServer::new(move || {
         let cors = Cors::permissive()
-            .allowed_methods(vec!["GET", "POST", "DELETE", "UPDATE"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "UPDATE"])
             .allowed_headers(vec![
                 header::CONTENT_TYPE,
                 header::AUTHORIZATION,
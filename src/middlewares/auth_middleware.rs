@@ -3,16 +3,199 @@ use actix_session::SessionExt as _;
 use actix_web::{
     body::{BoxBody, MessageBody},
     dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
     Error, HttpMessage, HttpResponse,
 };
 use futures::future::{ok, LocalBoxFuture, Ready};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use mongodb::Collection;
+use std::rc::Rc;
 use std::task::{Context, Poll};
 
-use crate::{get_config, jwt::Claims};
+use crate::access_token::{scope_granted, AccessToken};
+use crate::{
+    access_token_service, get_config, get_database, jwt::Claims, user::User, user_service,
+};
+
+// What accounts with an unverified email may still change when
+// REQUIRE_VERIFIED_EMAIL is set
+const UNVERIFIED_ALLOWED_PATHS: &[&str] = &[
+    "/users/me",
+    "/users/me/privacy",
+    "/users/me/avatar",
+    "/users/me/password",
+    "/users/me/2fa",
+    "/users/me/2fa/setup",
+    "/users/me/2fa/confirm",
+    "/users/me/email/verification",
+];
+
+// Passkeys are managed by id, so these are matched by prefix
+const UNVERIFIED_ALLOWED_PREFIXES: &[&str] = &["/users/me/passkeys/"];
+
+// Account security stays with the signed in user; personal access tokens are
+// refused here whatever their scopes
+const TOKEN_REFUSED_PREFIXES: &[&str] = &[
+    "/users/me/tokens",
+    "/users/me/password",
+    "/users/me/2fa",
+    "/users/me/passkeys",
+    "/users/me/email",
+];
+
+// Unverified accounts are limited to reading, apart from managing their account
+fn needs_verified_email(req: &ServiceRequest, user: &User) -> bool {
+    !user.is_verified
+        && get_config().require_verified_email
+        && !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && !UNVERIFIED_ALLOWED_PATHS.contains(&req.path())
+        && !UNVERIFIED_ALLOWED_PREFIXES
+            .iter()
+            .any(|prefix| req.path().starts_with(prefix))
+}
+
+// The scope a personal access token needs for this request: reading or writing
+// the resource the routes serve, or None if tokens aren't accepted
+fn required_scope(req: &ServiceRequest, resource: Option<&str>) -> Option<String> {
+    let resource = resource?;
+    if TOKEN_REFUSED_PREFIXES
+        .iter()
+        .any(|prefix| req.path().starts_with(prefix))
+    {
+        return None;
+    }
+    let access = if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        "read"
+    } else {
+        "write"
+    };
+    Some(format!("{}:{}", resource, access))
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    value
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
 
-// Define the struct for the Authentication middleware
-pub struct Authentication;
+// Claims and account of the session's user. The inner error is the response
+// refusing the request.
+async fn session_user(req: &ServiceRequest) -> Result<Result<(Claims, User), HttpResponse>, Error> {
+    let token = match req.get_session().get::<String>("token").unwrap() {
+        Some(token) => token,
+        None => return Ok(Err(HttpResponse::Unauthorized().body("Unauthorized"))),
+    };
+    let secret = get_config().jwt_secret;
+    let validation = Validation::new(Algorithm::HS256);
+    let claims = match decode::<Claims>(
+        &token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &validation,
+    ) {
+        Ok(token_data) => token_data.claims,
+        Err(err) => {
+            return Ok(Err(
+                HttpResponse::Unauthorized().body(format!("Invalid {:?}", err))
+            ))
+        }
+    };
+
+    let db = get_database().await;
+    let users: Collection<User> = db.collection("users");
+    let user = user_service::get_user_by_id_service(&users, &claims.sub)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    // Tokens issued before a password change are revoked
+    match user {
+        Some(user) if user.token_version == claims.ver => Ok(Ok((claims, user))),
+        _ => Ok(Err(HttpResponse::Unauthorized().body("Session expired"))),
+    }
+}
+
+// Claims and account of a personal access token's owner, provided the token
+// has the scope the request needs
+async fn token_user(
+    req: &ServiceRequest,
+    token: &str,
+    resource: Option<&str>,
+) -> Result<Result<(Claims, User), HttpResponse>, Error> {
+    let db = get_database().await;
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    let users: Collection<User> = db.collection("users");
+
+    let access_token = access_token_service::authenticate_token_service(&tokens, token)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let access_token = match access_token {
+        Some(access_token) => access_token,
+        None => {
+            return Ok(Err(
+                HttpResponse::Unauthorized().body("Invalid or expired access token")
+            ))
+        }
+    };
+    let scope = match required_scope(req, resource) {
+        Some(scope) => scope,
+        None => {
+            return Ok(Err(
+                HttpResponse::Forbidden().body("Personal access tokens can't be used here")
+            ))
+        }
+    };
+    if !scope_granted(&access_token.scopes, &scope) {
+        return Ok(Err(
+            HttpResponse::Forbidden().body(format!("Token lacks the {} scope", scope))
+        ));
+    }
+
+    let user = user_service::get_user_by_id_service(&users, &access_token.user_id.to_hex())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    // Tokens made before a password change are revoked along with sessions
+    let user = match user {
+        Some(user) if user.token_version == access_token.token_version => user,
+        _ => {
+            return Ok(Err(
+                HttpResponse::Unauthorized().body("Invalid or expired access token")
+            ))
+        }
+    };
+    let exp = access_token
+        .expires_at
+        .as_deref()
+        .and_then(|expires_at| chrono::DateTime::parse_from_rfc3339(expires_at).ok())
+        .map(|expires_at| expires_at.timestamp() as usize);
+    let claims = Claims {
+        sub: user.id.to_hex(),
+        role: user.role.as_str().to_string(),
+        exp,
+        ver: user.token_version,
+        scopes: Some(access_token.scopes),
+    };
+    Ok(Ok((claims, user)))
+}
+
+// Define the struct for the Authentication middleware. Signed in sessions are
+// always accepted; personal access tokens only where routes name the resource
+// they serve, with its read scope for reads and its write scope otherwise.
+pub struct Authentication {
+    resource: Option<&'static str>,
+}
+
+impl Authentication {
+    // Routes only signed in users may use
+    pub fn session_only() -> Self {
+        Authentication { resource: None }
+    }
+
+    // Routes personal access tokens with a scope for the resource may use too
+    pub fn scoped(resource: &'static str) -> Self {
+        Authentication {
+            resource: Some(resource),
+        }
+    }
+}
 
 // Implement the Transform trait for the Authentication middleware
 impl<S, B> Transform<S, ServiceRequest> for Authentication
@@ -28,13 +211,17 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(AuthenticationMiddleware { service })
+        ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+            resource: self.resource,
+        })
     }
 }
 
 // Define the struct for the inner middleware
 pub struct AuthenticationMiddleware<S> {
-    service: S,
+    service: Rc<S>,
+    resource: Option<&'static str>,
 }
 
 // Implement the Service trait for the inner middleware
@@ -53,39 +240,85 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let secret = get_config().jwt_secret;
-        let session = req.get_session();
-
-        let token = session.get::<String>("token").unwrap();
-
-        if token.is_some() {
-            let validation = Validation::new(Algorithm::HS256);
-            match decode::<Claims>(
-                &token.unwrap().clone(),
-                &DecodingKey::from_secret(secret.as_ref()),
-                &validation,
-            ) {
-                Ok(token_data) => {
-                    // Insert claims into the request extensions for access in handlers
-                    req.extensions_mut().insert(token_data.claims);
-
-                    // Call the next service in the middleware chain
-                    let fut = self.service.call(req);
-                    return Box::pin(async move { fut.await.map(|res| res.map_into_boxed_body()) });
-                }
-                Err(err) => {
-                    let response = HttpResponse::Unauthorized()
-                        .body(format!("Invalid {:?}", err))
-                        .map_into_boxed_body();
-                    return Box::pin(async move { Ok(req.into_response(response)) });
-                }
+        let service = Rc::clone(&self.service);
+        let resource = self.resource;
+        Box::pin(async move {
+            // A bearer token takes precedence over the session cookie
+            let authenticated = match bearer_token(&req) {
+                Some(token) => token_user(&req, &token, resource).await?,
+                None => session_user(&req).await?,
+            };
+            let (claims, user) = match authenticated {
+                Ok(authenticated) => authenticated,
+                Err(response) => return Ok(req.into_response(response.map_into_boxed_body())),
+            };
+            if needs_verified_email(&req, &user) {
+                let response = HttpResponse::Forbidden()
+                    .body("Verify your email address first")
+                    .map_into_boxed_body();
+                return Ok(req.into_response(response));
             }
+
+            // Insert claims into the request extensions for access in handlers
+            req.extensions_mut().insert(claims);
+
+            // Call the next service in the middleware chain
+            service.call(req).await.map(|res| res.map_into_boxed_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(method: Method, path: &str) -> ServiceRequest {
+        TestRequest::default()
+            .method(method)
+            .uri(path)
+            .to_srv_request()
+    }
+
+    #[test]
+    fn reads_and_writes_need_their_scope() {
+        let get = request(Method::GET, "/posts/123");
+        assert_eq!(required_scope(&get, Some("posts")).as_deref(), Some("posts:read"));
+        let head = request(Method::HEAD, "/posts");
+        assert_eq!(required_scope(&head, Some("posts")).as_deref(), Some("posts:read"));
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            let write = request(method, "/items/123");
+            assert_eq!(required_scope(&write, Some("items")).as_deref(), Some("items:write"));
         }
+    }
+
+    #[test]
+    fn refuses_tokens_for_session_only_routes() {
+        let admin = request(Method::GET, "/admin/users");
+        assert_eq!(required_scope(&admin, None), None);
+        for path in [
+            "/users/me/tokens",
+            "/users/me/password",
+            "/users/me/2fa/setup",
+            "/users/me/passkeys/123",
+            "/users/me/email/verification",
+        ] {
+            assert_eq!(required_scope(&request(Method::GET, path), Some("users")), None);
+        }
+        let profile = request(Method::PATCH, "/users/me");
+        assert_eq!(required_scope(&profile, Some("users")).as_deref(), Some("users:write"));
+    }
 
-        // If token is invalid or missing, return Unauthorized response
-        let response = HttpResponse::Unauthorized()
-            .body("Unauthorized")
-            .map_into_boxed_body();
-        Box::pin(async move { Ok(req.into_response(response)) })
+    #[test]
+    fn reads_bearer_tokens() {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer  pat_abc "))
+            .to_srv_request();
+        assert_eq!(bearer_token(&req).as_deref(), Some("pat_abc"));
+        let basic = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Basic dXNlcjpwYXNz"))
+            .to_srv_request();
+        assert_eq!(bearer_token(&basic), None);
+        assert_eq!(bearer_token(&TestRequest::default().to_srv_request()), None);
     }
 }
@@ -1,12 +1,41 @@
 use actix_web::{HttpMessage as _, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
 
+use crate::follow::Follow;
 use crate::jwt::Claims;
+use crate::post::Audience;
+use crate::user::{Role, User};
+use crate::{get_database, post_service};
 
 pub async fn handler(req: HttpRequest) -> Result<Claims, HttpResponse> {
     // Extract claims from the request extensions
     if let Some(claims) = req.extensions().get::<Claims>() {
-        return Ok(claims.to_owned());
+        Ok(claims.to_owned())
     } else {
-        return Err(HttpResponse::Unauthorized().body("Invalid credentials"));
+        Err(HttpResponse::Unauthorized().body("Invalid credentials"))
     }
 }
+
+// Claims of the authenticated user, provided they're an admin
+pub async fn require_admin(req: HttpRequest) -> Result<Claims, HttpResponse> {
+    let claims = handler(req).await?;
+    if claims.role != Role::Admin.as_str() {
+        return Err(HttpResponse::Forbidden().body("Admin access required"));
+    }
+    Ok(claims)
+}
+
+// Who the authenticated user is allowed to read posts from
+pub async fn viewer_audience(req: HttpRequest) -> Result<Audience, HttpResponse> {
+    let claims = handler(req).await?;
+    let viewer_id = ObjectId::parse_str(claims.sub)
+        .map_err(|_| HttpResponse::InternalServerError().body("Invalid user_id"))?;
+
+    let db = get_database().await;
+    let follows: Collection<Follow> = db.collection("follows");
+    let users: Collection<User> = db.collection("users");
+    post_service::get_audience_service(&follows, &users, viewer_id)
+        .await
+        .map_err(|err| HttpResponse::InternalServerError().body(err.to_string()))
+}
@@ -0,0 +1,127 @@
+use mongodb::bson::{oid::ObjectId, serde_helpers::serialize_object_id_as_hex_string};
+use serde::{Deserialize, Serialize};
+
+pub const ACCESS_TOKEN_PREFIX: &str = "pat_";
+pub const ACCESS_TOKEN_MAX_PER_USER: usize = 50;
+pub const ACCESS_TOKEN_NAME_MAX_LENGTH: usize = 64;
+pub const ACCESS_TOKEN_MAX_DAYS: i64 = 3650;
+
+// What a token can be granted. A write scope also grants reading the same
+// resource.
+pub const ACCESS_TOKEN_SCOPES: &[&str] = &[
+    "posts:read",
+    "posts:write",
+    "items:read",
+    "items:write",
+    "tags:read",
+    "tags:write",
+    "users:read",
+    "users:write",
+    "search:read",
+];
+
+pub fn scope_granted(scopes: &[String], required: &str) -> bool {
+    scopes.iter().any(|scope| {
+        scope == required
+            || required
+                .strip_suffix(":read")
+                .is_some_and(|resource| scope.strip_suffix(":write") == Some(resource))
+    })
+}
+
+// A long-lived token for scripts, sent as "Authorization: Bearer pat_..."
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccessToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub name: String,
+    // Only the hash is kept; the token itself is shown once
+    pub token_hash: String,
+    // Start of the token, to tell tokens apart
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    // The owner's token version at creation; a password change voids the token
+    #[serde(default)]
+    pub token_version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+impl AccessToken {
+    pub fn to_access_token(token: AccessToken) -> AccessTokenResponse {
+        AccessTokenResponse {
+            id: token.id.unwrap(),
+            name: token.name,
+            prefix: token.prefix,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccessTokenResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+// Returned once, when the token is created
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreatedAccessTokenResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub access_token: AccessTokenResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    // Leave out for a token that doesn't expire
+    pub expires_in_days: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|scope| scope.to_string()).collect()
+    }
+
+    #[test]
+    fn grants_exact_scopes() {
+        let granted = scopes(&["posts:read", "search:read"]);
+        assert!(scope_granted(&granted, "posts:read"));
+        assert!(scope_granted(&granted, "search:read"));
+        assert!(!scope_granted(&granted, "posts:write"));
+        assert!(!scope_granted(&granted, "items:read"));
+    }
+
+    #[test]
+    fn write_scopes_also_grant_reading() {
+        let granted = scopes(&["items:write"]);
+        assert!(scope_granted(&granted, "items:read"));
+        assert!(scope_granted(&granted, "items:write"));
+        assert!(!scope_granted(&granted, "tags:read"));
+        assert!(!scope_granted(&scopes(&["items:read"]), "items:write"));
+    }
+
+    #[test]
+    fn grants_nothing_without_scopes() {
+        assert!(!scope_granted(&[], "posts:read"));
+        assert!(!scope_granted(&scopes(&["posts"]), "posts:read"));
+    }
+}
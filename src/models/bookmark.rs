@@ -0,0 +1,97 @@
+use mongodb::bson::{
+    doc,
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+        serialize_object_id_as_hex_string,
+    },
+    DateTime, Document,
+};
+use serde::{Deserialize, Serialize};
+
+use super::post::PostResponse;
+use crate::utils::helps::serialize_option_object_id_as_hex_string;
+
+// Private to the user who saved it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bookmark {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub post_id: ObjectId,
+    pub folder_id: Option<ObjectId>,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookmarkFolder {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub name: String,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
+
+impl BookmarkFolder {
+    pub fn to_folder(folder: BookmarkFolder) -> BookmarkFolderResponse {
+        BookmarkFolderResponse {
+            id: folder.id.unwrap(),
+            name: folder.name,
+            created_at: folder.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BookmarkRequest {
+    // Bookmarks without a folder are listed as unfiled
+    pub folder_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookmarkFolderRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookmarkFolderResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    #[serde(rename(serialize = "id"))]
+    #[serde(rename(deserialize = "_id"))]
+    pub id: ObjectId,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookmarkResponse {
+    #[serde(serialize_with = "serialize_option_object_id_as_hex_string")]
+    pub folder_id: Option<ObjectId>,
+    pub post: PostResponse,
+    pub created_at: String,
+}
+
+// Query string filters for GET /users/me/bookmarks: ?folder=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BookmarkFilter {
+    pub folder: Option<String>,
+}
+
+impl BookmarkFilter {
+    pub fn to_document(&self, user_id: ObjectId) -> Result<Document, String> {
+        let mut filter = doc! { "user_id": user_id };
+        if let Some(folder) = &self.folder {
+            let folder_id = ObjectId::parse_str(folder).map_err(|_| "Invalid folder ID")?;
+            filter.insert("folder_id", folder_id);
+        }
+        Ok(filter)
+    }
+}
@@ -0,0 +1,39 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+// Kinds of account email, each rate limited on its own
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailPurpose {
+    Verification,
+    PasswordReset,
+    Unlock,
+}
+
+impl EmailPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailPurpose::Verification => "verification",
+            EmailPurpose::PasswordReset => "password_reset",
+            EmailPurpose::Unlock => "unlock",
+        }
+    }
+}
+
+// When emails of a purpose were last sent to a user, for rate limiting
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailLog {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub purpose: EmailPurpose,
+    pub last_sent_at: String,
+    // The most recent sends, at most the daily limit of them
+    #[serde(default)]
+    pub sent_at: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
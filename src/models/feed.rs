@@ -0,0 +1,24 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use super::{post::PostResponse, user::PublicUserResponse};
+
+// An entry of a feed, either a post or a repost of it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeedItem {
+    pub post: PostResponse,
+    // Set when the post appears because someone the reader follows reposted it
+    pub reposted_by: Option<PublicUserResponse>,
+    pub created_at: String,
+}
+
+// What a feed is made of: posts and reposts of `author_ids` plus posts carrying
+// `tag_ids`. Posts carrying `muted_tag_ids` are left out unless the viewer
+// wrote them.
+#[derive(Debug, Clone, Default)]
+pub struct FeedSources {
+    pub viewer_id: ObjectId,
+    pub author_ids: Vec<ObjectId>,
+    pub tag_ids: Vec<ObjectId>,
+    pub muted_tag_ids: Vec<ObjectId>,
+}
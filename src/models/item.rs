@@ -1,10 +1,13 @@
 use mongodb::bson::{
-    oid::ObjectId, serde_helpers::{
+    doc, oid::ObjectId, serde_helpers::{
         deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
-    }, DateTime
+    }, DateTime, Document
 };
 use serde::{Deserialize, Serialize};
 
+use crate::jwt::Claims;
+use crate::user::Role;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Item {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -14,6 +17,9 @@ pub struct Item {
     pub description: String,
     pub price: f64,
     pub stock: i32,
+    // Set while the item is in its owner's trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
     #[serde(
         deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
         serialize_with = "serialize_bson_datetime_as_rfc3339_string"
@@ -26,6 +32,13 @@ pub struct Item {
     pub updated_at: DateTime,
 }
 
+impl Item {
+    // Changing or trashing an item is left to its owner and admins
+    pub fn is_managed_by(&self, claims: &Claims) -> bool {
+        self.user_id == claims.sub || claims.role == Role::Admin.as_str()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ItemRequest {
     pub name: String,
@@ -33,3 +46,81 @@ pub struct ItemRequest {
     pub price: f64,
     pub stock: i32,
 }
+
+pub const ITEM_SORT_FIELDS: &[&str] = &["created_at", "updated_at", "name", "price", "stock"];
+
+// Query string filters for GET /items: ?owner=&min_price=&max_price=&min_stock=&max_stock=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ItemFilter {
+    pub owner: Option<String>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub min_stock: Option<i32>,
+    pub max_stock: Option<i32>,
+}
+
+impl ItemFilter {
+    pub fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(owner) = &self.owner {
+            filter.insert("user_id", owner);
+        }
+
+        let mut price = doc! {};
+        if let Some(min) = self.min_price {
+            price.insert("$gte", min);
+        }
+        if let Some(max) = self.max_price {
+            price.insert("$lte", max);
+        }
+        if !price.is_empty() {
+            filter.insert("price", price);
+        }
+
+        let mut stock = doc! {};
+        if let Some(min) = self.min_stock {
+            stock.insert("$gte", min);
+        }
+        if let Some(max) = self.max_stock {
+            stock.insert("$lte", max);
+        }
+        if !stock.is_empty() {
+            filter.insert("stock", stock);
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(sub: &str, role: Role) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            role: role.as_str().to_string(),
+            exp: None,
+            ver: 0,
+            scopes: None,
+        }
+    }
+
+    #[test]
+    fn only_owners_and_admins_manage_items() {
+        let owner = ObjectId::new().to_hex();
+        let item = Item {
+            id: None,
+            user_id: owner.clone(),
+            name: "Lamp".to_string(),
+            description: String::new(),
+            price: 10.0,
+            stock: 1,
+            deleted_at: None,
+            created_at: DateTime::now(),
+            updated_at: DateTime::now(),
+        };
+        assert!(item.is_managed_by(&claims(&owner, Role::User)));
+        assert!(item.is_managed_by(&claims(&ObjectId::new().to_hex(), Role::Admin)));
+        assert!(!item.is_managed_by(&claims(&ObjectId::new().to_hex(), Role::User)));
+    }
+}
@@ -0,0 +1,159 @@
+use mongodb::bson::{oid::ObjectId, serde_helpers::serialize_object_id_as_hex_string};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::helps::{now_rfc3339, serialize_option_object_id_as_hex_string};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginResult {
+    Success,
+    InvalidCredentials,
+    Throttled,
+    // Right password, second factor still to come
+    TwoFactorRequired,
+    InvalidTwoFactorCode,
+    InvalidPasskey,
+}
+
+// Audit record of a login attempt
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginAttempt {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // As typed, whether or not an account has it
+    pub username: String,
+    pub user_id: Option<ObjectId>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub result: LoginResult,
+    pub created_at: String,
+}
+
+impl LoginAttempt {
+    pub fn new(
+        username: &str,
+        user_id: Option<ObjectId>,
+        client: &LoginClient,
+        result: LoginResult,
+    ) -> LoginAttempt {
+        LoginAttempt {
+            id: None,
+            username: username.to_string(),
+            user_id,
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+            result,
+            created_at: now_rfc3339(),
+        }
+    }
+
+    pub fn to_attempt(attempt: LoginAttempt) -> LoginAttemptResponse {
+        LoginAttemptResponse {
+            id: attempt.id.unwrap(),
+            username: attempt.username,
+            user_id: attempt.user_id,
+            ip: attempt.ip,
+            user_agent: attempt.user_agent,
+            result: attempt.result,
+            created_at: attempt.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginAttemptResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub username: String,
+    #[serde(serialize_with = "serialize_option_object_id_as_hex_string")]
+    pub user_id: Option<ObjectId>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub result: LoginResult,
+    pub created_at: String,
+}
+
+// Failed logins counted against an account ("account:<username key>") or an IP
+// address ("ip:<address>")
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginThrottle {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: String,
+    // No attempts are checked before this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_until: Option<String>,
+    // Set once an account reaches the lockout threshold; lifted early by the
+    // unlock link sent to the account's email
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<String>,
+}
+
+impl LoginThrottle {
+    // Until when attempts are refused, if they are now
+    pub fn refused_until(&self, now: &str) -> Option<String> {
+        [&self.blocked_until, &self.locked_until]
+            .into_iter()
+            .flatten()
+            .filter(|until| until.as_str() > now)
+            .max()
+            .cloned()
+    }
+}
+
+// Who is logging in, for throttling and the audit log
+#[derive(Debug, Clone)]
+pub struct LoginClient {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+// Why a login was refused. Wrong usernames and wrong passwords look the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginRejection {
+    InvalidCredentials,
+    Throttled { retry_after_secs: i64 },
+    // The two-factor challenge is bad, expired or for an old password
+    InvalidChallenge,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlockQuery {
+    pub token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(blocked_until: Option<&str>, locked_until: Option<&str>) -> LoginThrottle {
+        LoginThrottle {
+            id: None,
+            key: "account:alice".to_string(),
+            failures: 5,
+            last_failure_at: "2024-05-01T11:59:00Z".to_string(),
+            blocked_until: blocked_until.map(str::to_string),
+            locked_until: locked_until.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn refuses_until_the_later_of_block_and_lock() {
+        let now = "2024-05-01T12:00:00Z";
+        let both = throttle(Some("2024-05-01T12:01:00Z"), Some("2024-05-01T12:30:00Z"));
+        assert_eq!(both.refused_until(now).as_deref(), Some("2024-05-01T12:30:00Z"));
+
+        let blocked = throttle(Some("2024-05-01T12:01:00Z"), None);
+        assert_eq!(blocked.refused_until(now).as_deref(), Some("2024-05-01T12:01:00Z"));
+    }
+
+    #[test]
+    fn lets_attempts_through_once_expired() {
+        let now = "2024-05-01T12:00:00Z";
+        assert_eq!(throttle(None, None).refused_until(now), None);
+        let expired = throttle(Some("2024-05-01T11:59:30Z"), Some(now));
+        assert_eq!(expired.refused_until(now), None);
+    }
+}
@@ -1,4 +1,21 @@
 pub mod user;
 pub mod post;
 pub mod tag;
-pub mod item;
\ No newline at end of file
+pub mod item;
+pub mod pagination;
+pub mod search;
+pub mod follow;
+pub mod repost;
+pub mod bookmark;
+pub mod feed;
+pub mod notification;
+pub mod revision;
+pub mod trash;
+pub mod trending;
+pub mod tag_subscription;
+pub mod email;
+pub mod password;
+pub mod login;
+pub mod two_factor;
+pub mod passkey;
+pub mod access_token;
\ No newline at end of file
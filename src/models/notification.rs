@@ -0,0 +1,63 @@
+use mongodb::bson::{
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+        serialize_object_id_as_hex_string,
+    },
+    DateTime,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::helps::serialize_option_object_id_as_hex_string;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    Mention,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // Recipient
+    pub user_id: ObjectId,
+    // User whose action triggered the notification
+    pub actor_id: ObjectId,
+    pub kind: NotificationKind,
+    pub post_id: Option<ObjectId>,
+    pub read: bool,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
+
+impl Notification {
+    pub fn to_notification(notification: Notification) -> NotificationResponse {
+        NotificationResponse {
+            id: notification.id.unwrap(),
+            actor_id: notification.actor_id,
+            kind: notification.kind,
+            post_id: notification.post_id,
+            read: notification.read,
+            created_at: notification.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    #[serde(rename(serialize = "id"))]
+    #[serde(rename(deserialize = "_id"))]
+    pub id: ObjectId,
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub actor_id: ObjectId,
+    pub kind: NotificationKind,
+    #[serde(serialize_with = "serialize_option_object_id_as_hex_string")]
+    pub post_id: Option<ObjectId>,
+    pub read: bool,
+    pub created_at: String,
+}
@@ -0,0 +1,278 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn direction(&self) -> i32 {
+        match self {
+            SortOrder::Asc => 1,
+            SortOrder::Desc => -1,
+        }
+    }
+
+    fn operator(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "$gt",
+            SortOrder::Desc => "$lt",
+        }
+    }
+}
+
+// Query string accepted by every list endpoint: ?cursor=&limit=&sort=&order=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PaginationQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub order: Option<SortOrder>,
+}
+
+// Position of the last returned document, encoded into the opaque cursor
+#[derive(Debug, Clone)]
+pub struct Cursor {
+    pub value: Bson,
+    pub id: ObjectId,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::new();
+        doc! { "v": self.value.clone(), "id": self.id }
+            .to_writer(&mut bytes)
+            .expect("cursor document is always serializable");
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Cursor> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let document = Document::from_reader(bytes.as_slice()).ok()?;
+        Some(Cursor {
+            value: document.get("v")?.clone(),
+            id: document.get_object_id("id").ok()?,
+        })
+    }
+}
+
+// Validated pagination parameters used by the repositories
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    pub limit: i64,
+    pub sort: String,
+    pub order: SortOrder,
+    pub after: Option<Cursor>,
+}
+
+impl PaginationQuery {
+    // Validate the query against the fields a collection may be sorted by.
+    // The first allowed field is the default sort.
+    pub fn resolve(&self, sortable: &[&str]) -> Result<Pagination, String> {
+        let sort = match &self.sort {
+            Some(sort) if sortable.contains(&sort.as_str()) => sort.to_owned(),
+            Some(sort) => return Err(format!("Cannot sort by '{}'", sort)),
+            None => sortable[0].to_string(),
+        };
+
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit < 1 {
+            return Err("limit must be greater than 0".to_string());
+        }
+
+        let after = match &self.cursor {
+            Some(cursor) => Some(Cursor::decode(cursor).ok_or("Invalid cursor")?),
+            None => None,
+        };
+
+        Ok(Pagination {
+            limit: limit.min(MAX_LIMIT),
+            sort,
+            order: self.order.unwrap_or_default(),
+            after,
+        })
+    }
+
+    // Offset based variant for results ranked by relevance, which have no stable sort key
+    pub fn resolve_offset(&self) -> Result<(u64, i64), String> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit < 1 {
+            return Err("limit must be greater than 0".to_string());
+        }
+        let offset = match &self.cursor {
+            Some(cursor) => decode_offset(cursor).ok_or("Invalid cursor")?,
+            None => 0,
+        };
+        Ok((offset, limit.min(MAX_LIMIT)))
+    }
+}
+
+pub fn encode_offset(offset: u64) -> String {
+    let mut bytes = Vec::new();
+    doc! { "o": offset as i64 }
+        .to_writer(&mut bytes)
+        .expect("cursor document is always serializable");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn decode_offset(cursor: &str) -> Option<u64> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    let document = Document::from_reader(bytes.as_slice()).ok()?;
+    u64::try_from(document.get_i64("o").ok()?).ok()
+}
+
+impl Pagination {
+    // `$sort` stage body; `_id` breaks ties so the cursor position is unique
+    pub fn sort_doc(&self) -> Document {
+        let direction = self.order.direction();
+        doc! { self.sort.as_str(): direction, "_id": direction }
+    }
+
+    // Filter that only keeps documents after the cursor position
+    pub fn cursor_filter(&self) -> Option<Document> {
+        self.after.as_ref().map(|cursor| {
+            let op = self.order.operator();
+            doc! {
+                "$or": [
+                    { self.sort.as_str(): { op: cursor.value.clone() } },
+                    { self.sort.as_str(): cursor.value.clone(), "_id": { op: cursor.id } },
+                ]
+            }
+        })
+    }
+
+    // Combine resource filters with the cursor filter
+    pub fn match_doc(&self, filter: Document) -> Document {
+        match self.cursor_filter() {
+            Some(cursor) if filter.is_empty() => cursor,
+            Some(cursor) => doc! { "$and": [filter, cursor] },
+            None => filter,
+        }
+    }
+
+    // One extra document is fetched to know whether another page exists
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    // Trim the extra document and build the cursor pointing past the page
+    pub fn page(&self, mut documents: Vec<Document>) -> (Vec<Document>, Option<String>) {
+        if documents.len() as i64 <= self.limit {
+            return (documents, None);
+        }
+        documents.truncate(self.limit as usize);
+        let next_cursor = documents.last().and_then(|last| {
+            Some(
+                Cursor {
+                    value: last.get(&self.sort).cloned().unwrap_or(Bson::Null),
+                    id: last.get_object_id("_id").ok()?,
+                }
+                .encode(),
+            )
+        });
+        (documents, next_cursor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Paginated<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub limit: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(cursor: Option<String>, limit: Option<i64>, sort: Option<&str>) -> PaginationQuery {
+        PaginationQuery {
+            cursor,
+            limit,
+            sort: sort.map(str::to_string),
+            order: None,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let id = ObjectId::new();
+        let cursor = Cursor { value: Bson::String("2024-01-01T00:00:00Z".into()), id }.encode();
+        let decoded = Cursor::decode(&cursor).unwrap();
+        assert_eq!(decoded.value, Bson::String("2024-01-01T00:00:00Z".into()));
+        assert_eq!(decoded.id, id);
+    }
+
+    #[test]
+    fn rejects_garbage_cursors() {
+        assert!(Cursor::decode("not a cursor").is_none());
+        assert!(Cursor::decode(&encode_offset(3)).is_none());
+        let resolved = query(Some("!!".to_string()), None, None).resolve(&["created_at"]);
+        assert_eq!(resolved.unwrap_err(), "Invalid cursor");
+    }
+
+    #[test]
+    fn resolves_defaults_and_limits() {
+        let pagination = query(None, None, None).resolve(&["created_at", "likes"]).unwrap();
+        assert_eq!(pagination.sort, "created_at");
+        assert_eq!(pagination.limit, DEFAULT_LIMIT);
+        assert_eq!(pagination.order, SortOrder::Desc);
+
+        let pagination = query(None, Some(1000), Some("likes")).resolve(&["created_at", "likes"]);
+        assert_eq!(pagination.unwrap().limit, MAX_LIMIT);
+        assert!(query(None, Some(0), None).resolve(&["created_at"]).is_err());
+        assert!(query(None, None, Some("password")).resolve(&["created_at"]).is_err());
+    }
+
+    #[test]
+    fn offset_cursor_round_trips() {
+        let (offset, limit) = query(Some(encode_offset(40)), Some(20), None)
+            .resolve_offset()
+            .unwrap();
+        assert_eq!((offset, limit), (40, 20));
+        assert!(query(Some("!!".to_string()), None, None).resolve_offset().is_err());
+    }
+
+    #[test]
+    fn pages_past_the_last_document() {
+        let pagination = query(None, Some(2), None).resolve(&["created_at"]).unwrap();
+        let documents: Vec<Document> = (0..3)
+            .map(|i| doc! { "_id": ObjectId::new(), "created_at": format!("2024-01-0{}", 3 - i) })
+            .collect();
+        let last_id = documents[1].get_object_id("_id").unwrap();
+
+        let (page, next_cursor) = pagination.page(documents.clone());
+        assert_eq!(page.len(), 2);
+        let cursor = Cursor::decode(&next_cursor.unwrap()).unwrap();
+        assert_eq!(cursor.id, last_id);
+        assert_eq!(cursor.value, Bson::String("2024-01-02".into()));
+
+        let (page, next_cursor) = pagination.page(documents[..2].to_vec());
+        assert_eq!(page.len(), 2);
+        assert!(next_cursor.is_none());
+    }
+
+    #[test]
+    fn cursor_filter_continues_after_ties() {
+        let id = ObjectId::new();
+        let pagination = Pagination {
+            limit: 20,
+            sort: "likes".to_string(),
+            order: SortOrder::Desc,
+            after: Some(Cursor { value: Bson::Int32(5), id }),
+        };
+        assert_eq!(
+            pagination.match_doc(doc! {}),
+            doc! { "$or": [{ "likes": { "$lt": 5 } }, { "likes": 5, "_id": { "$lt": id } }] }
+        );
+        assert_eq!(pagination.sort_doc(), doc! { "likes": -1, "_id": -1 });
+    }
+}
@@ -0,0 +1,121 @@
+use mongodb::bson::{oid::ObjectId, serde_helpers::serialize_object_id_as_hex_string};
+use serde::{Deserialize, Serialize};
+
+pub const PASSKEY_MAX_PER_USER: usize = 10;
+pub const PASSKEY_NAME_MAX_LENGTH: usize = 64;
+
+// A WebAuthn credential registered to an account
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Passkey {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    // Base64url credential id, as browsers send it
+    pub credential_id: String,
+    // Base64url SEC1 encoded P-256 public key
+    pub public_key: String,
+    // Authenticators that keep a counter increase it with every use; going
+    // back means the credential was cloned
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+}
+
+impl Passkey {
+    pub fn to_passkey(passkey: Passkey) -> PasskeyResponse {
+        PasskeyResponse {
+            id: passkey.id.unwrap(),
+            name: passkey.name,
+            created_at: passkey.created_at,
+            last_used_at: passkey.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasskeyResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CeremonyKind {
+    Registration,
+    Authentication,
+}
+
+impl CeremonyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CeremonyKind::Registration => "registration",
+            CeremonyKind::Authentication => "authentication",
+        }
+    }
+}
+
+// A challenge handed to the browser, usable once until it expires
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebauthnChallenge {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub challenge: String,
+    pub kind: CeremonyKind,
+    // Who is registering, or who is expected to log in, when known
+    pub user_id: Option<ObjectId>,
+    pub expires_at: String,
+}
+
+// Body of the registration finish, with the credential as the browser's
+// PublicKeyCredential.toJSON() gives it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyRegistrationRequest {
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PasskeyLoginStartRequest {
+    // Leave out to let the browser offer any passkey for the site
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyRenameRequest {
+    pub name: String,
+}
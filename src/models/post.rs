@@ -5,18 +5,20 @@ use tokio::sync::Mutex;
 
 use super::{
     tag::{Tag, TagResponse},
-    user::{User, UserResponse},
+    user::{PublicUserResponse, User},
 };
 use crate::{
     get_database,
     utils::helps::{
         deserialize_string_vec_as_object_id_vec, serialize_object_id_vec_as_string_vec,
+        not_deleted, now_rfc3339, serialize_option_object_id_as_hex_string,
     },
 };
 use mongodb::{
     bson::{
         doc,
         oid::ObjectId,
+        Document,
         serde_helpers::{
             deserialize_bson_datetime_from_rfc3339_string,
             serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
@@ -44,6 +46,52 @@ pub struct Media {
 pub enum PostType {
     Single,
     Multiple,
+    Reply,
+    Quote,
+}
+
+// Who can read a post besides its author
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Followers,
+    // Only the users mentioned in the content
+    Mentioned,
+    Private,
+    // The author's close friends list
+    CloseFriends,
+}
+
+// Drafts and scheduled posts are only visible to their author
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    Draft,
+    // Published by the scheduler once `publish_at` is reached
+    Scheduled,
+    #[default]
+    Published,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Hashtag,
+    Mention,
+}
+
+// A #hashtag or @mention in the content, linked to its tag or user.
+// `start` and `end` are char offsets into the content, sigil included.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostEntity {
+    pub kind: EntityKind,
+    pub start: u32,
+    pub end: u32,
+    pub text: String,
+    // Hex id of the tag or user, None for mentions of unknown users
+    pub target_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -59,6 +107,30 @@ pub struct Post {
     pub comments_count: i32,
     #[serde(rename = "type")]
     pub post_type: PostType,
+    // Post this one replies to, forming a conversation thread
+    #[serde(default)]
+    pub reply_to: Option<ObjectId>,
+    #[serde(default)]
+    pub quoted_post_id: Option<ObjectId>,
+    #[serde(default)]
+    pub replies_count: i32,
+    #[serde(default)]
+    pub reposts_count: i32,
+    #[serde(default)]
+    pub entities: Vec<PostEntity>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub status: PostStatus,
+    // RFC 3339 string like the other dates, so it can be compared with `$lte`
+    #[serde(default)]
+    pub publish_at: Option<String>,
+    // Set once the published post has been changed, see `PostRevision`
+    #[serde(default)]
+    pub edited: bool,
+    // Set while the post is in its author's trash
+    #[serde(default)]
+    pub deleted_at: Option<String>,
     #[serde(
         deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
         serialize_with = "serialize_bson_datetime_as_rfc3339_string"
@@ -83,6 +155,16 @@ impl Post {
                 likes_count: d.likes_count,
                 comments_count: d.comments_count,
                 post_type: d.post_type,
+                reply_to: d.reply_to,
+                quoted_post_id: d.quoted_post_id,
+                replies_count: d.replies_count,
+                reposts_count: d.reposts_count,
+                entities: d.entities,
+                visibility: d.visibility,
+                status: d.status,
+                publish_at: d.publish_at,
+                edited: d.edited,
+                deleted_at: d.deleted_at,
                 created_at: d.created_at.to_string(),
                 updated_at: d.updated_at.to_string(),
             }),
@@ -90,7 +172,7 @@ impl Post {
         }
     }
 
-    async fn author(author_id: ObjectId) -> Option<UserResponse> {
+    async fn author(author_id: ObjectId) -> Option<PublicUserResponse> {
         let db = get_database().await;
         let collection: Collection<User> = db.collection("users");
 
@@ -98,10 +180,7 @@ impl Post {
         if user.is_err() {
             return None;
         }
-        match user.unwrap() {
-            Some(u) => Some(User::to_user(u)),
-            None => None,
-        }
+        user.unwrap().map(User::to_public)
     }
 
     async fn tags(tag_ids: Vec<ObjectId>) -> Option<Vec<TagResponse>> {
@@ -117,12 +196,12 @@ impl Post {
                 {
                     let value = collection.clone();
                     async move {
-                        match value.find_one(doc! { "_id": tag_id }).await {
-                            Ok(Some(tag)) => {
-                                let mut tags = tags.lock().await;
-                                tags.push(Tag::to_tag(tag));
-                            }
-                            Ok(None) | Err(_) => {} // Handle not found and error cases
+                        // Not found and error cases are skipped
+                        if let Ok(Some(tag)) =
+                            value.find_one(not_deleted(doc! { "_id": tag_id })).await
+                        {
+                            let mut tags = tags.lock().await;
+                            tags.push(Tag::to_tag(tag));
                         }
                     }
                 }
@@ -146,6 +225,16 @@ impl Default for Post {
             post_type: PostType::Single,
             likes_count: 0,
             comments_count: 0,
+            reply_to: None,
+            quoted_post_id: None,
+            replies_count: 0,
+            reposts_count: 0,
+            entities: Vec::new(),
+            visibility: Visibility::Public,
+            status: PostStatus::Published,
+            publish_at: None,
+            edited: false,
+            deleted_at: None,
             created_at: DateTime::now(),
             updated_at: DateTime::now(),
         }
@@ -161,6 +250,37 @@ pub struct PostRequest {
         deserialize_with = "deserialize_string_vec_as_object_id_vec"
     )]
     pub tags: Vec<ObjectId>,
+    // Only read on creation, a post can't be moved to another thread
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    #[serde(default)]
+    pub quoted_post_id: Option<String>,
+    // Left out on updates to keep the current visibility; public on creation
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    // Only "draft" is meaningful, a `publish_at` schedules the post
+    #[serde(default)]
+    pub status: Option<PostStatus>,
+    #[serde(default)]
+    pub publish_at: Option<String>,
+}
+
+impl PostRequest {
+    // Status and normalized publish time requested for the post
+    pub fn schedule(&self) -> Result<(PostStatus, Option<String>), String> {
+        if let Some(publish_at) = &self.publish_at {
+            let publish_at = normalize_rfc3339(publish_at)?;
+            if publish_at.as_str() <= now_rfc3339().as_str() {
+                return Err("publish_at must be in the future".to_string());
+            }
+            return Ok((PostStatus::Scheduled, Some(publish_at)));
+        }
+        match self.status {
+            Some(PostStatus::Scheduled) => Err("Scheduled posts need a publish_at".to_string()),
+            Some(status) => Ok((status, None)),
+            None => Ok((PostStatus::Published, None)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -171,12 +291,341 @@ pub struct PostResponse {
     pub id: ObjectId,
     pub content: String,
     pub media: Option<Vec<Media>>,
-    pub author: Option<UserResponse>,
+    pub author: Option<PublicUserResponse>,
     pub tags: Option<Vec<TagResponse>>,
     pub likes_count: i32,
     pub comments_count: i32,
     #[serde(rename = "type")]
     pub post_type: PostType,
+    #[serde(default, serialize_with = "serialize_option_object_id_as_hex_string")]
+    pub reply_to: Option<ObjectId>,
+    #[serde(default, serialize_with = "serialize_option_object_id_as_hex_string")]
+    pub quoted_post_id: Option<ObjectId>,
+    #[serde(default)]
+    pub replies_count: i32,
+    #[serde(default)]
+    pub reposts_count: i32,
+    #[serde(default)]
+    pub entities: Vec<PostEntity>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub status: PostStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<String>,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
-}
\ No newline at end of file
+}
+
+// How many levels of replies a thread lookup follows
+pub const THREAD_MAX_DEPTH: i32 = 20;
+
+// A post with its nested replies
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThreadNode {
+    #[serde(flatten)]
+    pub post: PostResponse,
+    pub replies: Vec<ThreadNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThreadResponse {
+    // From the root of the conversation down to the direct parent
+    pub ancestors: Vec<PostResponse>,
+    pub post: PostResponse,
+    pub replies: Vec<ThreadNode>,
+}
+
+// What a reader may see, resolved once per request
+#[derive(Debug, Clone)]
+pub struct Audience {
+    pub viewer_id: ObjectId,
+    pub following_ids: Vec<ObjectId>,
+    // Authors who have the viewer on their close friends list
+    pub close_friend_of_ids: Vec<ObjectId>,
+}
+
+impl Audience {
+    // Filter on the posts collection matching the posts visible to the viewer.
+    // Unpublished and deleted posts are left out for everyone, authors list them separately.
+    pub fn to_document(&self) -> Document {
+        doc! {
+            "status": { "$in": [null, "published"] },
+            "deleted_at": null,
+            "$or": [
+                { "author_id": self.viewer_id },
+                // Posts written before visibility existed are public
+                { "visibility": { "$in": [null, "public"] } },
+                { "visibility": "followers", "author_id": { "$in": self.following_ids.as_slice() } },
+                {
+                    "visibility": "mentioned",
+                    "entities": { "$elemMatch": { "kind": "mention", "target_id": self.viewer_id.to_hex() } },
+                },
+                { "visibility": "close_friends", "author_id": { "$in": self.close_friend_of_ids.as_slice() } },
+            ]
+        }
+    }
+
+    // Same rules as `to_document`, for a post already loaded
+    pub fn can_see(&self, post: &Post) -> bool {
+        if post.status != PostStatus::Published || post.deleted_at.is_some() {
+            return false;
+        }
+        if post.author_id == self.viewer_id {
+            return true;
+        }
+        match post.visibility {
+            Visibility::Public => true,
+            Visibility::Followers => self.following_ids.contains(&post.author_id),
+            Visibility::Mentioned => post.entities.iter().any(|entity| {
+                entity.kind == EntityKind::Mention
+                    && entity.target_id.as_deref() == Some(self.viewer_id.to_hex().as_str())
+            }),
+            Visibility::Private => false,
+            Visibility::CloseFriends => self.close_friend_of_ids.contains(&post.author_id),
+        }
+    }
+
+    pub fn restrict(&self, filter: Document) -> Document {
+        if filter.is_empty() {
+            self.to_document()
+        } else {
+            doc! { "$and": [filter, self.to_document()] }
+        }
+    }
+}
+
+pub const POST_SORT_FIELDS: &[&str] = &["created_at", "updated_at", "likes_count", "comments_count"];
+
+// Query string filters for GET /posts: ?author=&tag=&from=&to=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PostFilter {
+    pub author: Option<String>,
+    pub tag: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl PostFilter {
+    pub fn to_document(&self) -> Result<Document, String> {
+        let mut filter = doc! {};
+        if let Some(author) = &self.author {
+            let author_id = ObjectId::parse_str(author).map_err(|_| "Invalid author ID")?;
+            filter.insert("author_id", author_id);
+        }
+        if let Some(tag) = &self.tag {
+            let tag_id = ObjectId::parse_str(tag).map_err(|_| "Invalid tag ID")?;
+            filter.insert("tags", tag_id);
+        }
+
+        // Dates are stored as RFC 3339 strings, so the bounds are normalized to the same format
+        let mut created_at = doc! {};
+        if let Some(from) = &self.from {
+            created_at.insert("$gte", normalize_rfc3339(from)?);
+        }
+        if let Some(to) = &self.to {
+            created_at.insert("$lte", normalize_rfc3339(to)?);
+        }
+        if !created_at.is_empty() {
+            filter.insert("created_at", created_at);
+        }
+        Ok(filter)
+    }
+}
+
+fn normalize_rfc3339(value: &str) -> Result<String, String> {
+    DateTime::parse_rfc3339_str(value)
+        .ok()
+        .and_then(|date| date.try_to_rfc3339_string().ok())
+        .ok_or(format!("Invalid date '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::{to_bson, Bson};
+
+    // Just enough of MongoDB's matching for the operators `Audience` uses
+    fn matches(document: &Document, filter: &Document) -> bool {
+        filter.iter().all(|(key, condition)| match key.as_str() {
+            "$or" => condition.as_array().unwrap().iter().any(|branch| {
+                matches(document, branch.as_document().unwrap())
+            }),
+            _ => field_matches(document.get(key).unwrap_or(&Bson::Null), condition),
+        })
+    }
+
+    fn field_matches(value: &Bson, condition: &Bson) -> bool {
+        match condition.as_document() {
+            Some(condition) if condition.keys().all(|key| key.starts_with('$')) => {
+                condition.iter().all(|(op, operand)| match op.as_str() {
+                    "$in" => operand.as_array().unwrap().contains(value),
+                    "$elemMatch" => value.as_array().is_some_and(|values| {
+                        values.iter().any(|element| {
+                            matches(element.as_document().unwrap(), operand.as_document().unwrap())
+                        })
+                    }),
+                    _ => panic!("unsupported operator {}", op),
+                })
+            }
+            _ => value == condition,
+        }
+    }
+
+    fn post(author_id: ObjectId, visibility: Visibility) -> Document {
+        doc! {
+            "author_id": author_id,
+            "visibility": to_bson(&visibility).unwrap(),
+            "status": to_bson(&PostStatus::Published).unwrap(),
+            "deleted_at": Bson::Null,
+            "entities": [],
+        }
+    }
+
+    fn audience(viewer_id: ObjectId) -> Audience {
+        Audience { viewer_id, following_ids: vec![], close_friend_of_ids: vec![] }
+    }
+
+    #[test]
+    fn authors_see_all_their_published_posts() {
+        let author = ObjectId::new();
+        let filter = audience(author).to_document();
+        for visibility in [
+            Visibility::Public,
+            Visibility::Followers,
+            Visibility::Mentioned,
+            Visibility::Private,
+            Visibility::CloseFriends,
+        ] {
+            assert!(matches(&post(author, visibility), &filter));
+        }
+    }
+
+    #[test]
+    fn strangers_only_see_public_posts() {
+        let author = ObjectId::new();
+        let filter = audience(ObjectId::new()).to_document();
+        assert!(matches(&post(author, Visibility::Public), &filter));
+        for visibility in [
+            Visibility::Followers,
+            Visibility::Mentioned,
+            Visibility::Private,
+            Visibility::CloseFriends,
+        ] {
+            assert!(!matches(&post(author, visibility), &filter));
+        }
+    }
+
+    #[test]
+    fn followers_and_close_friends_see_their_posts() {
+        let author = ObjectId::new();
+        let viewer = Audience { following_ids: vec![author], ..audience(ObjectId::new()) };
+        let filter = viewer.to_document();
+        assert!(matches(&post(author, Visibility::Followers), &filter));
+        assert!(!matches(&post(author, Visibility::CloseFriends), &filter));
+        assert!(!matches(&post(author, Visibility::Private), &filter));
+
+        let friend = Audience { close_friend_of_ids: vec![author], ..audience(ObjectId::new()) };
+        assert!(matches(&post(author, Visibility::CloseFriends), &friend.to_document()));
+    }
+
+    #[test]
+    fn mentioned_users_see_mentioned_posts() {
+        let author = ObjectId::new();
+        let viewer = ObjectId::new();
+        let mut mentioned = post(author, Visibility::Mentioned);
+        mentioned.insert(
+            "entities",
+            vec![doc! { "kind": "mention", "target_id": viewer.to_hex() }],
+        );
+        assert!(matches(&mentioned, &audience(viewer).to_document()));
+        assert!(!matches(&mentioned, &audience(ObjectId::new()).to_document()));
+    }
+
+    fn mentioning(author_id: ObjectId, visibility: Visibility, mentioned: ObjectId) -> Post {
+        Post {
+            author_id,
+            visibility,
+            entities: vec![PostEntity {
+                kind: EntityKind::Mention,
+                start: 0,
+                end: 4,
+                text: "bob".to_string(),
+                target_id: Some(mentioned.to_hex()),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mentions_only_reach_users_who_can_open_the_post() {
+        let author = ObjectId::new();
+        let mentioned = ObjectId::new();
+        let stranger = audience(mentioned);
+        assert!(!stranger.can_see(&mentioning(author, Visibility::Followers, mentioned)));
+        assert!(!stranger.can_see(&mentioning(author, Visibility::CloseFriends, mentioned)));
+        assert!(!stranger.can_see(&mentioning(author, Visibility::Private, mentioned)));
+        assert!(stranger.can_see(&mentioning(author, Visibility::Mentioned, mentioned)));
+        assert!(stranger.can_see(&mentioning(author, Visibility::Public, mentioned)));
+
+        let follower = Audience { following_ids: vec![author], ..audience(mentioned) };
+        assert!(follower.can_see(&mentioning(author, Visibility::Followers, mentioned)));
+    }
+
+    #[test]
+    fn can_see_agrees_with_the_filter() {
+        let author = ObjectId::new();
+        let viewer = ObjectId::new();
+        let audiences = [
+            audience(author),
+            audience(viewer),
+            Audience { following_ids: vec![author], ..audience(viewer) },
+            Audience { close_friend_of_ids: vec![author], ..audience(viewer) },
+        ];
+        for visibility in [
+            Visibility::Public,
+            Visibility::Followers,
+            Visibility::Mentioned,
+            Visibility::Private,
+            Visibility::CloseFriends,
+        ] {
+            for mentioned in [viewer, ObjectId::new()] {
+                for status in [PostStatus::Published, PostStatus::Draft] {
+                    let post = Post { status, ..mentioning(author, visibility, mentioned) };
+                    let document = mongodb::bson::to_document(&post).unwrap();
+                    for audience in &audiences {
+                        assert_eq!(
+                            audience.can_see(&post),
+                            matches(&document, &audience.to_document()),
+                            "{:?} {:?}",
+                            visibility,
+                            status
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hides_drafts_and_trashed_posts_even_from_the_author() {
+        let author = ObjectId::new();
+        let filter = audience(author).to_document();
+        let mut draft = post(author, Visibility::Public);
+        draft.insert("status", to_bson(&PostStatus::Draft).unwrap());
+        assert!(!matches(&draft, &filter));
+
+        let mut trashed = post(author, Visibility::Public);
+        trashed.insert("deleted_at", now_rfc3339());
+        assert!(!matches(&trashed, &filter));
+
+        let mut legacy = post(author, Visibility::Public);
+        legacy.remove("visibility");
+        legacy.remove("status");
+        assert!(matches(&legacy, &audience(ObjectId::new()).to_document()));
+    }
+}
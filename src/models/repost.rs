@@ -0,0 +1,21 @@
+use mongodb::bson::{
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+    },
+    DateTime,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Repost {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub post_id: ObjectId,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
@@ -0,0 +1,79 @@
+use mongodb::bson::{
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+        serialize_object_id_as_hex_string,
+    },
+    DateTime,
+};
+use serde::{Deserialize, Serialize};
+
+use super::post::{Media, Visibility};
+use crate::utils::diff::DiffChunk;
+use crate::utils::helps::serialize_object_id_vec_as_string_vec;
+
+// Snapshot of a post as it was before an edit. Revisions are only ever inserted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostRevision {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub post_id: ObjectId,
+    // 1 for the originally published version, increasing with every edit
+    pub revision: i32,
+    // User whose edit replaced this version
+    pub edited_by: ObjectId,
+    pub content: String,
+    pub media: Vec<Media>,
+    #[serde(rename = "tags")]
+    pub tag_ids: Vec<ObjectId>,
+    pub visibility: Visibility,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
+
+impl PostRevision {
+    pub fn to_revision(revision: PostRevision) -> RevisionResponse {
+        RevisionResponse {
+            id: revision.id.unwrap(),
+            post_id: revision.post_id,
+            revision: revision.revision,
+            edited_by: revision.edited_by,
+            content: revision.content,
+            media: revision.media,
+            tag_ids: revision.tag_ids,
+            visibility: revision.visibility,
+            created_at: revision.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevisionResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    #[serde(rename(serialize = "id"))]
+    #[serde(rename(deserialize = "_id"))]
+    pub id: ObjectId,
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub post_id: ObjectId,
+    pub revision: i32,
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    pub edited_by: ObjectId,
+    pub content: String,
+    pub media: Vec<Media>,
+    #[serde(rename = "tags", serialize_with = "serialize_object_id_vec_as_string_vec")]
+    pub tag_ids: Vec<ObjectId>,
+    pub visibility: Visibility,
+    pub created_at: String,
+}
+
+// Changes to the content from a revision to the version that replaced it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevisionDiff {
+    pub from_revision: i32,
+    // None when the newer side is the current version of the post
+    pub to_revision: Option<i32>,
+    pub chunks: Vec<DiffChunk>,
+}
@@ -0,0 +1,157 @@
+use mongodb::bson::{oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::search_engine::highlight::highlight;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchType {
+    Posts,
+    Items,
+    Users,
+    Tags,
+}
+
+impl SearchType {
+    pub const ALL: [SearchType; 4] = [
+        SearchType::Posts,
+        SearchType::Items,
+        SearchType::Users,
+        SearchType::Tags,
+    ];
+
+    // Also the name of the collection backing the type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchType::Posts => "posts",
+            SearchType::Items => "items",
+            SearchType::Users => "users",
+            SearchType::Tags => "tags",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<SearchType> {
+        SearchType::ALL.into_iter().find(|t| t.as_str() == value)
+    }
+}
+
+// Query string for GET /search: ?q=&type=, pagination comes from `PaginationQuery`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(rename = "type")]
+    pub search_type: Option<SearchType>,
+}
+
+// Searchable projection of a post, item, user or tag
+#[derive(Debug, Clone)]
+pub struct SearchDocument {
+    pub id: ObjectId,
+    pub search_type: SearchType,
+    pub title: String,
+    pub body: String,
+}
+
+impl SearchDocument {
+    pub fn new(search_type: SearchType, id: ObjectId, title: &str, body: &str) -> Self {
+        SearchDocument {
+            id,
+            search_type,
+            title: title.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    // Build from a raw document of the collection backing `search_type`
+    pub fn from_document(search_type: SearchType, doc: &Document) -> Option<Self> {
+        let id = doc.get_object_id("_id").ok()?;
+        let text = |key: &str| doc.get_str(key).unwrap_or_default();
+        let document = match search_type {
+            SearchType::Posts => Self::new(search_type, id, "", text("content")),
+            SearchType::Items => Self::new(search_type, id, text("name"), text("description")),
+            SearchType::Users => Self::new(search_type, id, text("username"), text("bio")),
+            SearchType::Tags => Self::new(search_type, id, text("name"), ""),
+        };
+        Some(document)
+    }
+
+    pub fn to_hit(&self, score: f64, terms: &[String]) -> SearchHit {
+        // Posts have no title, so the start of the content stands in for it
+        let title = if self.title.is_empty() {
+            self.body.chars().take(80).collect()
+        } else {
+            self.title.clone()
+        };
+        let highlight = if self.body.is_empty() {
+            highlight(&self.title, terms)
+        } else {
+            highlight(&self.body, terms)
+        };
+        SearchHit {
+            id: self.id.to_hex(),
+            search_type: self.search_type,
+            score,
+            title,
+            highlight,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub search_type: SearchType,
+    pub score: f64,
+    pub title: String,
+    // HTML escaped snippet with matches wrapped in <mark>
+    pub highlight: String,
+}
+
+// Number of matches per type, used by clients to render the result tabs
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchTabs {
+    pub posts: u64,
+    pub items: u64,
+    pub users: u64,
+    pub tags: u64,
+}
+
+impl SearchTabs {
+    pub fn set(&mut self, search_type: SearchType, count: u64) {
+        match search_type {
+            SearchType::Posts => self.posts = count,
+            SearchType::Items => self.items = count,
+            SearchType::Users => self.users = count,
+            SearchType::Tags => self.tags = count,
+        }
+    }
+
+    pub fn total(&self, search_type: Option<SearchType>) -> u64 {
+        match search_type {
+            Some(SearchType::Posts) => self.posts,
+            Some(SearchType::Items) => self.items,
+            Some(SearchType::Users) => self.users,
+            Some(SearchType::Tags) => self.tags,
+            None => self.posts + self.items + self.users + self.tags,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchResponse {
+    pub query: String,
+    pub tabs: SearchTabs,
+    pub data: Vec<SearchHit>,
+    pub next_cursor: Option<String>,
+    pub limit: i64,
+}
+
+// Lowercased alphanumeric terms of a search query
+pub fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
@@ -1,12 +1,17 @@
 use mongodb::bson::{
+    doc,
     oid::ObjectId,
     serde_helpers::{
         deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string
     },
-    DateTime,
+    DateTime, Document,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::jwt::Claims;
+use crate::user::Role;
+use crate::utils::helps::{escape_regex, normalize_tag_name, serialize_option_object_id_as_hex_string};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tag {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -16,6 +21,37 @@ pub struct Tag {
     #[serde(serialize_with = "serialize_object_id_as_hex_string")]
     pub owner_id: ObjectId,
     pub name: String,
+    // `name` as compared for uniqueness, see `normalize_tag_name`
+    #[serde(default)]
+    pub normalized_name: String,
+    #[serde(default)]
+    pub slug: String,
+    // Slugs the tag had before renames and merges, kept so old links redirect
+    #[serde(default)]
+    pub previous_slugs: Vec<String>,
+    // Published posts carrying the tag
+    #[serde(default)]
+    pub usage_count: i64,
+    // Broader tag this one is filed under; browsing the parent includes it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_option_object_id_as_hex_string"
+    )]
+    pub parent_id: Option<ObjectId>,
+    // Set on synonyms: the tag they are another name for
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_option_object_id_as_hex_string"
+    )]
+    pub canonical_id: Option<ObjectId>,
+    // Set while the tag is in its owner's trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    // Set on the source of a merge until the merge is done
+    #[serde(default, skip_serializing)]
+    pub merging_into: Option<ObjectId>,
     #[serde(
         deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
         serialize_with = "serialize_bson_datetime_as_rfc3339_string"
@@ -34,6 +70,14 @@ impl Default for Tag {
             id: None,
             owner_id: ObjectId::new(),
             name: String::new(),
+            normalized_name: String::new(),
+            slug: String::new(),
+            previous_slugs: Vec::new(),
+            usage_count: 0,
+            parent_id: None,
+            canonical_id: None,
+            deleted_at: None,
+            merging_into: None,
             created_at: DateTime::now(),
             updated_at: DateTime::now(),
         }
@@ -45,8 +89,14 @@ impl Tag {
         TagResponse {
             _id: tag.clone().id.unwrap(),
             name: tag.clone().name,
+            slug: tag.slug,
         }
     }
+
+    // Renaming or trashing a tag is left to its owner and admins
+    pub fn is_managed_by(&self, claims: &Claims) -> bool {
+        self.owner_id.to_hex() == claims.sub || claims.role == Role::Admin.as_str()
+    }
 }
 
 #[derive(Debug , Deserialize, Serialize)]
@@ -61,4 +111,119 @@ pub struct TagResponse {
     #[serde(serialize_with = "serialize_object_id_as_hex_string")]
     pub _id: ObjectId,
     pub name: String,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub slug: String,
+}
+
+// Body of POST /tags/{id}/merge
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TagMergeRequest {
+    // Id of the tag that takes over the merged tag's posts
+    pub into: String,
+}
+
+pub const TAG_SORT_FIELDS: &[&str] = &["name", "created_at", "updated_at", "usage_count"];
+
+// Body of PUT /admin/tags/{id}/parent; null detaches the tag from its parent
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TagParentRequest {
+    pub parent_id: Option<String>,
+}
+
+// Body of PUT /admin/tags/{id}/canonical; null makes the tag canonical again
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TagCanonicalRequest {
+    pub canonical_id: Option<String>,
+}
+
+// Where a tag sits in the taxonomy. Ancestors go from the parent up to the root.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagTaxonomy {
+    pub tag: Tag,
+    pub canonical: Option<TagResponse>,
+    pub ancestors: Vec<TagResponse>,
+    pub children: Vec<TagResponse>,
+    pub synonyms: Vec<TagResponse>,
+}
+
+// Completions returned by default and at most by the suggest endpoints
+pub const SUGGEST_DEFAULT_LIMIT: i64 = 10;
+pub const SUGGEST_MAX_LIMIT: i64 = 50;
+
+fn resolve_suggest_limit(limit: Option<i64>) -> Result<usize, String> {
+    let limit = limit.unwrap_or(SUGGEST_DEFAULT_LIMIT);
+    if limit < 1 {
+        return Err("limit must be greater than 0".to_string());
+    }
+    Ok(limit.min(SUGGEST_MAX_LIMIT) as usize)
+}
+
+// Query string for GET /tags/suggest: ?prefix=&limit=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagSuggestQuery {
+    #[serde(default)]
+    pub prefix: String,
+    pub limit: Option<i64>,
+}
+
+impl TagSuggestQuery {
+    pub fn resolve_limit(&self) -> Result<usize, String> {
+        resolve_suggest_limit(self.limit)
+    }
+}
+
+// Body of POST /tags/suggest: the content of a post being composed
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagSuggestRequest {
+    pub content: String,
+    pub limit: Option<i64>,
+}
+
+impl TagSuggestRequest {
+    pub fn resolve_limit(&self) -> Result<usize, String> {
+        resolve_suggest_limit(self.limit)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagSuggestion {
+    #[serde(flatten)]
+    pub tag: TagResponse,
+    pub usage_count: i64,
+    pub score: f64,
+}
+
+// Query string filters for GET /tags: ?prefix=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagFilter {
+    pub prefix: Option<String>,
+}
+
+impl TagFilter {
+    pub fn to_document(&self) -> Document {
+        match &self.prefix {
+            // Normalized names make the match case-insensitive and can use the index
+            Some(prefix) if !prefix.is_empty() => doc! {
+                "normalized_name": { "$regex": format!("^{}", escape_regex(&normalize_tag_name(prefix))) }
+            },
+            _ => doc! {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(sub: String, role: Role) -> Claims {
+        Claims { sub, role: role.as_str().to_string(), exp: None, ver: 0, scopes: None }
+    }
+
+    #[test]
+    fn only_owners_and_admins_manage_tags() {
+        let tag = Tag::default();
+        assert!(tag.is_managed_by(&claims(tag.owner_id.to_hex(), Role::User)));
+        assert!(tag.is_managed_by(&claims(ObjectId::new().to_hex(), Role::Admin)));
+        assert!(!tag.is_managed_by(&claims(ObjectId::new().to_hex(), Role::User)));
+    }
+}
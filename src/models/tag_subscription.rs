@@ -0,0 +1,55 @@
+use mongodb::bson::{
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+    },
+    DateTime,
+};
+use serde::{Deserialize, Serialize};
+
+use super::tag::TagResponse;
+
+// A user either follows or mutes a tag, never both
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionKind {
+    Follow,
+    Mute,
+}
+
+impl SubscriptionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Follow => "follow",
+            SubscriptionKind::Mute => "mute",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagSubscription {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub tag_id: ObjectId,
+    pub kind: SubscriptionKind,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagSubscriptionResponse {
+    pub tag: TagResponse,
+    pub kind: SubscriptionKind,
+    pub created_at: String,
+}
+
+// Query string of GET /feed: ?include_tags=true mixes in posts of followed tags
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HomeFeedQuery {
+    #[serde(default)]
+    pub include_tags: bool,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashType {
+    #[default]
+    Posts,
+    Items,
+    Tags,
+}
+
+// Query string for GET /users/me/trash: ?type=, pagination comes from `PaginationQuery`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TrashQuery {
+    #[serde(rename = "type", default)]
+    pub trash_type: TrashType,
+}
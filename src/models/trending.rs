@@ -0,0 +1,142 @@
+use mongodb::bson::{
+    oid::ObjectId,
+    serde_helpers::{
+        deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
+    },
+    DateTime,
+};
+use serde::{Deserialize, Serialize};
+
+use super::pagination::DEFAULT_LIMIT;
+use super::post::PostResponse;
+use super::tag::TagResponse;
+
+// Sliding window trending is computed over
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrendingWindow {
+    #[serde(rename = "1h")]
+    Hour,
+    #[default]
+    #[serde(rename = "24h")]
+    Day,
+    #[serde(rename = "7d")]
+    Week,
+}
+
+impl TrendingWindow {
+    pub const ALL: [TrendingWindow; 3] = [
+        TrendingWindow::Hour,
+        TrendingWindow::Day,
+        TrendingWindow::Week,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendingWindow::Hour => "1h",
+            TrendingWindow::Day => "24h",
+            TrendingWindow::Week => "7d",
+        }
+    }
+
+    pub fn duration_millis(&self) -> i64 {
+        const HOUR: i64 = 60 * 60 * 1000;
+        match self {
+            TrendingWindow::Hour => HOUR,
+            TrendingWindow::Day => 24 * HOUR,
+            TrendingWindow::Week => 7 * 24 * HOUR,
+        }
+    }
+
+    // Activity loses half its weight every quarter of the window
+    pub fn half_life_millis(&self) -> i64 {
+        self.duration_millis() / 4
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendingKind {
+    Tags,
+    Posts,
+}
+
+impl TrendingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendingKind::Tags => "tags",
+            TrendingKind::Posts => "posts",
+        }
+    }
+}
+
+// Number of entries kept per snapshot; requests can ask for fewer
+pub const TRENDING_SIZE: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrendingEntry {
+    pub id: ObjectId,
+    pub score: f64,
+}
+
+// Ranking computed by the trending job, cached until the next run.
+// `_id` is "<kind>:<window>" so every instance writes the same document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrendingSnapshot {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub kind: TrendingKind,
+    pub window: TrendingWindow,
+    pub entries: Vec<TrendingEntry>,
+    #[serde(
+        deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub computed_at: DateTime,
+}
+
+impl TrendingSnapshot {
+    pub fn key(kind: TrendingKind, window: TrendingWindow) -> String {
+        format!("{}:{}", kind.as_str(), window.as_str())
+    }
+}
+
+// Query string for GET /trending/tags and /trending/posts: ?window=&limit=
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TrendingQuery {
+    #[serde(default)]
+    pub window: TrendingWindow,
+    pub limit: Option<i64>,
+}
+
+impl TrendingQuery {
+    pub fn resolve_limit(&self) -> Result<usize, String> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit < 1 {
+            return Err("limit must be greater than 0".to_string());
+        }
+        Ok(limit.min(TRENDING_SIZE) as usize)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrendingTag {
+    #[serde(flatten)]
+    pub tag: TagResponse,
+    pub usage_count: i64,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrendingPost {
+    #[serde(flatten)]
+    pub post: PostResponse,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrendingResponse<T> {
+    pub window: TrendingWindow,
+    // None until the trending job has run once
+    pub computed_at: Option<String>,
+    pub data: Vec<T>,
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+// TOTP second factor of an account. Stays in the database; responses only say
+// whether it's enabled.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TwoFactor {
+    #[serde(default)]
+    pub enabled: bool,
+    // Base32 TOTP secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    // Secret being set up, until a code from it is confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_secret: Option<String>,
+    // Hashes of the unused recovery codes
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    // Time step of the last accepted code, which can't be used again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_step: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_at: Option<String>,
+}
+
+// Returned when setup starts, to be added to an authenticator app
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+// A code from the authenticator app, or a recovery code
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+// Shown once, when two-factor authentication is enabled
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+// Second step of a login, with the challenge the first step returned
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge: String,
+    pub code: String,
+}
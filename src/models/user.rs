@@ -4,9 +4,13 @@ use mongodb::bson::{
         deserialize_bson_datetime_from_rfc3339_string, serialize_bson_datetime_as_rfc3339_string,
         serialize_object_id_as_hex_string,
     },
-    DateTime,
+    doc, DateTime, Document,
 };
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use super::two_factor::TwoFactor;
+use crate::utils::helps::username_key;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "lowercase")] // Converts enum variants to lowercase when serializing/deserializing
@@ -16,11 +20,88 @@ pub enum Status {
     Deleted,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Carried in the JWT `role` claim. Admins are promoted in the database.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "USER",
+            Role::Admin => "ADMIN",
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Which optional profile fields other users get to see
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PrivacySettings {
+    #[serde(default)]
+    pub show_email: bool,
+    #[serde(default)]
+    pub show_last_login: bool,
+    #[serde(default = "default_true")]
+    pub show_follow_counts: bool,
+    #[serde(default = "default_true")]
+    pub show_joined_date: bool,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        PrivacySettings {
+            show_email: false,
+            show_last_login: false,
+            show_follow_counts: true,
+            show_joined_date: true,
+        }
+    }
+}
+
+// Body of PATCH /users/me/privacy; absent fields are left unchanged
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PrivacyUpdateRequest {
+    pub show_email: Option<bool>,
+    pub show_last_login: Option<bool>,
+    pub show_follow_counts: Option<bool>,
+    pub show_joined_date: Option<bool>,
+}
+
+impl PrivacyUpdateRequest {
+    // `$set` fields for the settings present in the request
+    pub fn to_document(&self) -> Document {
+        let mut set = doc! {};
+        let settings = [
+            ("show_email", self.show_email),
+            ("show_last_login", self.show_last_login),
+            ("show_follow_counts", self.show_follow_counts),
+            ("show_joined_date", self.show_joined_date),
+        ];
+        for (name, value) in settings {
+            if let Some(value) = value {
+                set.insert(format!("privacy.{}", name), value);
+            }
+        }
+        set
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
     #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
     pub id: ObjectId,
     pub username: String,
+    // `username_key` of the username, unique across users
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username_key: String,
     pub email: String,
     pub password: String,
     pub avatar: Option<String>,
@@ -30,6 +111,23 @@ pub struct User {
     pub is_verified: bool,
     pub last_login: Option<DateTime>,
     pub status: Status,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub privacy: PrivacySettings,
+    // Part of every JWT issued to the user; bumping it revokes them all
+    #[serde(default)]
+    pub token_version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<String>,
+    #[serde(default)]
+    pub two_factor: TwoFactor,
+    // Last username change, for the change cooldown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username_changed_at: Option<String>,
+    // Readers of the user's close friends posts
+    #[serde(default)]
+    pub close_friend_ids: Vec<ObjectId>,
     #[serde(
         deserialize_with = "deserialize_bson_datetime_from_rfc3339_string",
         serialize_with = "serialize_bson_datetime_as_rfc3339_string"
@@ -42,7 +140,7 @@ pub struct User {
     pub updated_at: DateTime,
 }
 
-
+// The user's own view of their account, returned by /users/me
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserResponse {
     #[serde(serialize_with = "serialize_object_id_as_hex_string")]
@@ -56,18 +154,31 @@ pub struct UserResponse {
     pub follower_count: i32,
     pub following_count: i32,
     pub is_verified: bool,
+    pub two_factor_enabled: bool,
     pub last_login: Option<DateTime>,
     pub status: Status,
+    pub privacy: PrivacySettings,
     pub created_at: String,
     pub updated_at: String,
 }
 
+// What admins see of an account
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminUserResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub role: Role,
+    pub username_changed_at: Option<String>,
+    pub close_friends_count: usize,
+}
+
 // Default values for fields
 impl Default for User {
     fn default() -> Self {
         User {
             id: ObjectId::new(),
             username: String::new(),
+            username_key: String::new(),
             email: String::new(),
             password: String::new(),
             avatar: None,
@@ -79,6 +190,13 @@ impl Default for User {
             is_verified: false,
             last_login: None,
             status: Status::Active,
+            role: Role::User,
+            privacy: PrivacySettings::default(),
+            token_version: 0,
+            password_changed_at: None,
+            two_factor: TwoFactor::default(),
+            username_changed_at: None,
+            close_friend_ids: Vec::new(),
         }
     }
 }
@@ -94,22 +212,288 @@ impl User {
             follower_count: user.follower_count.to_owned(),
             following_count: user.following_count.to_owned(),
             is_verified: user.is_verified.to_owned(),
+            two_factor_enabled: user.two_factor.enabled,
             last_login: user.last_login.to_owned(),
             status: user.status.to_owned(),
+            privacy: user.privacy,
             created_at: user.created_at.to_owned().to_string(),
             updated_at: user.updated_at.to_owned().to_string(),
         }
     }
+
+    pub fn to_admin(user: User) -> AdminUserResponse {
+        AdminUserResponse {
+            role: user.role,
+            username_changed_at: user.username_changed_at.clone(),
+            close_friends_count: user.close_friend_ids.len(),
+            user: User::to_user(user),
+        }
+    }
+}
+
+// What other users see, e.g. on profiles and as the author of posts. Optional
+// fields are left out as the user's privacy settings ask.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicUserResponse {
+    #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+    #[serde(rename(serialize = "id"))]
+    #[serde(rename(deserialize = "_id"))]
+    pub id: ObjectId,
+    pub username: String,
+    pub avatar: Option<String>,
+    pub bio: Option<String>,
+    pub is_verified: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follower_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub following_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl User {
+    pub fn to_public(user: User) -> PublicUserResponse {
+        let privacy = user.privacy;
+        PublicUserResponse {
+            id: user.id,
+            username: user.username,
+            avatar: user.avatar,
+            bio: user.bio,
+            is_verified: user.is_verified,
+            email: privacy.show_email.then_some(user.email),
+            last_login: user.last_login.filter(|_| privacy.show_last_login),
+            follower_count: privacy.show_follow_counts.then_some(user.follower_count),
+            following_count: privacy.show_follow_counts.then_some(user.following_count),
+            created_at: privacy
+                .show_joined_date
+                .then(|| user.created_at.try_to_rfc3339_string().unwrap_or_default()),
+        }
+    }
+}
+
+// Aggregation counterpart of `User::to_public` for a user joined at `path`,
+// e.g. "$user"
+pub fn public_user_projection(path: &str) -> Document {
+    let field = |name: &str| format!("{}.{}", path, name);
+    // Shown only when the setting is true, or unless it is false
+    let opt_in = |setting: &str, name: &str| {
+        doc! { "$cond": [{ "$eq": [field(&format!("privacy.{}", setting)), true] }, field(name), "$$REMOVE"] }
+    };
+    let opt_out = |setting: &str, name: &str| {
+        doc! { "$cond": [{ "$eq": [field(&format!("privacy.{}", setting)), false] }, "$$REMOVE", field(name)] }
+    };
+    doc! {
+        "_id": field("_id"),
+        "username": field("username"),
+        "avatar": field("avatar"),
+        "bio": field("bio"),
+        "is_verified": field("is_verified"),
+        "email": opt_in("show_email", "email"),
+        "last_login": opt_in("show_last_login", "last_login"),
+        "follower_count": opt_out("show_follow_counts", "follower_count"),
+        "following_count": opt_out("show_follow_counts", "following_count"),
+        "created_at": opt_out("show_joined_date", "created_at"),
+    }
+}
+
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 30;
+pub const BIO_MAX_LENGTH: usize = 160;
+
+// Names that could pass for the service itself or clash with routes
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin", "administrator", "root", "system", "support", "help", "moderator", "staff",
+    "official", "security", "api", "auth", "login", "logout", "register", "settings", "users",
+    "posts", "tags", "feed", "search", "trending", "null", "undefined", "anonymous", "everyone",
+];
+
+// The username in the form it is stored in, provided it is allowed. Compatibility
+// forms such as fullwidth letters are normalized to plain ones first.
+pub fn normalize_username(username: &str) -> Result<String, String> {
+    let username: String = username.trim().nfkc().collect();
+    let length = username.chars().count();
+    if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+        return Err(format!(
+            "username must be {} to {} characters long",
+            USERNAME_MIN_LENGTH, USERNAME_MAX_LENGTH
+        ));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("username may only contain letters, digits and underscores".to_string());
+    }
+    let key = username_key(&username);
+    if RESERVED_USERNAMES.iter().any(|reserved| username_key(reserved) == key) {
+        return Err("This username is reserved".to_string());
+    }
+    Ok(username)
+}
+
+pub const PASSWORD_MIN_LENGTH: usize = 8;
+pub const PASSWORD_MAX_LENGTH: usize = 128;
+
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "password1", "password123", "12345678", "123456789", "1234567890", "qwerty123",
+    "qwertyuiop", "iloveyou", "sunshine1", "letmein1", "welcome1", "admin123", "abc12345",
+    "football1", "baseball1", "trustno1", "passw0rd", "p@ssw0rd", "changeme",
+];
+
+// Password policy for new passwords; existing ones are never re-checked
+pub fn validate_password(password: &str, username: &str) -> Result<(), String> {
+    let length = password.chars().count();
+    if !(PASSWORD_MIN_LENGTH..=PASSWORD_MAX_LENGTH).contains(&length) {
+        return Err(format!(
+            "password must be {} to {} characters long",
+            PASSWORD_MIN_LENGTH, PASSWORD_MAX_LENGTH
+        ));
+    }
+    if !password.chars().any(char::is_alphabetic) || password.chars().all(char::is_alphabetic) {
+        return Err("password must contain a letter and a digit or symbol".to_string());
+    }
+    let lowercase = password.to_lowercase();
+    if !username.is_empty() && lowercase.contains(&username.to_lowercase()) {
+        return Err("password must not contain the username".to_string());
+    }
+    if COMMON_PASSWORDS.contains(&lowercase.as_str()) {
+        return Err("password is too common".to_string());
+    }
+    Ok(())
+}
+
+// Body of PATCH /users/me; absent fields are left unchanged, an empty bio clears it
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ProfileUpdateRequest {
+    pub username: Option<String>,
+    pub bio: Option<String>,
+}
+
+impl ProfileUpdateRequest {
+    // Trim the fields and check them against the profile rules
+    pub fn validate(mut self) -> Result<Self, String> {
+        if let Some(username) = &self.username {
+            self.username = Some(normalize_username(username)?);
+        }
+        if let Some(bio) = &self.bio {
+            let bio = bio.trim().to_string();
+            if bio.chars().count() > BIO_MAX_LENGTH {
+                return Err(format!("bio must be at most {} characters long", BIO_MAX_LENGTH));
+            }
+            self.bio = Some(bio);
+        }
+        if self.username.is_none() && self.bio.is_none() {
+            return Err("Nothing to update".to_string());
+        }
+        Ok(self)
+    }
+}
+
+// Body of PUT /users/me/avatar: the id of a finished chunked upload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AvatarRequest {
+    pub upload_id: String,
+}
+
+pub const EMAIL_MAX_LENGTH: usize = 254;
+
+// Emails are stored trimmed and lowercased, which is the form they're compared in
+pub fn normalize_email(email: &str) -> Result<String, String> {
+    let email = email.trim().to_lowercase();
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+        }
+        None => false,
+    };
+    if !valid || email.len() > EMAIL_MAX_LENGTH || email.chars().any(char::is_whitespace) {
+        return Err("Invalid email address".to_string());
+    }
+    Ok(email)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
     pub username: String,
+    pub email: String,
     pub password: String,
 }
 
+impl RegisterRequest {
+    pub fn validate(mut self) -> Result<Self, String> {
+        self.username = normalize_username(&self.username)?;
+        self.email = normalize_email(&self.email)?;
+        validate_password(&self.password, &self.username)?;
+        Ok(self)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
+
+impl LoginRequest {
+    // Only rules out input no account can match; the policies apply to new
+    // usernames and passwords
+    pub fn validate(mut self) -> Result<Self, String> {
+        self.username = self.username.trim().nfkc().collect();
+        let username_length = self.username.chars().count();
+        let password_length = self.password.chars().count();
+        if username_length == 0
+            || username_length > USERNAME_MAX_LENGTH
+            || password_length == 0
+            || password_length > PASSWORD_MAX_LENGTH
+        {
+            return Err("Invalid username or password".to_string());
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_compatibility_forms() {
+        assert_eq!(normalize_username("  ｊｏｈｎ_doe ").unwrap(), "john_doe");
+        assert_eq!(normalize_username("John_Doe").unwrap(), "John_Doe");
+    }
+
+    #[test]
+    fn rejects_invalid_usernames() {
+        assert!(normalize_username("jo").is_err());
+        assert!(normalize_username(&"a".repeat(USERNAME_MAX_LENGTH + 1)).is_err());
+        assert!(normalize_username("john doe").is_err());
+        assert!(normalize_username("jöhn").is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_usernames_and_look_alikes() {
+        for username in ["admin", "Admin", "adm1n", "ａｄｍｉｎ", "r00t", "5upport"] {
+            assert_eq!(
+                normalize_username(username).unwrap_err(),
+                "This username is reserved",
+                "{}",
+                username
+            );
+        }
+    }
+
+    #[test]
+    fn enforces_the_password_policy() {
+        assert!(validate_password("correct horse 9", "alice").is_ok());
+        assert!(validate_password("short1", "alice").is_err());
+        assert!(validate_password("onlyletters", "alice").is_err());
+        assert!(validate_password("12345678901", "alice").is_err());
+        assert!(validate_password("xAlice2024x", "alice").is_err());
+        assert_eq!(validate_password("Passw0rd", "alice").unwrap_err(), "password is too common");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::models::access_token::AccessToken;
+use crate::utils::helps::now_rfc3339;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::Error,
+    options::IndexOptions,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<AccessToken>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "token_hash": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    collection
+        .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+        .await?;
+    Ok(())
+}
+
+pub async fn create_token(
+    collection: &Collection<AccessToken>,
+    token: AccessToken,
+) -> Result<ObjectId, Error> {
+    let result = collection.insert_one(token).await?;
+    result
+        .inserted_id
+        .as_object_id()
+        .ok_or_else(|| Error::custom("Inserted access token has no ObjectId"))
+}
+
+// The token with this hash, unless it has expired
+pub async fn find_active_token(
+    collection: &Collection<AccessToken>,
+    token_hash: &str,
+) -> Result<Option<AccessToken>, Error> {
+    collection
+        .find_one(doc! {
+            "token_hash": token_hash,
+            "$or": [
+                { "expires_at": { "$exists": false } },
+                { "expires_at": { "$gt": now_rfc3339() } },
+            ],
+        })
+        .await
+}
+
+pub async fn get_user_tokens(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+) -> Result<Vec<AccessToken>, Error> {
+    let mut cursor = collection
+        .find(doc! { "user_id": user_id })
+        .sort(doc! { "created_at": -1 })
+        .await?;
+    let mut tokens = vec![];
+    while let Some(token) = cursor.try_next().await? {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+pub async fn count_user_tokens(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+) -> Result<u64, Error> {
+    collection
+        .count_documents(doc! { "user_id": user_id })
+        .await
+}
+
+pub async fn delete_token(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+    token_id: ObjectId,
+) -> Result<bool, Error> {
+    let result = collection
+        .delete_one(doc! { "_id": token_id, "user_id": user_id })
+        .await?;
+    Ok(result.deleted_count == 1)
+}
+
+pub async fn delete_user_tokens(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+) -> Result<u64, Error> {
+    let result = collection.delete_many(doc! { "user_id": user_id }).await?;
+    Ok(result.deleted_count)
+}
+
+// Note the token was used, at most once per cutoff so busy scripts don't cause
+// a write per request
+pub async fn touch_token(
+    collection: &Collection<AccessToken>,
+    token_id: ObjectId,
+    cutoff: &str,
+) -> Result<(), Error> {
+    collection
+        .update_one(
+            doc! {
+                "_id": token_id,
+                "$or": [
+                    { "last_used_at": { "$exists": false } },
+                    { "last_used_at": { "$lt": cutoff } },
+                ],
+            },
+            doc! { "$set": { "last_used_at": now_rfc3339() } },
+        )
+        .await?;
+    Ok(())
+}
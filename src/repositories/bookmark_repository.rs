@@ -0,0 +1,134 @@
+use crate::models::bookmark::{Bookmark, BookmarkFolder};
+use crate::pagination::Pagination;
+use crate::utils::helps::now_rfc3339;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    options::IndexOptions,
+    results::{DeleteResult, InsertOneResult, UpdateResult},
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<Bookmark>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "user_id": 1, "post_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+// Create the bookmark, or move it to `folder_id` if it already exists
+pub async fn upsert_bookmark(
+    collection: &Collection<Bookmark>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+    folder_id: Option<ObjectId>,
+) -> Result<UpdateResult, Error> {
+    let filter = doc! { "user_id": user_id, "post_id": post_id };
+    let update = doc! {
+        "$set": { "folder_id": folder_id },
+        "$setOnInsert": {
+            "created_at": now_rfc3339(),
+        },
+    };
+    collection.update_one(filter, update).upsert(true).await
+}
+
+pub async fn delete_bookmark(
+    collection: &Collection<Bookmark>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    let filter = doc! { "user_id": user_id, "post_id": post_id };
+    collection.delete_one(filter).await
+}
+
+pub async fn delete_bookmarks_of_post(
+    collection: &Collection<Bookmark>,
+    post_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    collection.delete_many(doc! { "post_id": post_id }).await
+}
+
+// A page of bookmarks and the cursor to the next one
+pub async fn get_bookmarks(
+    collection: &Collection<Bookmark>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<(Vec<Bookmark>, Option<String>), Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(filter))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut bookmarks: Vec<Bookmark> = Vec::new();
+    for doc in documents {
+        bookmarks.push(from_document(doc)?);
+    }
+    Ok((bookmarks, next_cursor))
+}
+
+pub async fn create_folder(
+    collection: &Collection<BookmarkFolder>,
+    new_folder: BookmarkFolder,
+) -> Result<InsertOneResult, Error> {
+    collection.insert_one(new_folder).await
+}
+
+pub async fn get_folder(
+    collection: &Collection<BookmarkFolder>,
+    user_id: ObjectId,
+    folder_id: ObjectId,
+) -> Result<Option<BookmarkFolder>, Error> {
+    collection
+        .find_one(doc! { "_id": folder_id, "user_id": user_id })
+        .await
+}
+
+pub async fn get_folders(
+    collection: &Collection<BookmarkFolder>,
+    user_id: ObjectId,
+) -> Result<Vec<BookmarkFolder>, Error> {
+    let mut cursor = collection
+        .find(doc! { "user_id": user_id })
+        .sort(doc! { "name": 1 })
+        .await?;
+    let mut folders = vec![];
+    while let Some(folder) = cursor.try_next().await? {
+        folders.push(folder);
+    }
+    Ok(folders)
+}
+
+pub async fn delete_folder(
+    collection: &Collection<BookmarkFolder>,
+    user_id: ObjectId,
+    folder_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    collection
+        .delete_one(doc! { "_id": folder_id, "user_id": user_id })
+        .await
+}
+
+// Bookmarks of a deleted folder become unfiled
+pub async fn unfile_bookmarks(
+    collection: &Collection<Bookmark>,
+    folder_id: ObjectId,
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_many(
+            doc! { "folder_id": folder_id },
+            doc! { "$set": { "folder_id": None::<ObjectId> } },
+        )
+        .await
+}
+
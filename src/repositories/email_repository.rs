@@ -0,0 +1,53 @@
+use crate::models::email::{EmailLog, EmailPurpose};
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::now_rfc3339;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::Error,
+    options::IndexOptions,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<EmailLog>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "user_id": 1, "purpose": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+// Record a send unless the last one is newer than `cooldown_cutoff` or `limit`
+// were sent since `window_cutoff`. Returns false when rate limited.
+pub async fn claim_send(
+    collection: &Collection<EmailLog>,
+    user_id: ObjectId,
+    purpose: EmailPurpose,
+    cooldown_cutoff: &str,
+    window_cutoff: &str,
+    limit: i32,
+) -> Result<bool, Error> {
+    let now = now_rfc3339();
+    // `sent_at` keeps the last `limit` sends, so the window is full when the
+    // oldest of a full list is inside it
+    let filter = doc! {
+        "user_id": user_id,
+        "purpose": purpose.as_str(),
+        "last_sent_at": { "$not": { "$gt": cooldown_cutoff } },
+        "$or": [
+            { format!("sent_at.{}", limit - 1): { "$exists": false } },
+            { "sent_at.0": { "$lte": window_cutoff } },
+        ],
+    };
+    let update = doc! {
+        "$set": { "last_sent_at": &now },
+        "$push": { "sent_at": { "$each": [&now], "$slice": -limit } },
+    };
+    // A throttled user's document doesn't match, and the upsert then collides
+    // with it on the unique index
+    match collection.update_one(filter, update).upsert(true).await {
+        Ok(_) => Ok(true),
+        Err(err) if is_duplicate_key(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,142 @@
+use crate::models::feed::FeedSources;
+use crate::models::post::{Audience, Post};
+use crate::pagination::Pagination;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    error::Error,
+    Collection,
+};
+
+// Feed entries of `sources` visible to `audience`, as documents of
+// `{ _id, post_id, reposted_by, created_at }` ordered by activity time.
+// Every entry is filtered before the page is cut, so pages come back full.
+pub async fn get_feed_entries(
+    collection: &Collection<Post>,
+    sources: &FeedSources,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Result<(Vec<Document>, Option<String>), Error> {
+    let pipeline = feed_pipeline(sources, audience, pagination);
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut entries: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        entries.push(doc);
+    }
+    Ok(pagination.page(entries))
+}
+
+fn feed_pipeline(
+    sources: &FeedSources,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Vec<Document> {
+    let not_muted = doc! {
+        "$or": [
+            { "author_id": sources.viewer_id },
+            { "tags": { "$nin": &sources.muted_tag_ids } },
+        ]
+    };
+    let filter = doc! {
+        "$and": [
+            { "$or": [
+                { "author_id": { "$in": &sources.author_ids } },
+                { "tags": { "$in": &sources.tag_ids } },
+            ] },
+            not_muted.clone(),
+        ]
+    };
+    vec![
+        doc! { "$match": audience.restrict(filter) },
+        doc! {
+            "$project": {
+                "post_id": "$_id",
+                "reposted_by": { "$literal": null },
+                "created_at": 1,
+            }
+        },
+        // Reposts are attributed to the reposter and ordered by the time of the repost
+        doc! {
+            "$unionWith": {
+                "coll": "reposts",
+                "pipeline": [
+                    { "$match": { "user_id": { "$in": &sources.author_ids } } },
+                    // Reposts carry no tags or visibility of their own; the
+                    // reposted post decides whether the entry is shown
+                    {
+                        "$lookup": {
+                            "from": "posts",
+                            "let": { "post_id": "$post_id" },
+                            "pipeline": [
+                                { "$match": { "$expr": { "$eq": ["$_id", "$$post_id"] } } },
+                                { "$match": audience.restrict(not_muted) },
+                                { "$project": { "_id": 1 } },
+                            ],
+                            "as": "post",
+                        }
+                    },
+                    { "$match": { "post": { "$ne": [] } } },
+                    {
+                        "$project": {
+                            "post_id": 1,
+                            "reposted_by": "$user_id",
+                            "created_at": 1,
+                        }
+                    },
+                ],
+            }
+        },
+        doc! { "$match": pagination.match_doc(doc! {}) },
+        doc! { "$sort": pagination.sort_doc() },
+        doc! { "$limit": pagination.fetch_limit() },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pagination::PaginationQuery;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn filters_posts_and_reposts_before_the_page_is_cut() {
+        let viewer_id = ObjectId::new();
+        let sources = FeedSources {
+            viewer_id,
+            author_ids: vec![ObjectId::new()],
+            tag_ids: vec![],
+            muted_tag_ids: vec![ObjectId::new()],
+        };
+        let audience = Audience { viewer_id, following_ids: vec![], close_friend_of_ids: vec![] };
+        let pagination = PaginationQuery::default().resolve(&["created_at"]).unwrap();
+        let pipeline = feed_pipeline(&sources, &audience, &pagination);
+
+        let stage = |name: &str| pipeline.iter().position(|stage| stage.contains_key(name));
+        let limit = stage("$limit").unwrap();
+        assert!(stage("$sort").unwrap() < limit);
+        assert_eq!(stage("$match"), Some(0));
+
+        let reposts = pipeline[stage("$unionWith").unwrap()]
+            .get_document("$unionWith")
+            .unwrap()
+            .get_array("pipeline")
+            .unwrap();
+        let lookup = reposts
+            .iter()
+            .filter_map(|stage| stage.as_document()?.get_document("$lookup").ok())
+            .next()
+            .expect("reposts are checked against their post");
+        let post_filter = lookup.get_array("pipeline").unwrap()[1]
+            .as_document()
+            .unwrap()
+            .get_document("$match")
+            .unwrap();
+        let muted = doc! {
+            "$or": [
+                { "author_id": viewer_id },
+                { "tags": { "$nin": &sources.muted_tag_ids } },
+            ]
+        };
+        assert_eq!(post_filter, &audience.restrict(muted));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::models::follow::Follow;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_document, DateTime},
+    error::Error,
+    options::IndexOptions,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<Follow>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "follower_id": 1, "following_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+// Returns true when the follow didn't exist yet
+pub async fn follow(
+    collection: &Collection<Follow>,
+    follower_id: ObjectId,
+    following_id: ObjectId,
+) -> Result<bool, Error> {
+    let new_follow = Follow {
+        id: None,
+        follower_id,
+        following_id,
+        created_at: DateTime::now(),
+    };
+    let filter = doc! { "follower_id": follower_id, "following_id": following_id };
+    let result = collection
+        .update_one(filter, doc! { "$setOnInsert": to_document(&new_follow)? })
+        .upsert(true)
+        .await?;
+    Ok(result.upserted_id.is_some())
+}
+
+// Returns true when a follow was removed
+pub async fn unfollow(
+    collection: &Collection<Follow>,
+    follower_id: ObjectId,
+    following_id: ObjectId,
+) -> Result<bool, Error> {
+    let filter = doc! { "follower_id": follower_id, "following_id": following_id };
+    let result = collection.delete_one(filter).await?;
+    Ok(result.deleted_count == 1)
+}
+
+pub async fn get_following_ids(
+    collection: &Collection<Follow>,
+    follower_id: ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    let mut cursor = collection.find(doc! { "follower_id": follower_id }).await?;
+    let mut ids = vec![];
+    while let Some(follow) = cursor.try_next().await? {
+        ids.push(follow.following_id);
+    }
+    Ok(ids)
+}
+
+pub async fn get_follower_ids(
+    collection: &Collection<Follow>,
+    following_id: ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    let mut cursor = collection.find(doc! { "following_id": following_id }).await?;
+    let mut ids = vec![];
+    while let Some(follow) = cursor.try_next().await? {
+        ids.push(follow.follower_id);
+    }
+    Ok(ids)
+}
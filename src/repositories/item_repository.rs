@@ -1,7 +1,10 @@
-use crate::models::item::Item;
+use crate::models::item::{Item, ItemRequest};
+use crate::pagination::{Paginated, Pagination};
+use crate::utils::helps::{not_deleted, now_rfc3339};
 use futures::stream::TryStreamExt;
 use mongodb::{
-    bson::doc,
+    bson::{doc, from_document, Document},
+    options::ReturnDocument,
     results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection,
 };
@@ -22,29 +25,66 @@ pub async fn get_item_by_id(
     item_id: &str,
 ) -> Result<Option<Item>, Error> {
     let obj_id = ObjectId::parse_str(item_id).unwrap();
-    let filter = doc! { "_id": obj_id };
+    let filter = not_deleted(doc! { "_id": obj_id });
     let item = collection.find_one(filter).await?;
     Ok(item)
 }
 
-// Get all items
-pub async fn get_all_items(collection: &Collection<Item>) -> Result<Vec<Item>, Error> {
-    let mut cursor = collection.find(doc! {}).await?;
+// Get a page of items matching the filter
+pub async fn get_all_items(
+    collection: &Collection<Item>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Item>, Error> {
+    get_paginated_items(collection, not_deleted(filter), pagination).await
+}
+
+// Get a page of the items in the owner's trash
+pub async fn get_deleted_items(
+    collection: &Collection<Item>,
+    user_id: &str,
+    pagination: &Pagination,
+) -> Result<Paginated<Item>, Error> {
+    let filter = doc! { "user_id": user_id, "deleted_at": { "$ne": null } };
+    get_paginated_items(collection, filter, pagination).await
+}
+
+async fn get_paginated_items(
+    collection: &Collection<Item>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Item>, Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(filter))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
     let mut items: Vec<Item> = Vec::new();
-    while let Some(item) = cursor.try_next().await? {
-        items.push(item);
+    for doc in documents {
+        items.push(from_document(doc)?);
     }
-    Ok(items)
+    Ok(Paginated {
+        data: items,
+        next_cursor,
+        limit: pagination.limit,
+    })
 }
 
 // Update an item
 pub async fn update_item(
     collection: &Collection<Item>,
     item_id: &str,
-    updated_item: Item,
+    updated_item: ItemRequest,
 ) -> Result<UpdateResult, Error> {
     let obj_id = ObjectId::parse_str(item_id).unwrap();
-    let filter = doc! { "_id": obj_id };
+    let filter = not_deleted(doc! { "_id": obj_id });
     let update = doc! {
         "$set": {
             "name": updated_item.name,
@@ -57,13 +97,39 @@ pub async fn update_item(
     Ok(result)
 }
 
-// Delete an item
+// Move an item to the trash
 pub async fn delete_item(
     collection: &Collection<Item>,
     item_id: &str,
-) -> Result<DeleteResult, Error> {
+) -> Result<UpdateResult, Error> {
     let obj_id = ObjectId::parse_str(item_id).unwrap();
-    let filter = doc! { "_id": obj_id };
-    let result = collection.delete_one(filter).await?;
+    let filter = not_deleted(doc! { "_id": obj_id });
+    let update = doc! { "$set": { "deleted_at": now_rfc3339() } };
+    let result = collection.update_one(filter, update).await?;
     Ok(result)
 }
+
+// Take an item out of its owner's trash
+pub async fn restore_item(
+    collection: &Collection<Item>,
+    item_id: ObjectId,
+    user_id: &str,
+) -> Result<Option<Item>, Error> {
+    collection
+        .find_one_and_update(
+            doc! { "_id": item_id, "user_id": user_id, "deleted_at": { "$ne": null } },
+            doc! { "$unset": { "deleted_at": "" } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Permanently remove the items deleted before `cutoff`
+pub async fn purge_deleted_items(
+    collection: &Collection<Item>,
+    cutoff: &str,
+) -> Result<DeleteResult, Error> {
+    collection
+        .delete_many(doc! { "deleted_at": { "$ne": null, "$lt": cutoff } })
+        .await
+}
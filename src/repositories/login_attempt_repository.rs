@@ -0,0 +1,57 @@
+use crate::models::login::LoginAttempt;
+use crate::pagination::{Paginated, Pagination};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<LoginAttempt>) -> Result<(), Error> {
+    for key in ["user_id", "ip"] {
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { key: 1, "created_at": -1 })
+                    .build(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn record_attempt(
+    collection: &Collection<LoginAttempt>,
+    attempt: LoginAttempt,
+) -> Result<(), Error> {
+    collection.insert_one(attempt).await?;
+    Ok(())
+}
+
+pub async fn get_attempts(
+    collection: &Collection<LoginAttempt>,
+    user_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<LoginAttempt>, Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(doc! { "user_id": user_id }))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut attempts: Vec<LoginAttempt> = Vec::new();
+    for doc in documents {
+        attempts.push(from_document(doc)?);
+    }
+    Ok(Paginated {
+        data: attempts,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
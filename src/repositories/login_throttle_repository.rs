@@ -0,0 +1,90 @@
+use crate::models::login::LoginThrottle;
+use crate::utils::helps::now_rfc3339;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    error::Error,
+    options::{IndexOptions, ReturnDocument},
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<LoginThrottle>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "key": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+pub async fn get_throttles(
+    collection: &Collection<LoginThrottle>,
+    keys: &[String],
+) -> Result<Vec<LoginThrottle>, Error> {
+    let mut cursor = collection.find(doc! { "key": { "$in": keys } }).await?;
+    let mut throttles = vec![];
+    while let Some(throttle) = cursor.try_next().await? {
+        throttles.push(throttle);
+    }
+    Ok(throttles)
+}
+
+// Count a failure, starting over when the previous one is older than
+// `window_cutoff`, and return the updated count
+pub async fn record_failure(
+    collection: &Collection<LoginThrottle>,
+    key: &str,
+    window_cutoff: &str,
+) -> Result<LoginThrottle, Error> {
+    let recent = doc! { "$gt": [{ "$ifNull": ["$last_failure_at", ""] }, window_cutoff] };
+    let update = vec![doc! {
+        "$set": {
+            "key": key,
+            "failures": { "$cond": [recent, { "$add": [{ "$ifNull": ["$failures", 0] }, 1] }, 1] },
+            "last_failure_at": now_rfc3339(),
+        }
+    }];
+    collection
+        .find_one_and_update(doc! { "key": key }, update)
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await?
+        .ok_or_else(|| Error::custom("Upserted login throttle not returned"))
+}
+
+pub async fn set_block(
+    collection: &Collection<LoginThrottle>,
+    key: &str,
+    blocked_until: &str,
+    locked_until: Option<&str>,
+) -> Result<(), Error> {
+    let mut set = doc! { "blocked_until": blocked_until };
+    if let Some(locked_until) = locked_until {
+        set.insert("locked_until", locked_until);
+    }
+    collection
+        .update_one(doc! { "key": key }, doc! { "$set": set })
+        .await?;
+    Ok(())
+}
+
+pub async fn clear_throttle(
+    collection: &Collection<LoginThrottle>,
+    key: &str,
+) -> Result<(), Error> {
+    collection.delete_one(doc! { "key": key }).await?;
+    Ok(())
+}
+
+// Lift the lockout an unlock link was sent for. Returns false when it has been
+// lifted already or replaced by a newer one.
+pub async fn unlock(
+    collection: &Collection<LoginThrottle>,
+    key: &str,
+    locked_until: &str,
+) -> Result<bool, Error> {
+    let result = collection
+        .delete_one(doc! { "key": key, "locked_until": locked_until })
+        .await?;
+    Ok(result.deleted_count == 1)
+}
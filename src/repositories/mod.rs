@@ -1,4 +1,20 @@
 pub mod user_repository;
 pub mod item_repository;
 pub mod post_repository;
-pub mod tag_repository;
\ No newline at end of file
+pub mod tag_repository;
+pub mod search_repository;
+pub mod follow_repository;
+pub mod repost_repository;
+pub mod bookmark_repository;
+pub mod feed_repository;
+pub mod notification_repository;
+pub mod revision_repository;
+pub mod trending_repository;
+pub mod tag_subscription_repository;
+pub mod email_repository;
+pub mod password_reset_repository;
+pub mod login_attempt_repository;
+pub mod login_throttle_repository;
+pub mod passkey_repository;
+pub mod webauthn_challenge_repository;
+pub mod access_token_repository;
\ No newline at end of file
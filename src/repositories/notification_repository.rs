@@ -0,0 +1,59 @@
+use crate::models::notification::Notification;
+use crate::pagination::{Paginated, Pagination};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    results::UpdateResult,
+    Collection,
+};
+
+pub async fn create_notifications(
+    collection: &Collection<Notification>,
+    notifications: Vec<Notification>,
+) -> Result<(), Error> {
+    if !notifications.is_empty() {
+        collection.insert_many(notifications).await?;
+    }
+    Ok(())
+}
+
+pub async fn get_notifications(
+    collection: &Collection<Notification>,
+    user_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<Notification>, Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(doc! { "user_id": user_id }))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut notifications: Vec<Notification> = Vec::new();
+    for doc in documents {
+        notifications.push(from_document(doc)?);
+    }
+    Ok(Paginated {
+        data: notifications,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
+
+pub async fn mark_all_read(
+    collection: &Collection<Notification>,
+    user_id: ObjectId,
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_many(
+            doc! { "user_id": user_id, "read": false },
+            doc! { "$set": { "read": true } },
+        )
+        .await
+}
@@ -0,0 +1,99 @@
+use crate::models::passkey::Passkey;
+use crate::utils::helps::now_rfc3339;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::Error,
+    options::{IndexOptions, ReturnDocument},
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<Passkey>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "credential_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    collection
+        .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+        .await?;
+    Ok(())
+}
+
+pub async fn create_passkey(
+    collection: &Collection<Passkey>,
+    passkey: Passkey,
+) -> Result<ObjectId, Error> {
+    let result = collection.insert_one(passkey).await?;
+    result
+        .inserted_id
+        .as_object_id()
+        .ok_or_else(|| Error::custom("Inserted passkey has no ObjectId"))
+}
+
+pub async fn find_by_credential_id(
+    collection: &Collection<Passkey>,
+    credential_id: &str,
+) -> Result<Option<Passkey>, Error> {
+    collection
+        .find_one(doc! { "credential_id": credential_id })
+        .await
+}
+
+pub async fn get_user_passkeys(
+    collection: &Collection<Passkey>,
+    user_id: ObjectId,
+) -> Result<Vec<Passkey>, Error> {
+    let mut cursor = collection
+        .find(doc! { "user_id": user_id })
+        .sort(doc! { "created_at": 1 })
+        .await?;
+    let mut passkeys = vec![];
+    while let Some(passkey) = cursor.try_next().await? {
+        passkeys.push(passkey);
+    }
+    Ok(passkeys)
+}
+
+pub async fn rename_passkey(
+    collection: &Collection<Passkey>,
+    user_id: ObjectId,
+    passkey_id: ObjectId,
+    name: &str,
+) -> Result<Option<Passkey>, Error> {
+    collection
+        .find_one_and_update(
+            doc! { "_id": passkey_id, "user_id": user_id },
+            doc! { "$set": { "name": name } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+pub async fn delete_passkey(
+    collection: &Collection<Passkey>,
+    user_id: ObjectId,
+    passkey_id: ObjectId,
+) -> Result<bool, Error> {
+    let result = collection
+        .delete_one(doc! { "_id": passkey_id, "user_id": user_id })
+        .await?;
+    Ok(result.deleted_count == 1)
+}
+
+// Store the counter from a login, unless another login got there first with a
+// higher one
+pub async fn record_use(
+    collection: &Collection<Passkey>,
+    passkey_id: ObjectId,
+    previous_sign_count: i64,
+    sign_count: i64,
+) -> Result<bool, Error> {
+    let result = collection
+        .update_one(
+            doc! { "_id": passkey_id, "sign_count": previous_sign_count },
+            doc! { "$set": { "sign_count": sign_count, "last_used_at": now_rfc3339() } },
+        )
+        .await?;
+    Ok(result.matched_count == 1)
+}
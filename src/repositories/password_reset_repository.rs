@@ -0,0 +1,62 @@
+use crate::models::password::PasswordReset;
+use crate::utils::helps::now_rfc3339;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    error::Error,
+    options::IndexOptions,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<PasswordReset>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "token_hash": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    collection
+        .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+        .await?;
+    Ok(())
+}
+
+// Replace the user's pending resets, so only the latest link works
+pub async fn create_reset(
+    collection: &Collection<PasswordReset>,
+    reset: PasswordReset,
+) -> Result<(), Error> {
+    delete_resets(collection, reset.user_id).await?;
+    collection.insert_one(reset).await?;
+    Ok(())
+}
+
+pub async fn delete_resets(collection: &Collection<PasswordReset>, user_id: ObjectId) -> Result<(), Error> {
+    collection.delete_many(doc! { "user_id": user_id }).await?;
+    Ok(())
+}
+
+fn usable(token_hash: &str, now: &str) -> Document {
+    doc! {
+        "token_hash": token_hash,
+        "used_at": null,
+        "expires_at": { "$gt": now },
+    }
+}
+
+// An unused, unexpired reset, without using it up
+pub async fn find_reset(
+    collection: &Collection<PasswordReset>,
+    token_hash: &str,
+) -> Result<Option<PasswordReset>, Error> {
+    collection.find_one(usable(token_hash, &now_rfc3339())).await
+}
+
+// Mark an unused, unexpired reset as used, so a token works only once
+pub async fn consume_reset(
+    collection: &Collection<PasswordReset>,
+    token_hash: &str,
+) -> Result<Option<PasswordReset>, Error> {
+    let now = now_rfc3339();
+    collection
+        .find_one_and_update(usable(token_hash, &now), doc! { "$set": { "used_at": &now } })
+        .await
+}
@@ -1,15 +1,29 @@
-use crate::post::{PostRequest, PostResponse};
+use crate::pagination::{Paginated, Pagination};
+use crate::utils::helps::{not_deleted, now_rfc3339};
+use crate::post::{Audience, PostEntity, PostRequest, PostResponse, PostStatus, THREAD_MAX_DEPTH};
 use crate::{models::post::Post, post::Media};
+use crate::user::public_user_projection;
 use futures::stream::TryStreamExt;
+use std::collections::HashMap;
 use futures::StreamExt;
-use mongodb::bson::{from_bson, Bson};
+use mongodb::bson::{from_bson, to_bson, Bson, Document};
 use mongodb::{bson::oid::ObjectId, error::Error};
 use mongodb::{
     bson::{doc, to_document},
-    results::{DeleteResult, InsertOneResult, UpdateResult},
-    Collection,
+    options::ReturnDocument,
+    results::{InsertOneResult, UpdateResult},
+    Collection, IndexModel,
 };
 
+// Lets the scheduler find due posts without scanning the collection
+pub async fn ensure_indexes(collection: &Collection<Post>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "status": 1, "publish_at": 1 })
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
 pub async fn create_post(
     collection: &Collection<Post>,
     new_post: Post,
@@ -18,24 +32,9 @@ pub async fn create_post(
     Ok(result)
 }
 
-pub async fn get_post_by_id(
-    collection: &Collection<Post>,
-    post_id: &str,
-) -> Result<Option<PostResponse>, Error> {
-    let obj_id = match ObjectId::parse_str(post_id) {
-        Ok(id) => id,
-        Err(_) => return Err(Error::custom("Invalid Post ID")),
-    };
-    
-    // let filter = doc! { "_id": obj_id };
-    // let post = collection.find_one(filter).await?;
-    // Ok(Post::to_post(post).await)
-    let pipeline = vec![
-        // Step 1: Match the specific post by its ID
-        doc! {
-            "$match": { "_id": obj_id }
-        },
-        // Step 2: Lookup user details from the users collection
+// Join the author and merge it into the post; posts without an author are dropped
+fn author_stages() -> Vec<Document> {
+    vec![
         doc! {
             "$lookup": {
                 "from": "users",                // Collection to join
@@ -44,11 +43,15 @@ pub async fn get_post_by_id(
                 "as": "user"                    // Output array field
             }
         },
-        // Step 3: Unwind to merge user details into the document
         doc! {
             "$unwind": "$user"
         },
-        // Step 4: Lookup tags details from the tags collection
+    ]
+}
+
+// Join the tags and shape the document into a `PostResponse`
+fn response_stages() -> Vec<Document> {
+    vec![
         doc! {
             "$lookup": {
                 "from": "tags",                 // Collection to join
@@ -57,50 +60,86 @@ pub async fn get_post_by_id(
                 "as": "tag_details"             // Output array field
             }
         },
-        // Step 5: Project to format the output document
         doc! {
             "$project": {
                 "_id": 1,
                 "title": 1,
                 "content": 1,
                 "media": 1,
-                "author": {
-                    "_id": "$user._id",
-                    "username": "$user.username",
-                    "email": "$user.email",
-                    "avatar": "$user.avatar",
-                    "bio": "$user.bio",
-                    "follower_count": "$user.follower_count",
-                    "following_count": "$user.following_count",
-                    "is_verified": "$user.is_verified",
-                    "last_login": "$user.last_login",
-                    "status": "$user.status",
-                    "created_at": "$user.created_at",
-                    "updated_at": "$user.updated_at"
-                },
-                // Mapping tags to have only the necessary fields
+                // Only what the author's privacy settings let others see
+                "author": public_user_projection("$user"),
+                // Mapping tags to have only the necessary fields, deleted tags are left out
                 "tags": {
                     "$map": {
-                        "input": "$tag_details",
+                        "input": {
+                            "$filter": {
+                                "input": "$tag_details",
+                                "as": "tag",
+                                "cond": { "$not": ["$$tag.deleted_at"] }
+                            }
+                        },
                         "as": "tag",
                         "in": {
                             "_id": "$$tag._id",
-                            "name": "$$tag.name"
+                            "name": "$$tag.name",
+                            "slug": "$$tag.slug"
                         }
                     }
                 },
                 "type": 1,
+                "reply_to": 1,
+                "quoted_post_id": 1,
+                "replies_count": 1,
+                "reposts_count": 1,
+                "entities": 1,
+                "visibility": 1,
+                "status": 1,
+                "publish_at": 1,
+                "edited": 1,
+                "deleted_at": 1,
                 "likes_count": 1,
                 "comments_count": 1,
                 "created_at": 1,
                 "updated_at": 1,
             }
         },
-        // Optional: Limit the result to 1, though $match ensures only one document
-        doc! {
-            "$limit": 1
-        },
-    ];
+    ]
+}
+
+pub async fn find_post_by_id(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+) -> Result<Option<Post>, Error> {
+    collection.find_one(not_deleted(doc! { "_id": post_id })).await
+}
+
+// The post if the audience is allowed to see it
+pub async fn find_visible_post(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+    audience: &Audience,
+) -> Result<Option<Post>, Error> {
+    collection
+        .find_one(audience.restrict(doc! { "_id": post_id }))
+        .await
+}
+
+pub async fn get_post_by_id(
+    collection: &Collection<Post>,
+    post_id: &str,
+    audience: &Audience,
+) -> Result<Option<PostResponse>, Error> {
+    let obj_id = match ObjectId::parse_str(post_id) {
+        Ok(id) => id,
+        Err(_) => return Err(Error::custom("Invalid Post ID")),
+    };
+
+    let mut pipeline = vec![doc! {
+        "$match": audience.restrict(doc! { "_id": obj_id })
+    }];
+    pipeline.extend(author_stages());
+    pipeline.extend(response_stages());
+    pipeline.push(doc! { "$limit": 1 });
 
     let mut cursor = collection.aggregate(pipeline).await?;
     if let Some(doc) = cursor.next().await {
@@ -110,86 +149,214 @@ pub async fn get_post_by_id(
     Ok(None)
 }
 
-pub async fn get_all_posts(collection: &Collection<Post>) -> Result<Vec<PostResponse>, Error> {
-    let pipeline = vec![
+// Posts for the given ids that the audience may see, oldest first
+pub async fn get_posts_by_ids(
+    collection: &Collection<Post>,
+    post_ids: &[ObjectId],
+    audience: &Audience,
+) -> Result<Vec<PostResponse>, Error> {
+    let mut pipeline = vec![
+        doc! { "$match": audience.restrict(doc! { "_id": { "$in": post_ids } }) },
+        doc! { "$sort": { "created_at": 1, "_id": 1 } },
+    ];
+    pipeline.extend(author_stages());
+    pipeline.extend(response_stages());
+
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut posts: Vec<PostResponse> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        posts.push(from_bson(Bson::Document(doc))?);
+    }
+    Ok(posts)
+}
+
+pub async fn get_all_posts(
+    collection: &Collection<Post>,
+    filter: Document,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    get_paginated_posts(collection, audience.restrict(filter), pagination).await
+}
+
+// Drafts and scheduled posts of the author
+pub async fn get_drafts(
+    collection: &Collection<Post>,
+    author_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    let filter = not_deleted(doc! {
+        "author_id": author_id,
+        "status": { "$in": ["draft", "scheduled"] },
+    });
+    get_paginated_posts(collection, filter, pagination).await
+}
+
+// Posts in the author's trash
+pub async fn get_deleted_posts(
+    collection: &Collection<Post>,
+    author_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    let filter = doc! { "author_id": author_id, "deleted_at": { "$ne": null } };
+    get_paginated_posts(collection, filter, pagination).await
+}
+
+async fn get_paginated_posts(
+    collection: &Collection<Post>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    // Filtering, visibility and keyset pagination run before the joins
+    let mut pipeline = vec![
         doc! {
-            "$lookup": {
-                "from": "users",           // Collection to join
-                "localField": "author_id", // Field from the posts collection
-                "foreignField": "_id",     // Field from the users collection
-                "as": "user",              // Output array field
-            }
+            "$match": pagination.match_doc(filter)
         },
         doc! {
-            "$unwind": "$user" // Unwind the user array to merge it into a single object
+            "$sort": pagination.sort_doc()
         },
+    ];
+    pipeline.extend(author_stages());
+    // Limit after the unwind so posts without an author don't shorten the page
+    pipeline.push(doc! { "$limit": pagination.fetch_limit() });
+    pipeline.extend(response_stages());
+    // $lookup keeps the order, but sort again to be explicit
+    pipeline.push(doc! { "$sort": pagination.sort_doc() });
+
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut posts: Vec<PostResponse> = Vec::new();
+    for doc in documents {
+        let post: PostResponse = from_bson(Bson::Document(doc))?;
+        posts.push(post);
+    }
+    Ok(Paginated {
+        data: posts,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
+
+// Ids of the posts above `post_id` in its thread, from the root down to the parent
+pub async fn get_ancestor_ids(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    let pipeline = vec![
+        doc! { "$match": { "_id": post_id } },
         doc! {
-            "$lookup": {
-                "from": "tags",
-                "localField": "tags",
-                "foreignField": "_id",
-                "as": "tag_details",
+            "$graphLookup": {
+                "from": "posts",
+                "startWith": "$reply_to",
+                "connectFromField": "reply_to",
+                "connectToField": "_id",
+                "as": "ancestors",
+                "maxDepth": THREAD_MAX_DEPTH,
+                "depthField": "depth",
             }
         },
+        doc! { "$unwind": "$ancestors" },
+        // The furthest ancestor is the root of the conversation
+        doc! { "$sort": { "ancestors.depth": -1 } },
+        doc! { "$project": { "_id": "$ancestors._id" } },
+    ];
+
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut ids = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+// Ids of every reply below `post_id`, at any depth
+pub async fn get_descendant_ids(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    let pipeline = vec![
+        doc! { "$match": { "_id": post_id } },
         doc! {
-            "$project": {
-                "_id": 1,
-                "title": 1,
-                "content": 1,
-                "media": 1,
-                "author": {
-                    "_id": "$user._id",
-                    "username": "$user.username",
-                    "email": "$user.email",
-                    "avatar": "$user.avatar",
-                    "bio": "$user.bio",
-                    "follower_count": "$user.follower_count",
-                    "following_count": "$user.following_count",
-                    "is_verified": "$user.is_verified",
-                    "last_login": "$user.last_login",
-                    "status": "$user.status",
-                    "created_at": "$user.created_at",
-                    "updated_at": "$user.created_at"
-                },
-                "tags": {
-                    "$map": {
-                        "input": "$tag_details",
-                        "as": "tag",
-                        "in": {
-                            "_id": "$$tag._id",
-                            "name": "$$tag.name"
-                        }
-                    }
-                },
-                "type": 1,
-                "likes_count": 1,
-                "comments_count": 1,
-                "created_at": 1,
-                "updated_at": 1,
+            "$graphLookup": {
+                "from": "posts",
+                "startWith": "$_id",
+                "connectFromField": "_id",
+                "connectToField": "reply_to",
+                "as": "descendants",
+                "maxDepth": THREAD_MAX_DEPTH,
             }
         },
-        // Pagination: Sort by creation date and limit results
-        doc! {
-            "$sort": { "created_at": -1 } // Sort by creation date descending
-        },
-        doc! {
-            "$limit": 10 // Limit to 10 documents per page
-        },
+        doc! { "$unwind": "$descendants" },
+        doc! { "$project": { "_id": "$descendants._id" } },
     ];
 
     let mut cursor = collection.aggregate(pipeline).await?;
-    let mut posts: Vec<PostResponse> = Vec::new();
+    let mut ids = vec![];
     while let Some(doc) = cursor.try_next().await? {
-        let post: PostResponse = from_bson(Bson::Document(doc))?;
-        posts.push(post);
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
     }
-    Ok(posts)
+    Ok(ids)
+}
+
+// Adjust one of the post's counters, e.g. `replies_count` or `reposts_count`
+pub async fn increment_count(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+    field: &str,
+    amount: i32,
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_one(doc! { "_id": post_id }, doc! { "$inc": { field: amount } })
+        .await
+}
+
+// Atomically move one unpublished post matching `filter` to published and return it.
+// The status check in the filter makes sure a post is only ever published once,
+// even when several server instances race for it.
+pub async fn publish_post(
+    collection: &Collection<Post>,
+    filter: Document,
+) -> Result<Option<Post>, Error> {
+    let mut filter = not_deleted(filter);
+    filter.insert("status", doc! { "$in": ["draft", "scheduled"] });
+    // The post is dated by its publication so it shows up at the top of feeds
+    let now = now_rfc3339();
+    collection
+        .find_one_and_update(
+            filter,
+            doc! {
+                "$set": { "status": "published", "created_at": &now, "updated_at": &now },
+                "$unset": { "publish_at": "" },
+            },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Scheduled posts whose publish time has passed
+pub fn due_posts_filter() -> Document {
+    not_deleted(doc! {
+        "status": "scheduled",
+        "publish_at": { "$lte": now_rfc3339() },
+    })
 }
 
 pub async fn update_post(
     collection: &Collection<Post>,
     post_id: &str,
     updated_post: PostRequest,
+    entities: &[PostEntity],
+    schedule: Option<(PostStatus, Option<String>)>,
+    edited: bool,
 ) -> Result<UpdateResult, Error> {
     let obj_id = match ObjectId::parse_str(post_id) {
         Ok(id) => id,
@@ -205,27 +372,180 @@ pub async fn update_post(
         })?)
     }
 
-    let filter = doc! { "_id": obj_id };
-    let update = doc! {
-        "$set": {
-            "content": updated_post.content,
-            "media": updated_media,
-            "tags": updated_post.tags,
-        }
+    let filter = not_deleted(doc! { "_id": obj_id });
+    let mut set = doc! {
+        "content": updated_post.content,
+        "media": updated_media,
+        "tags": updated_post.tags,
+        "entities": to_bson(entities)?,
+        "updated_at": now_rfc3339(),
     };
+    if let Some(visibility) = updated_post.visibility {
+        set.insert("visibility", to_bson(&visibility)?);
+    }
+    // Only unpublished posts can be rescheduled
+    if let Some((status, publish_at)) = schedule {
+        set.insert("status", to_bson(&status)?);
+        set.insert("publish_at", to_bson(&publish_at)?);
+    }
+    if edited {
+        set.insert("edited", true);
+    }
+    let update = doc! { "$set": set };
     let result = collection.update_one(filter, update).await?;
     Ok(result)
 }
 
+// Move the post to the trash, it is purged after the retention window
 pub async fn delete_post(
     collection: &Collection<Post>,
     post_id: &str,
-) -> Result<DeleteResult, Error> {
+) -> Result<UpdateResult, Error> {
     let obj_id = match ObjectId::parse_str(post_id) {
         Ok(id) => id,
         Err(_) => return Err(Error::custom("Invalid Post ID")),
     };
-    let filter = doc! { "_id": obj_id };
-    let result = collection.delete_one(filter).await?;
+    let filter = not_deleted(doc! { "_id": obj_id });
+    let update = doc! { "$set": { "deleted_at": now_rfc3339() } };
+    let result = collection.update_one(filter, update).await?;
     Ok(result)
 }
+
+// Take the post out of the author's trash
+pub async fn restore_post(
+    collection: &Collection<Post>,
+    post_id: ObjectId,
+    author_id: ObjectId,
+) -> Result<Option<Post>, Error> {
+    collection
+        .find_one_and_update(
+            doc! { "_id": post_id, "author_id": author_id, "deleted_at": { "$ne": null } },
+            doc! { "$unset": { "deleted_at": "" } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Permanently remove the posts deleted before `cutoff`, returns their ids
+pub async fn purge_deleted_posts(
+    collection: &Collection<Post>,
+    cutoff: &str,
+) -> Result<Vec<ObjectId>, Error> {
+    let filter = doc! { "deleted_at": { "$ne": null, "$lt": cutoff } };
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(filter)
+        .projection(doc! { "_id": 1 })
+        .await?;
+    let mut ids = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
+    }
+    if !ids.is_empty() {
+        collection
+            .delete_many(doc! { "_id": { "$in": &ids } })
+            .await?;
+    }
+    Ok(ids)
+}
+
+// Replace the tags in `from` with `to` in every post, or drop them when `to` is None.
+// Hashtag entities linked to those tags are relinked the same way. Each post is
+// rewritten by a single update, so no post is ever seen half migrated.
+pub async fn replace_tag_references(
+    collection: &Collection<Post>,
+    from: &[ObjectId],
+    to: Option<ObjectId>,
+) -> Result<UpdateResult, Error> {
+    let from_hex: Vec<String> = from.iter().map(|id| id.to_hex()).collect();
+    let remaining = doc! { "$setDifference": ["$tags", from] };
+    let (tags, target_id) = match to {
+        Some(to) => (
+            Bson::Document(doc! { "$setUnion": [remaining, [to]] }),
+            Bson::String(to.to_hex()),
+        ),
+        None => (Bson::Document(remaining), Bson::Null),
+    };
+    let update = vec![doc! {
+        "$set": {
+            "tags": tags,
+            "entities": {
+                "$map": {
+                    "input": { "$ifNull": ["$entities", []] },
+                    "as": "entity",
+                    "in": {
+                        "$cond": [
+                            { "$in": ["$$entity.target_id", &from_hex] },
+                            { "$mergeObjects": ["$$entity", { "target_id": target_id }] },
+                            "$$entity"
+                        ]
+                    }
+                }
+            }
+        }
+    }];
+    collection
+        .update_many(doc! { "tags": { "$in": from } }, update)
+        .await
+}
+
+// Number of published, live posts carrying each tag; None counts every tag
+pub async fn count_tag_usage(
+    collection: &Collection<Post>,
+    tag_ids: Option<&[ObjectId]>,
+) -> Result<HashMap<ObjectId, i64>, Error> {
+    let mut filter = not_deleted(doc! { "status": { "$in": [null, "published"] } });
+    if let Some(tag_ids) = tag_ids {
+        filter.insert("tags", doc! { "$in": tag_ids });
+    }
+    let mut pipeline = vec![doc! { "$match": filter }, doc! { "$unwind": "$tags" }];
+    if let Some(tag_ids) = tag_ids {
+        pipeline.push(doc! { "$match": { "tags": { "$in": tag_ids } } });
+    }
+    pipeline.push(doc! { "$group": { "_id": "$tags", "count": { "$sum": 1 } } });
+
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut counts = HashMap::new();
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            let count = doc.get_i32("count").map(i64::from).or_else(|_| doc.get_i64("count"));
+            counts.insert(id, count.unwrap_or_default());
+        }
+    }
+    Ok(counts)
+}
+
+// How often the author used each tag, drafts included, for personal suggestions
+pub async fn count_author_tag_usage(
+    collection: &Collection<Post>,
+    author_id: ObjectId,
+) -> Result<HashMap<ObjectId, i64>, Error> {
+    let pipeline = vec![
+        doc! { "$match": not_deleted(doc! { "author_id": author_id }) },
+        doc! { "$unwind": "$tags" },
+        doc! { "$group": { "_id": "$tags", "count": { "$sum": 1 } } },
+    ];
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut counts = HashMap::new();
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            let count = doc.get_i32("count").map(i64::from).or_else(|_| doc.get_i64("count"));
+            counts.insert(id, count.unwrap_or_default());
+        }
+    }
+    Ok(counts)
+}
+
+// Live posts with the given ids, in no particular order
+pub async fn find_posts_by_ids(
+    collection: &Collection<Post>,
+    post_ids: &[ObjectId],
+) -> Result<Vec<Post>, Error> {
+    collection
+        .find(not_deleted(doc! { "_id": { "$in": post_ids } }))
+        .await?
+        .try_collect()
+        .await
+}
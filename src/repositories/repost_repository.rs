@@ -0,0 +1,55 @@
+use crate::models::repost::Repost;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_document, DateTime},
+    error::Error,
+    options::IndexOptions,
+    results::DeleteResult,
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<Repost>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "user_id": 1, "post_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+// Returns true when the repost didn't exist yet
+pub async fn create_repost(
+    collection: &Collection<Repost>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<bool, Error> {
+    let new_repost = Repost {
+        id: None,
+        user_id,
+        post_id,
+        created_at: DateTime::now(),
+    };
+    let filter = doc! { "user_id": user_id, "post_id": post_id };
+    let result = collection
+        .update_one(filter, doc! { "$setOnInsert": to_document(&new_repost)? })
+        .upsert(true)
+        .await?;
+    Ok(result.upserted_id.is_some())
+}
+
+// Returns true when a repost was removed
+pub async fn delete_repost(
+    collection: &Collection<Repost>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<bool, Error> {
+    let filter = doc! { "user_id": user_id, "post_id": post_id };
+    let result = collection.delete_one(filter).await?;
+    Ok(result.deleted_count == 1)
+}
+
+pub async fn delete_reposts_of_post(
+    collection: &Collection<Repost>,
+    post_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    collection.delete_many(doc! { "post_id": post_id }).await
+}
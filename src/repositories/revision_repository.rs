@@ -0,0 +1,86 @@
+use crate::models::revision::PostRevision;
+use crate::pagination::{Paginated, Pagination};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    options::IndexOptions,
+    results::{DeleteResult, InsertOneResult},
+    Collection, IndexModel,
+};
+
+// Two concurrent edits can't both claim the same revision number
+pub async fn ensure_indexes(collection: &Collection<PostRevision>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "post_id": 1, "revision": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+pub async fn create_revision(
+    collection: &Collection<PostRevision>,
+    revision: PostRevision,
+) -> Result<InsertOneResult, Error> {
+    collection.insert_one(revision).await
+}
+
+// Number the next revision of the post will get
+pub async fn next_revision_number(
+    collection: &Collection<PostRevision>,
+    post_id: ObjectId,
+) -> Result<i32, Error> {
+    let last = collection
+        .find_one(doc! { "post_id": post_id })
+        .sort(doc! { "revision": -1 })
+        .await?;
+    Ok(last.map_or(1, |revision| revision.revision + 1))
+}
+
+pub async fn find_revision(
+    collection: &Collection<PostRevision>,
+    post_id: ObjectId,
+    revision: i32,
+) -> Result<Option<PostRevision>, Error> {
+    collection
+        .find_one(doc! { "post_id": post_id, "revision": revision })
+        .await
+}
+
+pub async fn get_revisions(
+    collection: &Collection<PostRevision>,
+    post_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostRevision>, Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(doc! { "post_id": post_id }))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut revisions: Vec<PostRevision> = Vec::new();
+    for doc in documents {
+        revisions.push(from_document(doc)?);
+    }
+    Ok(Paginated {
+        data: revisions,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
+
+pub async fn delete_revisions_of_posts(
+    collection: &Collection<PostRevision>,
+    post_ids: &[ObjectId],
+) -> Result<DeleteResult, Error> {
+    collection
+        .delete_many(doc! { "post_id": { "$in": post_ids } })
+        .await
+}
@@ -0,0 +1,134 @@
+use crate::models::search::{SearchDocument, SearchType};
+use crate::utils::helps::not_deleted;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    error::Error,
+    options::IndexOptions,
+    Database, IndexModel,
+};
+
+// Text index definition (keys and weights) for each searchable collection
+fn text_index(search_type: SearchType) -> (Document, Document) {
+    match search_type {
+        SearchType::Posts => (doc! { "content": "text" }, doc! { "content": 1 }),
+        SearchType::Items => (
+            doc! { "name": "text", "description": "text" },
+            doc! { "name": 3, "description": 1 },
+        ),
+        SearchType::Users => (
+            doc! { "username": "text", "bio": "text" },
+            doc! { "username": 3, "bio": 1 },
+        ),
+        SearchType::Tags => (doc! { "name": "text" }, doc! { "name": 1 }),
+    }
+}
+
+pub async fn ensure_text_indexes(db: &Database) -> Result<(), Error> {
+    for search_type in SearchType::ALL {
+        let (keys, weights) = text_index(search_type);
+        let index = IndexModel::builder()
+            .keys(keys)
+            .options(
+                IndexOptions::builder()
+                    .name("search_text".to_string())
+                    .weights(weights)
+                    .build(),
+            )
+            .build();
+        db.collection::<Document>(search_type.as_str())
+            .create_index(index)
+            .await?;
+    }
+    Ok(())
+}
+
+// `filter` restricts the matches further, e.g. to the posts a user may see
+fn text_filter(query: &str, mut filter: Document) -> Document {
+    filter.insert("$text", doc! { "$search": query });
+    filter
+}
+
+pub async fn count(
+    db: &Database,
+    search_type: SearchType,
+    query: &str,
+    filter: Document,
+) -> Result<u64, Error> {
+    db.collection::<Document>(search_type.as_str())
+        .count_documents(text_filter(query, filter))
+        .await
+}
+
+// Matches ranked by MongoDB's text score
+pub async fn search(
+    db: &Database,
+    search_type: SearchType,
+    query: &str,
+    filter: Document,
+    skip: u64,
+    limit: i64,
+) -> Result<Vec<(f64, SearchDocument)>, Error> {
+    let mut cursor = db
+        .collection::<Document>(search_type.as_str())
+        .find(text_filter(query, filter))
+        .projection(doc! { "score": { "$meta": "textScore" } })
+        .sort(doc! { "score": { "$meta": "textScore" } })
+        .skip(skip)
+        .limit(limit)
+        .await?;
+
+    let mut hits = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        let score = doc.get_f64("score").unwrap_or_default();
+        if let Some(document) = SearchDocument::from_document(search_type, &doc) {
+            hits.push((score, document));
+        }
+    }
+    Ok(hits)
+}
+
+// Every searchable document of a collection, used to rebuild the local index
+pub async fn get_all_documents(
+    db: &Database,
+    search_type: SearchType,
+) -> Result<Vec<SearchDocument>, Error> {
+    // Only published posts are searchable
+    let filter = match search_type {
+        SearchType::Posts => doc! { "status": { "$in": [null, "published"] } },
+        _ => doc! {},
+    };
+    let mut cursor = db
+        .collection::<Document>(search_type.as_str())
+        .find(not_deleted(filter))
+        .await?;
+    let mut documents = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Some(document) = SearchDocument::from_document(search_type, &doc) {
+            documents.push(document);
+        }
+    }
+    Ok(documents)
+}
+
+// The subset of `post_ids` matching `filter`
+pub async fn filter_post_ids(
+    db: &Database,
+    post_ids: &[ObjectId],
+    filter: Document,
+) -> Result<Vec<ObjectId>, Error> {
+    let mut filter = filter;
+    filter.insert("_id", doc! { "$in": post_ids });
+    let mut cursor = db
+        .collection::<Document>("posts")
+        .find(filter)
+        .projection(doc! { "_id": 1 })
+        .await?;
+    let mut ids = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
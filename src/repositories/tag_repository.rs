@@ -1,12 +1,34 @@
+use crate::pagination::{Paginated, Pagination};
 use crate::tag::{Tag, TagRequest};
+use crate::utils::helps::{escape_regex, normalize_tag_name, not_deleted, now_rfc3339};
 use futures::stream::TryStreamExt;
 use mongodb::{
-    bson::doc,
+    bson::{doc, from_document, Document},
+    options::{IndexOptions, ReturnDocument},
     results::{DeleteResult, InsertOneResult, UpdateResult},
-    Collection,
+    Collection, IndexModel,
 };
 use mongodb::{bson::oid::ObjectId, error::Error};
 
+// One live tag per normalized name and per slug. Trashed tags carry a distinct
+// `deleted_at`, so they don't block reusing the name.
+pub async fn ensure_indexes(collection: &Collection<Tag>) -> Result<(), Error> {
+    for key in ["normalized_name", "slug"] {
+        let index = IndexModel::builder()
+            .keys(doc! { key: 1, "deleted_at": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        collection.create_index(index).await?;
+    }
+    // Old slugs and the taxonomy links are looked up by value
+    for key in ["previous_slugs", "parent_id", "canonical_id"] {
+        collection
+            .create_index(IndexModel::builder().keys(doc! { key: 1 }).build())
+            .await?;
+    }
+    Ok(())
+}
+
 pub async fn create_tag(
     collection: &Collection<Tag>,
     new_tag: Tag,
@@ -24,46 +46,440 @@ pub async fn get_tag_by_id(
         Err(_) => return Err(Error::custom("Invalid TAG ID")),
     };
 
-    let filter = doc! { "_id": obj_id };
+    let filter = not_deleted(doc! { "_id": obj_id });
     let tag = collection.find_one(filter).await?;
     Ok(tag)
 }
 
-pub async fn get_all_tags(collection: &Collection<Tag>) -> Result<Vec<Tag>, Error> {
-    let mut cursor = collection.find(doc! {}).await?;
-    let mut tags: Vec<Tag> = Vec::new();
+pub async fn get_tags_by_ids(
+    collection: &Collection<Tag>,
+    tag_ids: &[ObjectId],
+) -> Result<Vec<Tag>, Error> {
+    collection
+        .find(not_deleted(doc! { "_id": { "$in": tag_ids } }))
+        .await?
+        .try_collect()
+        .await
+}
+
+// Live tags whose normalized name starts with `prefix`, most used first.
+// `tag_ids` narrows the candidates, e.g. to the tags a user has used.
+pub async fn find_tags_by_prefix(
+    collection: &Collection<Tag>,
+    prefix: &str,
+    tag_ids: Option<&[ObjectId]>,
+    limit: i64,
+) -> Result<Vec<Tag>, Error> {
+    let mut filter = not_deleted(doc! {
+        "normalized_name": { "$regex": format!("^{}", escape_regex(prefix)) }
+    });
+    if let Some(tag_ids) = tag_ids {
+        filter.insert("_id", doc! { "$in": tag_ids });
+    }
+    collection
+        .find(filter)
+        .sort(doc! { "usage_count": -1, "normalized_name": 1 })
+        .limit(limit)
+        .await?
+        .try_collect()
+        .await
+}
+
+// Live tags with any of the given normalized names
+pub async fn find_tags_by_names(
+    collection: &Collection<Tag>,
+    normalized_names: &[String],
+) -> Result<Vec<Tag>, Error> {
+    collection
+        .find(not_deleted(doc! { "normalized_name": { "$in": normalized_names } }))
+        .await?
+        .try_collect()
+        .await
+}
+
+pub async fn get_all_tags(
+    collection: &Collection<Tag>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Tag>, Error> {
+    get_paginated_tags(collection, not_deleted(filter), pagination).await
+}
+
+// Tags in the owner's trash
+pub async fn get_deleted_tags(
+    collection: &Collection<Tag>,
+    owner_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<Tag>, Error> {
+    let filter = doc! { "owner_id": owner_id, "deleted_at": { "$ne": null } };
+    get_paginated_tags(collection, filter, pagination).await
+}
+
+async fn get_paginated_tags(
+    collection: &Collection<Tag>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Tag>, Error> {
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(filter))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
     while let Some(doc) = cursor.try_next().await? {
-        tags.push(doc);
+        documents.push(doc);
     }
-    Ok(tags)
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut tags: Vec<Tag> = Vec::new();
+    for doc in documents {
+        tags.push(from_document(doc)?);
+    }
+    Ok(Paginated {
+        data: tags,
+        next_cursor,
+        limit: pagination.limit,
+    })
 }
 
+// Rename the tag; the slug it had so far keeps resolving to it
 pub async fn update_tag(
     collection: &Collection<Tag>,
     tag_id: &str,
     updated_post: TagRequest,
+    normalized_name: &str,
+    slug: &str,
+    previous_slug: &str,
 ) -> Result<UpdateResult, Error> {
     let obj_id = match ObjectId::parse_str(tag_id) {
         Ok(id) => id,
         Err(_) => return Err(Error::custom("Invalid Post ID")),
     };
 
-    let filter = doc! { "_id": obj_id };
-    let update = doc! {
+    let filter = not_deleted(doc! { "_id": obj_id });
+    let mut update = doc! {
         "$set": {
-            "name": updated_post.name
+            "name": updated_post.name,
+            "normalized_name": normalized_name,
+            "slug": slug,
+            "updated_at": now_rfc3339(),
         }
     };
+    if !previous_slug.is_empty() && previous_slug != slug {
+        update.insert("$addToSet", doc! { "previous_slugs": previous_slug });
+    }
     let result = collection.update_one(filter, update).await?;
     Ok(result)
 }
 
-pub async fn delete_tag(collection: &Collection<Tag>, tag_id: &str) -> Result<DeleteResult, Error> {
+// Tag whose current slug is `slug`
+pub async fn find_tag_by_slug(collection: &Collection<Tag>, slug: &str) -> Result<Option<Tag>, Error> {
+    collection.find_one(not_deleted(doc! { "slug": slug })).await
+}
+
+// Tag that had `slug` before being renamed or merged into another one
+pub async fn find_tag_by_previous_slug(
+    collection: &Collection<Tag>,
+    slug: &str,
+) -> Result<Option<Tag>, Error> {
+    collection
+        .find_one(not_deleted(doc! { "previous_slugs": slug }))
+        .await
+}
+
+// Keep the slugs of a tag merged into this one
+pub async fn add_previous_slugs(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    slugs: &[String],
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_one(
+            doc! { "_id": tag_id },
+            doc! { "$addToSet": { "previous_slugs": { "$each": slugs } } },
+        )
+        .await
+}
+
+// Note on the source of a merge where it's going, so an interrupted merge can
+// be finished. False when the tag is being merged into another tag already.
+pub async fn mark_merging(
+    collection: &Collection<Tag>,
+    source_id: ObjectId,
+    target_id: ObjectId,
+) -> Result<bool, Error> {
+    let filter = doc! {
+        "_id": source_id,
+        "$or": [
+            { "merging_into": { "$exists": false } },
+            { "merging_into": target_id },
+        ],
+    };
+    let result = collection
+        .update_one(filter, doc! { "$set": { "merging_into": target_id } })
+        .await?;
+    Ok(result.matched_count == 1)
+}
+
+pub async fn clear_merging(collection: &Collection<Tag>, tag_id: ObjectId) -> Result<UpdateResult, Error> {
+    collection
+        .update_one(doc! { "_id": tag_id }, doc! { "$unset": { "merging_into": "" } })
+        .await
+}
+
+// Sources of merges that didn't get to the end
+pub async fn get_merging_tags(collection: &Collection<Tag>) -> Result<Vec<Tag>, Error> {
+    collection
+        .find(doc! { "merging_into": { "$exists": true } })
+        .await?
+        .try_collect()
+        .await
+}
+
+// Permanently remove a tag, references to it must be handled by the caller
+pub async fn remove_tag(collection: &Collection<Tag>, tag_id: ObjectId) -> Result<DeleteResult, Error> {
+    collection.delete_one(doc! { "_id": tag_id }).await
+}
+
+// Move a tag to the trash
+pub async fn delete_tag(collection: &Collection<Tag>, tag_id: &str) -> Result<UpdateResult, Error> {
     let obj_id = match ObjectId::parse_str(tag_id) {
         Ok(id) => id,
         Err(_) => return Err(Error::custom("Invalid Post ID")),
     };
-    let filter = doc! { "_id": obj_id };
-    let result = collection.delete_one(filter).await?;
+    let filter = not_deleted(doc! { "_id": obj_id });
+    let update = doc! { "$set": { "deleted_at": now_rfc3339() } };
+    let result = collection.update_one(filter, update).await?;
     Ok(result)
 }
+
+pub async fn restore_tag(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    owner_id: ObjectId,
+) -> Result<Option<Tag>, Error> {
+    collection
+        .find_one_and_update(
+            doc! { "_id": tag_id, "owner_id": owner_id, "deleted_at": { "$ne": null } },
+            doc! { "$unset": { "deleted_at": "" } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Permanently remove the tags deleted before `cutoff`, returns their ids
+pub async fn purge_deleted_tags(
+    collection: &Collection<Tag>,
+    cutoff: &str,
+) -> Result<Vec<ObjectId>, Error> {
+    let filter = doc! { "deleted_at": { "$ne": null, "$lt": cutoff } };
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(filter)
+        .projection(doc! { "_id": 1 })
+        .await?;
+    let mut ids = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
+    }
+    if !ids.is_empty() {
+        collection
+            .delete_many(doc! { "_id": { "$in": &ids } })
+            .await?;
+    }
+    Ok(ids)
+}
+
+// Match on the normalized name, so case and Unicode variants find the same tag
+pub async fn find_tag_by_name(collection: &Collection<Tag>, name: &str) -> Result<Option<Tag>, Error> {
+    let filter = not_deleted(doc! { "normalized_name": normalize_tag_name(name) });
+    collection.find_one(filter).await
+}
+
+// Whether a live tag other than `except` uses `slug`
+pub async fn slug_exists(
+    collection: &Collection<Tag>,
+    slug: &str,
+    except: Option<ObjectId>,
+) -> Result<bool, Error> {
+    let mut filter = not_deleted(doc! { "slug": slug });
+    if let Some(id) = except {
+        filter.insert("_id", doc! { "$ne": id });
+    }
+    Ok(collection.find_one(filter).await?.is_some())
+}
+
+// Every live tag, oldest first
+pub async fn get_live_tags(collection: &Collection<Tag>) -> Result<Vec<Tag>, Error> {
+    let mut cursor = collection
+        .find(not_deleted(doc! {}))
+        .sort(doc! { "created_at": 1, "_id": 1 })
+        .await?;
+    let mut tags = vec![];
+    while let Some(tag) = cursor.try_next().await? {
+        tags.push(tag);
+    }
+    Ok(tags)
+}
+
+// Fill in the normalized name and slug of a tag created before they existed
+pub async fn set_normalized_fields(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    normalized_name: &str,
+    slug: &str,
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_one(
+            doc! { "_id": tag_id },
+            doc! { "$set": { "normalized_name": normalized_name, "slug": slug } },
+        )
+        .await
+}
+
+pub async fn set_usage_count(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    usage_count: i64,
+) -> Result<UpdateResult, Error> {
+    collection
+        .update_one(
+            doc! { "_id": tag_id },
+            doc! { "$set": { "usage_count": usage_count } },
+        )
+        .await
+}
+
+pub async fn set_parent(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    parent_id: Option<ObjectId>,
+) -> Result<Option<Tag>, Error> {
+    let update = match parent_id {
+        Some(parent_id) => doc! { "$set": { "parent_id": parent_id, "updated_at": now_rfc3339() } },
+        None => doc! { "$unset": { "parent_id": "" }, "$set": { "updated_at": now_rfc3339() } },
+    };
+    collection
+        .find_one_and_update(not_deleted(doc! { "_id": tag_id }), update)
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Synonyms stay out of the hierarchy, so making one also detaches it
+pub async fn set_canonical(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    canonical_id: Option<ObjectId>,
+) -> Result<Option<Tag>, Error> {
+    let update = match canonical_id {
+        Some(canonical_id) => doc! {
+            "$set": { "canonical_id": canonical_id, "updated_at": now_rfc3339() },
+            "$unset": { "parent_id": "" },
+        },
+        None => doc! { "$unset": { "canonical_id": "" }, "$set": { "updated_at": now_rfc3339() } },
+    };
+    collection
+        .find_one_and_update(not_deleted(doc! { "_id": tag_id }), update)
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+pub async fn get_children(collection: &Collection<Tag>, tag_id: ObjectId) -> Result<Vec<Tag>, Error> {
+    collection
+        .find(not_deleted(doc! { "parent_id": tag_id }))
+        .sort(doc! { "normalized_name": 1 })
+        .await?
+        .try_collect()
+        .await
+}
+
+// Live synonyms of any of the tags
+pub async fn get_synonyms(
+    collection: &Collection<Tag>,
+    tag_ids: &[ObjectId],
+) -> Result<Vec<Tag>, Error> {
+    collection
+        .find(not_deleted(doc! { "canonical_id": { "$in": tag_ids } }))
+        .sort(doc! { "normalized_name": 1 })
+        .await?
+        .try_collect()
+        .await
+}
+
+// Ids of the live tags reached by following `connect_from` -> `connect_to` from
+// the tag, nearest first
+async fn walk_hierarchy(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    start_with: &str,
+    connect_from: &str,
+    connect_to: &str,
+) -> Result<Vec<ObjectId>, Error> {
+    let pipeline = vec![
+        doc! { "$match": { "_id": tag_id } },
+        doc! {
+            "$graphLookup": {
+                "from": "tags",
+                "startWith": start_with,
+                "connectFromField": connect_from,
+                "connectToField": connect_to,
+                "as": "related",
+                "depthField": "depth",
+                "restrictSearchWithMatch": { "deleted_at": null }
+            }
+        },
+        doc! { "$unwind": "$related" },
+        doc! { "$project": { "_id": "$related._id", "depth": "$related.depth" } },
+        doc! { "$sort": { "depth": 1 } },
+    ];
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut ids = vec![];
+    while let Some(doc) = cursor.try_next().await? {
+        if let Ok(id) = doc.get_object_id("_id") {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+// Parent first, root last
+pub async fn get_ancestor_ids(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    walk_hierarchy(collection, tag_id, "$parent_id", "parent_id", "_id").await
+}
+
+pub async fn get_descendant_ids(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+) -> Result<Vec<ObjectId>, Error> {
+    walk_hierarchy(collection, tag_id, "$_id", "_id", "parent_id").await
+}
+
+// Point children and synonyms of the `from` tags at `to`, or detach them.
+// A tag never ends up as its own parent or canonical.
+pub async fn relink_taxonomy(
+    collection: &Collection<Tag>,
+    from: &[ObjectId],
+    to: Option<ObjectId>,
+) -> Result<(), Error> {
+    for field in ["parent_id", "canonical_id"] {
+        let update = match to {
+            Some(to) => doc! { "$set": { field: to } },
+            None => doc! { "$unset": { field: "" } },
+        };
+        collection
+            .update_many(doc! { field: { "$in": from } }, update)
+            .await?;
+        if let Some(to) = to {
+            collection
+                .update_one(doc! { "_id": to, field: to }, doc! { "$unset": { field: "" } })
+                .await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::models::tag_subscription::{SubscriptionKind, TagSubscription};
+use crate::pagination::{Paginated, Pagination};
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::now_rfc3339;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    options::{IndexOptions, ReturnDocument},
+    Collection, IndexModel,
+};
+
+pub async fn ensure_indexes(collection: &Collection<TagSubscription>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "user_id": 1, "tag_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+// Follow or mute the tag, replacing the other kind if it was set.
+// Returns true when something changed.
+pub async fn subscribe(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    tag_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<bool, Error> {
+    let filter = doc! { "user_id": user_id, "tag_id": tag_id };
+    let update = doc! {
+        "$set": { "kind": kind.as_str() },
+        "$setOnInsert": { "created_at": now_rfc3339() },
+    };
+    let before = collection
+        .find_one_and_update(filter, update)
+        .upsert(true)
+        .return_document(ReturnDocument::Before)
+        .await?;
+    Ok(before.is_none_or(|subscription| subscription.kind != kind))
+}
+
+// Returns true when a subscription of this kind was removed
+pub async fn unsubscribe(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    tag_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<bool, Error> {
+    let filter = doc! { "user_id": user_id, "tag_id": tag_id, "kind": kind.as_str() };
+    let result = collection.delete_one(filter).await?;
+    Ok(result.deleted_count == 1)
+}
+
+pub async fn get_tag_ids(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<Vec<ObjectId>, Error> {
+    let mut cursor = collection
+        .find(doc! { "user_id": user_id, "kind": kind.as_str() })
+        .await?;
+    let mut ids = vec![];
+    while let Some(subscription) = cursor.try_next().await? {
+        ids.push(subscription.tag_id);
+    }
+    Ok(ids)
+}
+
+pub async fn get_subscriptions(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    kind: SubscriptionKind,
+    pagination: &Pagination,
+) -> Result<Paginated<TagSubscription>, Error> {
+    let filter = doc! { "user_id": user_id, "kind": kind.as_str() };
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(pagination.match_doc(filter))
+        .sort(pagination.sort_doc())
+        .limit(pagination.fetch_limit())
+        .await?;
+    let mut documents: Vec<Document> = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        documents.push(doc);
+    }
+
+    let (documents, next_cursor) = pagination.page(documents);
+    let mut subscriptions: Vec<TagSubscription> = Vec::new();
+    for doc in documents {
+        subscriptions.push(from_document(doc)?);
+    }
+    Ok(Paginated {
+        data: subscriptions,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
+
+// Subscriptions to removed tags go with them
+pub async fn delete_subscriptions_of_tags(
+    collection: &Collection<TagSubscription>,
+    tag_ids: &[ObjectId],
+) -> Result<(), Error> {
+    collection
+        .delete_many(doc! { "tag_id": { "$in": tag_ids } })
+        .await?;
+    Ok(())
+}
+
+// Hand the subscriptions of a merged tag to the tag it was merged into. Users
+// already subscribed to that one keep their own subscription.
+pub async fn move_subscriptions(
+    collection: &Collection<TagSubscription>,
+    from: ObjectId,
+    to: ObjectId,
+) -> Result<(), Error> {
+    let subscriptions: Vec<TagSubscription> = collection
+        .find(doc! { "tag_id": from })
+        .await?
+        .try_collect()
+        .await?;
+    for subscription in subscriptions {
+        let filter = doc! { "_id": subscription.id };
+        match collection
+            .update_one(filter.clone(), doc! { "$set": { "tag_id": to } })
+            .await
+        {
+            Ok(_) => {}
+            Err(err) if is_duplicate_key(&err) => {
+                collection.delete_one(filter).await?;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
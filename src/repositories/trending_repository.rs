@@ -0,0 +1,176 @@
+use crate::models::trending::{TrendingEntry, TrendingKind, TrendingSnapshot, TrendingWindow};
+use crate::post::Post;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, from_document, Bson, DateTime, Document};
+use mongodb::{error::Error, Collection};
+
+// Only published public posts feed the shared rankings; they're cached for
+// every viewer alike.
+fn public_posts(since: &str) -> Document {
+    doc! {
+        "created_at": { "$gte": since },
+        "status": { "$in": [Bson::Null, "published"] },
+        "visibility": { "$in": [Bson::Null, "public"] },
+        "deleted_at": Bson::Null,
+    }
+}
+
+// `weight` halved for every half-life elapsed between `at` and `now`
+fn decay_stage(now: DateTime, window: TrendingWindow) -> Document {
+    let age = doc! {
+        "$max": [0, { "$subtract": [now, { "$dateFromString": { "dateString": "$at" } }] }]
+    };
+    doc! {
+        "$set": {
+            "score": {
+                "$multiply": [
+                    "$weight",
+                    { "$exp": { "$divide": [
+                        { "$multiply": [-std::f64::consts::LN_2, age] },
+                        window.half_life_millis(),
+                    ] } },
+                ]
+            }
+        }
+    }
+}
+
+fn window_bounds(window: TrendingWindow) -> (DateTime, String) {
+    let now = DateTime::now();
+    let since = DateTime::from_millis(now.timestamp_millis() - window.duration_millis())
+        .try_to_rfc3339_string()
+        .expect("valid timestamp");
+    (now, since)
+}
+
+async fn collect_entries(
+    collection: &Collection<Post>,
+    pipeline: Vec<Document>,
+) -> Result<Vec<TrendingEntry>, Error> {
+    let mut cursor = collection.aggregate(pipeline).await?;
+    let mut entries = Vec::new();
+    while let Some(doc) = cursor.try_next().await? {
+        entries.push(from_document(doc)?);
+    }
+    Ok(entries)
+}
+
+// Tags ranked by how often recent posts use them. Each post counts once plus
+// its engagement, decayed by the post's age.
+pub async fn compute_trending_tags(
+    collection: &Collection<Post>,
+    window: TrendingWindow,
+    limit: i64,
+) -> Result<Vec<TrendingEntry>, Error> {
+    let (now, since) = window_bounds(window);
+    let pipeline = vec![
+        doc! { "$match": { "$and": [public_posts(&since), { "tags.0": { "$exists": true } }] } },
+        doc! {
+            "$project": {
+                "tags": 1,
+                "at": "$created_at",
+                "weight": { "$add": [
+                    1,
+                    { "$ifNull": ["$likes_count", 0] },
+                    { "$ifNull": ["$replies_count", 0] },
+                    { "$ifNull": ["$reposts_count", 0] },
+                ] },
+            }
+        },
+        decay_stage(now, window),
+        doc! { "$unwind": "$tags" },
+        doc! { "$group": { "_id": "$tags", "score": { "$sum": "$score" } } },
+        doc! { "$sort": { "score": -1, "_id": 1 } },
+        doc! { "$limit": limit },
+        doc! { "$project": { "_id": 0, "id": "$_id", "score": 1 } },
+    ];
+    collect_entries(collection, pipeline).await
+}
+
+// Posts ranked by engagement velocity: replies and reposts that happened inside
+// the window, each decayed by its own age, plus the post itself if it's new.
+pub async fn compute_trending_posts(
+    collection: &Collection<Post>,
+    window: TrendingWindow,
+    limit: i64,
+) -> Result<Vec<TrendingEntry>, Error> {
+    let (now, since) = window_bounds(window);
+    let pipeline = vec![
+        doc! { "$match": public_posts(&since) },
+        doc! {
+            "$project": {
+                "_id": 0,
+                "post_id": "$_id",
+                "at": "$created_at",
+                "weight": { "$add": [1, { "$ifNull": ["$likes_count", 0] }] },
+            }
+        },
+        doc! {
+            "$unionWith": {
+                "coll": "posts",
+                "pipeline": [
+                    { "$match": {
+                        "reply_to": { "$ne": Bson::Null },
+                        "created_at": { "$gte": &since },
+                        "status": { "$in": [Bson::Null, "published"] },
+                        "deleted_at": Bson::Null,
+                    } },
+                    { "$project": { "_id": 0, "post_id": "$reply_to", "at": "$created_at", "weight": { "$literal": 2 } } },
+                ]
+            }
+        },
+        doc! {
+            "$unionWith": {
+                "coll": "reposts",
+                "pipeline": [
+                    { "$match": { "created_at": { "$gte": &since } } },
+                    { "$project": { "_id": 0, "post_id": 1, "at": "$created_at", "weight": { "$literal": 3 } } },
+                ]
+            }
+        },
+        decay_stage(now, window),
+        doc! { "$group": { "_id": "$post_id", "score": { "$sum": "$score" } } },
+        // Engagement on older posts counts too, as long as the post is still public
+        doc! {
+            "$lookup": {
+                "from": "posts",
+                "localField": "_id",
+                "foreignField": "_id",
+                "as": "post"
+            }
+        },
+        doc! {
+            "$match": {
+                "post.status": { "$in": [Bson::Null, "published"] },
+                "post.visibility": { "$in": [Bson::Null, "public"] },
+                "post.deleted_at": Bson::Null,
+                "post.0": { "$exists": true },
+            }
+        },
+        doc! { "$sort": { "score": -1, "_id": 1 } },
+        doc! { "$limit": limit },
+        doc! { "$project": { "_id": 0, "id": "$_id", "score": 1 } },
+    ];
+    collect_entries(collection, pipeline).await
+}
+
+pub async fn save_snapshot(
+    collection: &Collection<TrendingSnapshot>,
+    snapshot: TrendingSnapshot,
+) -> Result<(), Error> {
+    collection
+        .replace_one(doc! { "_id": &snapshot.id }, snapshot)
+        .upsert(true)
+        .await?;
+    Ok(())
+}
+
+pub async fn find_snapshot(
+    collection: &Collection<TrendingSnapshot>,
+    kind: TrendingKind,
+    window: TrendingWindow,
+) -> Result<Option<TrendingSnapshot>, Error> {
+    collection
+        .find_one(doc! { "_id": TrendingSnapshot::key(kind, window) })
+        .await
+}
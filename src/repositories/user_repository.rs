@@ -1,5 +1,21 @@
 use crate::models::user::User;
-use mongodb::{bson::{doc, oid::ObjectId}, error::Error, results::InsertOneResult, Collection};
+use futures::stream::TryStreamExt;
+use crate::utils::helps::{now_rfc3339, username_key};
+use mongodb::{bson::{doc, oid::ObjectId, Document}, error::Error, options::{IndexOptions, ReturnDocument}, results::{InsertOneResult, UpdateResult}, Collection, IndexModel};
+
+// Usernames and emails are unique. Accounts from before these fields were
+// filled in are left out of the indexes.
+pub async fn ensure_indexes(collection: &Collection<User>) -> Result<(), Error> {
+    for key in ["username_key", "email"] {
+        let options = IndexOptions::builder()
+            .unique(true)
+            .partial_filter_expression(doc! { key: { "$gt": "" } })
+            .build();
+        let index = IndexModel::builder().keys(doc! { key: 1 }).options(options).build();
+        collection.create_index(index).await?;
+    }
+    Ok(())
+}
 
 // Create a new user
 pub async fn create_user(collection: &Collection<User>, new_user: User) -> Result<InsertOneResult, Error> {
@@ -16,6 +32,22 @@ pub async fn find_user_by_username(
     collection.find_one(filter).await
 }
 
+// Find a user by `username_key`, so look-alike spellings of a username find it
+pub async fn find_user_by_username_key(
+    collection: &Collection<User>,
+    key: &str,
+) -> mongodb::error::Result<Option<User>> {
+    collection.find_one(doc! { "username_key": key }).await
+}
+
+// Find a user by normalized email
+pub async fn find_user_by_email(
+    collection: &Collection<User>,
+    email: &str,
+) -> mongodb::error::Result<Option<User>> {
+    collection.find_one(doc! { "email": email }).await
+}
+
 // Find a user by id
 pub async fn get_user_by_id_service(
     collection: &Collection<User>,
@@ -24,4 +56,321 @@ pub async fn get_user_by_id_service(
     let obj_id = ObjectId::parse_str(id).unwrap();
     let filter = doc! { "_id": obj_id };
     collection.find_one(filter).await
-}
\ No newline at end of file
+}
+// Find users by ids
+pub async fn get_users_by_ids(
+    collection: &Collection<User>,
+    ids: &[ObjectId],
+) -> mongodb::error::Result<Vec<User>> {
+    let mut cursor = collection.find(doc! { "_id": { "$in": ids } }).await?;
+    let mut users = vec![];
+    while let Some(user) = cursor.try_next().await? {
+        users.push(user);
+    }
+    Ok(users)
+}
+
+// Keep follower_count and following_count in step with the follows collection
+pub async fn increment_follow_counts(
+    collection: &Collection<User>,
+    follower_id: ObjectId,
+    following_id: ObjectId,
+    amount: i32,
+) -> mongodb::error::Result<()> {
+    collection
+        .update_one(doc! { "_id": follower_id }, doc! { "$inc": { "following_count": amount } })
+        .await?;
+    collection
+        .update_one(doc! { "_id": following_id }, doc! { "$inc": { "follower_count": amount } })
+        .await?;
+    Ok(())
+}
+
+// Find users by usernames
+// Users by `username_key`, see `find_user_by_username_key`
+pub async fn find_users_by_username_keys(
+    collection: &Collection<User>,
+    keys: &[String],
+) -> mongodb::error::Result<Vec<User>> {
+    let mut cursor = collection.find(doc! { "username_key": { "$in": keys } }).await?;
+    let mut users = vec![];
+    while let Some(user) = cursor.try_next().await? {
+        users.push(user);
+    }
+    Ok(users)
+}
+
+pub async fn add_close_friend(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    friend_id: ObjectId,
+) -> mongodb::error::Result<UpdateResult> {
+    collection
+        .update_one(doc! { "_id": user_id }, doc! { "$addToSet": { "close_friend_ids": friend_id } })
+        .await
+}
+
+pub async fn remove_close_friend(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    friend_id: ObjectId,
+) -> mongodb::error::Result<UpdateResult> {
+    collection
+        .update_one(doc! { "_id": user_id }, doc! { "$pull": { "close_friend_ids": friend_id } })
+        .await
+}
+
+// Ids of the users who have `friend_id` on their close friends list
+pub async fn get_close_friend_of_ids(
+    collection: &Collection<User>,
+    friend_id: ObjectId,
+) -> mongodb::error::Result<Vec<ObjectId>> {
+    let mut cursor = collection.find(doc! { "close_friend_ids": friend_id }).await?;
+    let mut ids = vec![];
+    while let Some(user) = cursor.try_next().await? {
+        ids.push(user.id);
+    }
+    Ok(ids)
+}
+
+// Whether another user has this username or one that looks like it
+pub async fn username_taken(
+    collection: &Collection<User>,
+    username: &str,
+    except: Option<ObjectId>,
+) -> mongodb::error::Result<bool> {
+    let mut filter = doc! { "username_key": username_key(username) };
+    if let Some(except) = except {
+        filter.insert("_id", doc! { "$ne": except });
+    }
+    Ok(collection.find_one(filter).await?.is_some())
+}
+
+// Apply a profile update. A username change only goes through when the last one
+// is older than `cooldown_cutoff`; otherwise None is returned.
+pub async fn update_profile(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    username: Option<&str>,
+    bio: Option<&str>,
+    cooldown_cutoff: &str,
+) -> mongodb::error::Result<Option<User>> {
+    let now = now_rfc3339();
+    let mut filter = doc! { "_id": user_id };
+    let mut set = doc! { "updated_at": &now };
+    let mut unset = doc! {};
+    if let Some(username) = username {
+        filter.insert("username_changed_at", doc! { "$not": { "$gt": cooldown_cutoff } });
+        set.insert("username", username);
+        set.insert("username_key", username_key(username));
+        set.insert("username_changed_at", &now);
+    }
+    match bio {
+        Some("") => {
+            unset.insert("bio", "");
+        }
+        Some(bio) => {
+            set.insert("bio", bio);
+        }
+        None => {}
+    }
+    let mut update = doc! { "$set": set };
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    collection
+        .find_one_and_update(filter, update)
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Returns the user as it was before, so the old avatar can be cleaned up
+pub async fn set_avatar(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    avatar: Option<&str>,
+) -> mongodb::error::Result<Option<User>> {
+    let update = match avatar {
+        Some(avatar) => doc! { "$set": { "avatar": avatar, "updated_at": now_rfc3339() } },
+        None => doc! { "$set": { "avatar": null, "updated_at": now_rfc3339() } },
+    };
+    collection
+        .find_one_and_update(doc! { "_id": user_id }, update)
+        .return_document(ReturnDocument::Before)
+        .await
+}
+
+pub async fn update_privacy(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    mut set: Document,
+) -> mongodb::error::Result<Option<User>> {
+    set.insert("updated_at", now_rfc3339());
+    collection
+        .find_one_and_update(doc! { "_id": user_id }, doc! { "$set": set })
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Mark the email verified, provided it is still the account's email
+pub async fn verify_email(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    email: &str,
+) -> mongodb::error::Result<UpdateResult> {
+    collection
+        .update_one(
+            doc! { "_id": user_id, "email": email },
+            doc! { "$set": { "is_verified": true, "updated_at": now_rfc3339() } },
+        )
+        .await
+}
+
+// Store a new password hash and bump the token version, which signs the user
+// out everywhere
+pub async fn set_password(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    password: &str,
+) -> mongodb::error::Result<Option<User>> {
+    let now = now_rfc3339();
+    collection
+        .find_one_and_update(
+            doc! { "_id": user_id },
+            doc! {
+                "$set": { "password": password, "password_changed_at": &now, "updated_at": &now },
+                "$inc": { "token_version": 1 },
+            },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+}
+
+// Accounts created before usernames had a `username_key`
+pub async fn get_users_without_username_key(
+    collection: &Collection<User>,
+) -> mongodb::error::Result<Vec<User>> {
+    let mut cursor = collection
+        .find(doc! { "username_key": { "$not": { "$gt": "" } } })
+        .await?;
+    let mut users = vec![];
+    while let Some(user) = cursor.try_next().await? {
+        users.push(user);
+    }
+    Ok(users)
+}
+
+pub async fn set_username_key(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    key: &str,
+) -> mongodb::error::Result<UpdateResult> {
+    collection
+        .update_one(doc! { "_id": user_id }, doc! { "$set": { "username_key": key } })
+        .await
+}
+
+// Swap in an upgraded hash of the same password. Unlike `set_password` this
+// keeps the user's sessions, and does nothing if the password changed meanwhile.
+pub async fn rehash_password(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    old_hash: &str,
+    new_hash: &str,
+) -> mongodb::error::Result<UpdateResult> {
+    collection
+        .update_one(
+            doc! { "_id": user_id, "password": old_hash },
+            doc! { "$set": { "password": new_hash } },
+        )
+        .await
+}
+
+// Keep a new TOTP secret until a code confirms it. Returns false when two-factor
+// authentication is enabled already.
+pub async fn set_pending_two_factor(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    secret: &str,
+) -> mongodb::error::Result<bool> {
+    let result = collection
+        .update_one(
+            doc! { "_id": user_id, "two_factor.enabled": { "$ne": true } },
+            doc! { "$set": { "two_factor.pending_secret": secret } },
+        )
+        .await?;
+    Ok(result.matched_count == 1)
+}
+
+// Switch to the confirmed secret, provided it is still the pending one
+pub async fn enable_two_factor(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    secret: &str,
+    step: i64,
+    recovery_codes: &[String],
+) -> mongodb::error::Result<bool> {
+    let filter = doc! {
+        "_id": user_id,
+        "two_factor.enabled": { "$ne": true },
+        "two_factor.pending_secret": secret,
+    };
+    let update = doc! {
+        "$set": {
+            "two_factor": {
+                "enabled": true,
+                "secret": secret,
+                "recovery_codes": recovery_codes,
+                "last_used_step": step,
+                "enabled_at": now_rfc3339(),
+            },
+        },
+    };
+    Ok(collection.update_one(filter, update).await?.matched_count == 1)
+}
+
+// Record a TOTP code's time step as used, unless it or a later one already was
+pub async fn use_totp_step(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    step: i64,
+) -> mongodb::error::Result<bool> {
+    let filter = doc! {
+        "_id": user_id,
+        "two_factor.enabled": true,
+        "$or": [
+            { "two_factor.last_used_step": null },
+            { "two_factor.last_used_step": { "$lt": step } },
+        ],
+    };
+    let update = doc! { "$set": { "two_factor.last_used_step": step } };
+    Ok(collection.update_one(filter, update).await?.matched_count == 1)
+}
+
+// Use up a recovery code. Returns false when it isn't one of the user's.
+pub async fn use_recovery_code(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    code_hash: &str,
+) -> mongodb::error::Result<bool> {
+    let filter = doc! {
+        "_id": user_id,
+        "two_factor.enabled": true,
+        "two_factor.recovery_codes": code_hash,
+    };
+    let update = doc! { "$pull": { "two_factor.recovery_codes": code_hash } };
+    Ok(collection.update_one(filter, update).await?.matched_count == 1)
+}
+
+pub async fn clear_two_factor(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+) -> mongodb::error::Result<bool> {
+    let result = collection
+        .update_one(
+            doc! { "_id": user_id },
+            doc! { "$unset": { "two_factor": "" }, "$set": { "updated_at": now_rfc3339() } },
+        )
+        .await?;
+    Ok(result.matched_count == 1)
+}
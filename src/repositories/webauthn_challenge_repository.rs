@@ -0,0 +1,45 @@
+use crate::models::passkey::{CeremonyKind, WebauthnChallenge};
+use crate::utils::helps::now_rfc3339;
+use mongodb::{bson::doc, error::Error, options::IndexOptions, Collection, IndexModel};
+
+pub async fn ensure_indexes(collection: &Collection<WebauthnChallenge>) -> Result<(), Error> {
+    let index = IndexModel::builder()
+        .keys(doc! { "challenge": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    collection.create_index(index).await?;
+    Ok(())
+}
+
+pub async fn create_challenge(
+    collection: &Collection<WebauthnChallenge>,
+    challenge: WebauthnChallenge,
+) -> Result<(), Error> {
+    collection.insert_one(challenge).await?;
+    Ok(())
+}
+
+// Take an unexpired challenge of this kind, so it can't be answered twice
+pub async fn consume_challenge(
+    collection: &Collection<WebauthnChallenge>,
+    challenge: &str,
+    kind: CeremonyKind,
+) -> Result<Option<WebauthnChallenge>, Error> {
+    collection
+        .find_one_and_delete(doc! {
+            "challenge": challenge,
+            "kind": kind.as_str(),
+            "expires_at": { "$gt": now_rfc3339() },
+        })
+        .await
+}
+
+// Challenges that were never answered
+pub async fn delete_expired_challenges(
+    collection: &Collection<WebauthnChallenge>,
+) -> Result<u64, Error> {
+    let result = collection
+        .delete_many(doc! { "expires_at": { "$lte": now_rfc3339() } })
+        .await?;
+    Ok(result.deleted_count)
+}
@@ -0,0 +1,148 @@
+use crate::database::mongodb::get_database;
+use crate::login::LoginAttempt;
+use crate::pagination::{Paginated, PaginationQuery};
+use crate::tag::{Tag, TagCanonicalRequest, TagParentRequest};
+use crate::user::User;
+use crate::{
+    login_throttle_service, require_admin, tag_service, two_factor_service, user_service,
+    Authentication,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(Authentication::session_only())
+            .route("/users/{id}", web::get().to(get_user))
+            .route("/users/{id}/login-attempts", web::get().to(get_login_attempts))
+            .route("/users/{id}/2fa", web::delete().to(reset_two_factor))
+            .route("/tags/{id}/parent", web::put().to(set_tag_parent))
+            .route("/tags/{id}/canonical", web::put().to(set_tag_canonical)),
+    );
+}
+
+async fn get_user(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    if ObjectId::parse_str(id.as_str()).is_err() {
+        return HttpResponse::BadRequest().body("Invalid user ID");
+    }
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match user_service::get_user_by_id_service(&collection, &id).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(User::to_admin(user)),
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Audit log of the logins to an account, newest first by default
+async fn get_login_attempts(
+    id: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    let user_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
+    };
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let attempts: Collection<LoginAttempt> = db.collection("login_attempts");
+    match login_throttle_service::get_login_attempts_service(&attempts, user_id, &pagination).await {
+        Ok(page) => HttpResponse::Ok().json(Paginated {
+            data: page.data.into_iter().map(LoginAttempt::to_attempt).collect(),
+            next_cursor: page.next_cursor,
+            limit: page.limit,
+        }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// For users locked out of their second factor and recovery codes
+async fn reset_two_factor(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    let user_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
+    };
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match two_factor_service::reset_two_factor_service(&collection, user_id).await {
+        Ok(true) => HttpResponse::Ok().body("Two-factor authentication reset"),
+        Ok(false) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// The tag and, when given, the tag it should be attached to
+async fn load_tags(
+    collection: &Collection<Tag>,
+    id: &str,
+    other_id: Option<&str>,
+) -> Result<(Tag, Option<Tag>), HttpResponse> {
+    let mut tags = vec![];
+    for tag_id in std::iter::once(id).chain(other_id) {
+        match tag_service::get_tag_by_id_service(collection, tag_id).await {
+            Ok(Some(tag)) => tags.push(tag),
+            Ok(None) => return Err(HttpResponse::NotFound().body("TAG not found")),
+            Err(err) => return Err(HttpResponse::BadRequest().body(err.to_string())),
+        }
+    }
+    let other = if tags.len() > 1 { tags.pop() } else { None };
+    Ok((tags.remove(0), other))
+}
+
+async fn set_tag_parent(
+    id: web::Path<String>,
+    body: web::Json<TagParentRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    let (tag, parent) = match load_tags(&collection, &id, body.parent_id.as_deref()).await {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    match tag_service::set_tag_parent_service(&collection, &tag, parent.as_ref()).await {
+        Ok(Ok(tag)) => HttpResponse::Ok().json(tag),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn set_tag_canonical(
+    id: web::Path<String>,
+    body: web::Json<TagCanonicalRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    let (tag, canonical) = match load_tags(&collection, &id, body.canonical_id.as_deref()).await {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    match tag_service::set_tag_canonical_service(&collection, &tag, canonical.as_ref()).await {
+        Ok(Ok(tag)) => HttpResponse::Ok().json(tag),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -1,10 +1,22 @@
 
 use actix_session::Session;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 use mongodb::Collection;
+use serde_json::Value;
+use crate::access_token::AccessToken;
 use crate::database::mongodb::get_database;
-use crate::models::user::{RegisterRequest, LoginRequest};
-use crate::services::auth_service;
+use crate::email::{EmailLog, VerifyEmailQuery};
+use crate::models::passkey::{
+    AuthenticationCredential, Passkey, PasskeyLoginStartRequest, WebauthnChallenge,
+};
+use crate::models::password::{ForgotPasswordRequest, PasswordReset, ResetPasswordRequest};
+use crate::models::user::{normalize_email, RegisterRequest, LoginRequest};
+use crate::login::{LoginClient, LoginRejection, LoginThrottle, UnlockQuery};
+use crate::repositories::user_repository;
+use crate::services::{auth_service, login_throttle_service, passkey_service};
+use crate::utils::helps::username_key;
+use crate::two_factor::TwoFactorLoginRequest;
+use crate::get_config;
 use crate::user::User;
 
 // Route configuration
@@ -13,25 +25,181 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         web::scope("/auth")
             .route("/register", web::post().to(register))
             .route("/login", web::post().to(login))
+            .route("/login/2fa", web::post().to(login_two_factor))
+            .route("/passkeys/login/start", web::post().to(start_passkey_login))
+            .route("/passkeys/login/finish", web::post().to(finish_passkey_login))
+            .route("/verify-email", web::get().to(verify_email))
+            .route("/unlock", web::get().to(unlock_account))
+            .route("/password/forgot", web::post().to(forgot_password))
+            .route("/password/reset", web::post().to(reset_password))
     );
 }
 
 // Register a new user
 async fn register(register_req: web::Json<RegisterRequest>) -> impl Responder {
+    let register_req = match register_req.into_inner().validate() {
+        Ok(req) => req,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
     let db = get_database().await;
     let collection: Collection<User> = db.collection("users");
-    match auth_service::register_user_service(&collection, register_req.into_inner()).await {
-        Ok(_) => HttpResponse::Ok().body("User registered successfully"),
+    let email_logs: Collection<EmailLog> = db.collection("email_logs");
+    match auth_service::register_user_service(&collection, &email_logs, register_req).await {
+        Ok(Ok(_)) => HttpResponse::Ok().body("User registered successfully"),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Target of the link in verification emails
+async fn verify_email(query: web::Query<VerifyEmailQuery>) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match auth_service::verify_email_service(&collection, &query.token).await {
+        Ok(true) => HttpResponse::Ok().body("Email verified"),
+        Ok(false) => HttpResponse::BadRequest().body("Invalid or expired verification link"),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
 // Login and return JWT token
-async fn login(login_req: web::Json<LoginRequest>, session: Session) -> impl Responder {
+async fn login(login_req: web::Json<LoginRequest>, req: HttpRequest, session: Session) -> impl Responder {
+    let login_req = match login_req.into_inner().validate() {
+        Ok(req) => req,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let db = get_database().await;
+    let result = auth_service::login_user_service(&db, login_req, login_client(&req), session).await;
+    login_response(result, "Invalid username or password")
+}
+
+// Second login step for accounts with two-factor authentication
+async fn login_two_factor(
+    body: web::Json<TwoFactorLoginRequest>,
+    req: HttpRequest,
+    session: Session,
+) -> impl Responder {
+    let db = get_database().await;
+    let result =
+        auth_service::complete_two_factor_login_service(&db, body.into_inner(), login_client(&req), session)
+            .await;
+    login_response(result, "Invalid authentication code")
+}
+
+// Options for the browser's passkey prompt. A username narrows it down to that
+// account's passkeys.
+async fn start_passkey_login(body: Option<web::Json<PasskeyLoginStartRequest>>) -> impl Responder {
+    let body = body.map(web::Json::into_inner).unwrap_or_default();
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    let challenges: Collection<WebauthnChallenge> = db.collection("webauthn_challenges");
+    let user = match body.username.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+        Some(username) => {
+            match user_repository::find_user_by_username_key(&collection, &username_key(username)).await {
+                Ok(user) => user,
+                Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+            }
+        }
+        None => None,
+    };
+    match passkey_service::start_authentication_service(&passkeys, &challenges, user.as_ref()).await {
+        Ok(options) => HttpResponse::Ok().json(options),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn finish_passkey_login(
+    body: web::Json<AuthenticationCredential>,
+    req: HttpRequest,
+    session: Session,
+) -> impl Responder {
+    let db = get_database().await;
+    let result =
+        auth_service::login_with_passkey_service(&db, body.into_inner(), login_client(&req), session).await;
+    login_response(result, "Passkey not recognized")
+}
+
+fn login_response(
+    result: Result<Result<Value, LoginRejection>, Box<dyn std::error::Error>>,
+    invalid_message: &'static str,
+) -> HttpResponse {
+    match result {
+        Ok(Ok(token)) => HttpResponse::Ok().json(token),
+        Ok(Err(LoginRejection::InvalidCredentials)) => HttpResponse::Unauthorized().body(invalid_message),
+        Ok(Err(LoginRejection::InvalidChallenge)) => {
+            HttpResponse::Unauthorized().body("Login challenge is invalid or expired, log in again")
+        }
+        Ok(Err(LoginRejection::Throttled { retry_after_secs })) => HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+            .body("Too many failed login attempts, try again later"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Forwarding headers are only believed when configured, as clients can set them
+fn login_client(req: &HttpRequest) -> LoginClient {
+    let info = req.connection_info();
+    let ip = if get_config().trust_proxy_headers {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+    LoginClient {
+        ip: ip.unwrap_or("unknown").to_string(),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    }
+}
+
+// Target of the link in the email sent when an account gets locked
+async fn unlock_account(query: web::Query<UnlockQuery>) -> impl Responder {
+    let db = get_database().await;
+    let throttles: Collection<LoginThrottle> = db.collection("login_throttles");
+    match login_throttle_service::unlock_account_service(&throttles, &query.token).await {
+        Ok(true) => HttpResponse::Ok().body("Account unlocked"),
+        Ok(false) => HttpResponse::BadRequest().body("Invalid or expired unlock link"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Always answers the same, so it can't be used to find out who has an account
+async fn forgot_password(body: web::Json<ForgotPasswordRequest>) -> impl Responder {
+    let email = match normalize_email(&body.email) {
+        Ok(email) => email,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let resets: Collection<PasswordReset> = db.collection("password_resets");
+    let email_logs: Collection<EmailLog> = db.collection("email_logs");
+    match auth_service::forgot_password_service(&collection, &resets, &email_logs, &email).await {
+        Ok(()) => HttpResponse::Accepted()
+            .body("If an account uses this email, a password reset link has been sent to it"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Signs the user out everywhere
+async fn reset_password(body: web::Json<ResetPasswordRequest>) -> impl Responder {
     let db = get_database().await;
     let collection: Collection<User> = db.collection("users");
-    match auth_service::login_user_service(&collection, login_req.into_inner(), session).await {
-        Ok(token) => HttpResponse::Ok().json(token),
-        Err(err) => HttpResponse::Unauthorized().body(err.to_string()),
+    let resets: Collection<PasswordReset> = db.collection("password_resets");
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    match auth_service::reset_password_service(
+        &collection,
+        &resets,
+        &tokens,
+        &body.token,
+        &body.new_password,
+    )
+    .await
+    {
+        Ok(Ok(())) => HttpResponse::Ok().body("Password has been reset"),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
@@ -0,0 +1,85 @@
+use crate::database::mongodb::get_database;
+use crate::follow::Follow;
+use crate::pagination::PaginationQuery;
+use crate::post::Post;
+use crate::tag::Tag;
+use crate::tag_subscription::{HomeFeedQuery, TagSubscription};
+use crate::user::User;
+use crate::{feed_service, handler, Authentication};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Collection;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/feed")
+            .wrap(Authentication::scoped("posts"))
+            .route("", web::get().to(get_home_feed))
+            .route("/tags", web::get().to(get_tag_feed)),
+    );
+}
+
+async fn get_home_feed(
+    pagination: web::Query<PaginationQuery>,
+    query: web::Query<HomeFeedQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let user_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+    // Feed entries are ordered by when the post or repost happened
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let posts: Collection<Post> = db.collection("posts");
+    let follows: Collection<Follow> = db.collection("follows");
+    let users: Collection<User> = db.collection("users");
+    let subscriptions: Collection<TagSubscription> = db.collection("tag_subscriptions");
+    let tags: Collection<Tag> = db.collection("tags");
+    match feed_service::get_home_feed_service(
+        &posts,
+        &follows,
+        &users,
+        &subscriptions,
+        &tags,
+        user_id,
+        query.include_tags,
+        &pagination,
+    )
+    .await
+    {
+        Ok(feed) => HttpResponse::Ok().json(feed),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Posts carrying the tags the user follows
+async fn get_tag_feed(pagination: web::Query<PaginationQuery>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let user_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let posts: Collection<Post> = db.collection("posts");
+    let follows: Collection<Follow> = db.collection("follows");
+    let users: Collection<User> = db.collection("users");
+    let subscriptions: Collection<TagSubscription> = db.collection("tag_subscriptions");
+    let tags: Collection<Tag> = db.collection("tags");
+    match feed_service::get_tag_feed_service(&posts, &follows, &users, &subscriptions, &tags, user_id, &pagination)
+        .await
+    {
+        Ok(feed) => HttpResponse::Ok().json(feed),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
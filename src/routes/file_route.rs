@@ -1,5 +1,5 @@
 use actix_multipart::Multipart;
-use actix_web::{post, web, HttpResponse, Responder};
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
 use futures::stream::StreamExt;
 use std::{collections::HashMap, fs, path::Path};
 use tokio::{
@@ -8,19 +8,32 @@ use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
 };
 
+use crate::utils::uploads;
+use crate::{handler, Authentication};
+
 // Route configuration
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(upload_chunk);
 }
 
-#[post("/upload_chunk")]
+// Uploads are kept per user, so only the uploader can use them
+#[post("/upload_chunk", wrap = "Authentication::session_only()")]
 async fn upload_chunk(
     mut payload: Multipart,
     web::Query(params): web::Query<HashMap<String, String>>,
+    req: HttpRequest,
 ) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
     let chunk_index: usize = params.get("chunkIndex").unwrap().parse().unwrap();
     let total_chunks: usize = params.get("totalChunks").unwrap().parse().unwrap();
     let upload_id = params.get("uploadId").unwrap();
+    if !uploads::valid_upload_id(upload_id) {
+        return HttpResponse::BadRequest().body("Invalid upload ID");
+    }
+    let upload_id = &uploads::upload_name(&claims.sub, upload_id);
     let mut original_extension = String::new(); // Default extension if not detected
     let mut filename = String::new();
 
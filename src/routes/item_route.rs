@@ -1,21 +1,26 @@
 use crate::database::mongodb::get_database;
-use crate::item::ItemRequest;
+use crate::item::{ItemFilter, ItemRequest, ITEM_SORT_FIELDS};
 use crate::models::item::Item;
+use crate::pagination::PaginationQuery;
 use crate::services::item_service;
 use crate::{handler, Authentication};
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use mongodb::{bson::DateTime, Collection};
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    Collection,
+};
 
 // Route configuration
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/items")
-            .wrap(Authentication)
+            .wrap(Authentication::scoped("items"))
             .route("", web::post().to(create_item))
             .route("", web::get().to(get_items))
             .route("/{id}", web::get().to(get_item))
             .route("/{id}", web::put().to(update_item))
-            .route("/{id}", web::delete().to(delete_item)),
+            .route("/{id}", web::delete().to(delete_item))
+            .route("/{id}/restore", web::post().to(restore_item)),
     );
 }
 
@@ -32,6 +37,7 @@ async fn create_item(json: web::Json<ItemRequest>, req: HttpRequest) -> impl Res
         description: json.clone().description,
         price: json.clone().price,
         stock: json.clone().stock,
+        deleted_at: None,
         created_at: DateTime::now(),
         updated_at: DateTime::now(),
     };
@@ -41,11 +47,19 @@ async fn create_item(json: web::Json<ItemRequest>, req: HttpRequest) -> impl Res
     }
 }
 
-// Handler to get all items
-async fn get_items() -> impl Responder {
+// Handler to get a page of items
+async fn get_items(
+    pagination: web::Query<PaginationQuery>,
+    filter: web::Query<ItemFilter>,
+) -> impl Responder {
+    let pagination = match pagination.resolve(ITEM_SORT_FIELDS) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
     let db = get_database().await;
     let collection: Collection<Item> = db.collection("items");
-    match item_service::get_all_items_service(&collection).await {
+    match item_service::get_all_items_service(&collection, filter.to_document(), &pagination).await {
         Ok(items) => HttpResponse::Ok().json(items),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
@@ -62,22 +76,69 @@ async fn get_item(id: web::Path<String>) -> impl Responder {
     }
 }
 
-// Handler to update an item
-async fn update_item(id: web::Path<String>, item: web::Json<Item>) -> impl Responder {
+// Handler to update an item; owners change their own items, admins any item
+async fn update_item(
+    id: web::Path<String>,
+    item: web::Json<ItemRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if ObjectId::parse_str(id.as_str()).is_err() {
+        return HttpResponse::BadRequest().body("Invalid item ID");
+    }
     let db = get_database().await;
     let collection: Collection<Item> = db.collection("items");
+    match item_service::get_item_by_id_service(&collection, &id).await {
+        Ok(Some(existing)) if existing.is_managed_by(&claims) => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().body("Only the owner can update an item"),
+        Ok(None) => return HttpResponse::NotFound().body("Item not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    }
     match item_service::update_item_service(&collection, &id, item.into_inner()).await {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-// Handler to delete an item
-async fn delete_item(id: web::Path<String>) -> impl Responder {
+// Handler to delete an item; owners trash their own items, admins any item
+async fn delete_item(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if ObjectId::parse_str(id.as_str()).is_err() {
+        return HttpResponse::BadRequest().body("Invalid item ID");
+    }
     let db = get_database().await;
     let collection: Collection<Item> = db.collection("items");
+    match item_service::get_item_by_id_service(&collection, &id).await {
+        Ok(Some(item)) if item.is_managed_by(&claims) => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().body("Only the owner can delete an item"),
+        Ok(None) => return HttpResponse::NotFound().body("Item not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    }
     match item_service::delete_item_service(&collection, &id).await {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
+
+// Handler to take an item out of the current user's trash
+async fn restore_item(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let item_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid item ID"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Item> = db.collection("items");
+    match item_service::restore_item_service(&collection, item_id, &claims.sub).await {
+        Ok(Some(item)) => HttpResponse::Ok().json(item),
+        Ok(None) => HttpResponse::NotFound().body("Item not found in trash"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
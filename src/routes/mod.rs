@@ -6,6 +6,10 @@ pub mod post_route;
 pub mod item_route;
 pub mod tag_route;
 pub mod file_route;
+pub mod search_route;
+pub mod feed_route;
+pub mod trending_route;
+pub mod admin_route;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     auth_route::configure(cfg);
@@ -14,4 +18,8 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     item_route::configure(cfg);
     tag_route::configure(cfg);
     file_route::configure(cfg);
+    search_route::configure(cfg);
+    feed_route::configure(cfg);
+    trending_route::configure(cfg);
+    admin_route::configure(cfg);
 }
\ No newline at end of file
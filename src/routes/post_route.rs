@@ -1,6 +1,17 @@
 use crate::database::mongodb::get_database;
-use crate::post::{Media, Post, PostRequest, PostType};
-use crate::{handler, post_service, Authentication};
+use crate::pagination::{Paginated, PaginationQuery};
+use crate::post::{Media, Post, PostFilter, PostRequest, PostType, POST_SORT_FIELDS};
+use crate::bookmark::{Bookmark, BookmarkFolder, BookmarkRequest};
+use crate::follow::Follow;
+use crate::notification::Notification;
+use crate::repost::Repost;
+use crate::revision::PostRevision;
+use crate::tag::Tag;
+use crate::user::{Role, User};
+use crate::post::Audience;
+use crate::{
+    bookmark_service, handler, post_service, revision_service, viewer_audience, Authentication,
+};
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use mongodb::bson::oid::ObjectId;
 use mongodb::Collection;
@@ -8,81 +19,564 @@ use mongodb::Collection;
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/posts")
-            .wrap(Authentication)
+            .wrap(Authentication::scoped("posts"))
             .route("", web::post().to(create_post))
             .route("", web::get().to(get_posts))
+            .route("/drafts", web::get().to(get_drafts))
             .route("/{id}", web::get().to(get_post))
+            .route("/{id}/thread", web::get().to(get_thread))
+            .route("/{id}/publish", web::post().to(publish_post))
+            .route("/{id}/restore", web::post().to(restore_post))
+            .route("/{id}/revisions", web::get().to(get_revisions))
+            .route("/{id}/revisions/{revision}", web::get().to(get_revision))
+            .route("/{id}/revisions/{revision}/diff", web::get().to(get_revision_diff))
+            .route("/{id}/revisions/{revision}/restore", web::post().to(restore_revision))
+            .route("/{id}/repost", web::put().to(repost_post))
+            .route("/{id}/repost", web::delete().to(undo_repost))
+            .route("/{id}/bookmark", web::put().to(bookmark_post))
+            .route("/{id}/bookmark", web::delete().to(remove_bookmark))
             .route("/{id}", web::put().to(update_post))
             .route("/{id}", web::delete().to(delete_post)),
     );
 }
 
-fn determine_post_type(media: &Option<Vec<Media>>) -> PostType {
-    match media {
-        Some(media_list) if media_list.len() > 1 => PostType::Multiple,
-        Some(_) | None => PostType::Single,
+fn determine_post_type(
+    media: &Option<Vec<Media>>,
+    reply_to: &Option<ObjectId>,
+    quoted_post_id: &Option<ObjectId>,
+) -> PostType {
+    match (media, reply_to, quoted_post_id) {
+        (_, Some(_), _) => PostType::Reply,
+        (_, _, Some(_)) => PostType::Quote,
+        (Some(media_list), _, _) if media_list.len() > 1 => PostType::Multiple,
+        _ => PostType::Single,
+    }
+}
+
+// Parse a referenced post id from the request and make sure the user can see the post
+async fn referenced_post(
+    collection: &Collection<Post>,
+    post_id: &Option<String>,
+    audience: &Audience,
+) -> Result<Option<ObjectId>, HttpResponse> {
+    let post_id = match post_id {
+        Some(post_id) => ObjectId::parse_str(post_id)
+            .map_err(|_| HttpResponse::BadRequest().body("Invalid Post ID"))?,
+        None => return Ok(None),
+    };
+    match post_service::find_visible_post_service(collection, &post_id, audience).await {
+        Ok(Some(_)) => Ok(Some(post_id)),
+        Ok(None) => Err(HttpResponse::NotFound().body("Referenced post not found")),
+        Err(err) => Err(HttpResponse::InternalServerError().body(err.to_string())),
     }
 }
 
 async fn create_post(post: web::Json<PostRequest>, req: HttpRequest) -> impl Responder {
     let db = get_database().await;
     let collection: Collection<Post> = db.collection("posts");
-    let claims = handler(req).await.expect("User not found");
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+    let author_id = audience.viewer_id;
 
-    let author_id = match ObjectId::parse_str(claims.clone().sub) {
+    let reply_to = match referenced_post(&collection, &post.reply_to, &audience).await {
         Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().body("Invalid author_id"),
+        Err(response) => return response,
     };
-    
-    let post_type = determine_post_type(&Some(post.clone().media));
+    let quoted_post_id = match referenced_post(&collection, &post.quoted_post_id, &audience).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let (status, publish_at) = match post.schedule() {
+        Ok(schedule) => schedule,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let post_type = determine_post_type(&Some(post.clone().media), &reply_to, &quoted_post_id);
     let post = Post {
         id: None,
-        author_id: author_id.clone(),
+        author_id,
         content: post.clone().content,
         media: post.clone().media,
         tag_ids: post.clone().tags,
-        post_type: post_type,
+        post_type,
+        reply_to,
+        quoted_post_id,
+        visibility: post.visibility.unwrap_or_default(),
+        status,
+        publish_at,
         ..Default::default()
     };
 
-    match post_service::create_post_service(&collection, post).await {
+    let tags: Collection<Tag> = db.collection("tags");
+    let users: Collection<User> = db.collection("users");
+    let follows: Collection<Follow> = db.collection("follows");
+    let notifications: Collection<Notification> = db.collection("notifications");
+    match post_service::create_post_service(
+        &collection,
+        &tags,
+        &users,
+        &follows,
+        &notifications,
+        post,
+    )
+    .await
+    {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn get_posts() -> impl Responder {
+async fn get_posts(
+    pagination: web::Query<PaginationQuery>,
+    filter: web::Query<PostFilter>,
+    req: HttpRequest,
+) -> impl Responder {
+    let pagination = match pagination.resolve(POST_SORT_FIELDS) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let filter = match filter.to_document() {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
     let db = get_database().await;
     let collection: Collection<Post> = db.collection("posts");
-    match post_service::get_all_posts_service(&collection).await {
+    match post_service::get_all_posts_service(&collection, filter, &audience, &pagination).await {
         Ok(posts) => HttpResponse::Ok().json(posts),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn get_post(id: web::Path<String>) -> impl Responder {
+// Drafts and scheduled posts of the current user
+async fn get_drafts(pagination: web::Query<PaginationQuery>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let author_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid author_id"),
+    };
+    let pagination = match pagination.resolve(&["created_at", "updated_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    match post_service::get_drafts_service(&collection, author_id, &pagination).await {
+        Ok(posts) => HttpResponse::Ok().json(posts),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Publish a draft or scheduled post of the current user right away
+async fn publish_post(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let author_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid author_id"),
+    };
+    let post_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid Post ID"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let notifications: Collection<Notification> = db.collection("notifications");
+    let tags: Collection<Tag> = db.collection("tags");
+    let users: Collection<User> = db.collection("users");
+    let follows: Collection<Follow> = db.collection("follows");
+    match post_service::publish_post_service(
+        &collection,
+        &tags,
+        &users,
+        &follows,
+        &notifications,
+        post_id,
+        author_id,
+    )
+    .await
+    {
+        Ok(Some(_)) => HttpResponse::Ok().json(serde_json::json!({ "published": true })),
+        Ok(None) => HttpResponse::NotFound().body("Draft not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_post(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
     let db = get_database().await;
     let collection: Collection<Post> = db.collection("posts");
-    match post_service::get_post_by_id_service(&collection, &id).await {
+    // Posts the user can't see are reported as missing
+    match post_service::get_post_by_id_service(&collection, &id, &audience).await {
         Ok(Some(post)) => HttpResponse::Ok().json(post),
         Ok(None) => HttpResponse::NotFound().body("Post not found"),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn update_post(id: web::Path<String>, post: web::Json<PostRequest>) -> impl Responder {
+async fn get_thread(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    match post_service::get_thread_service(&collection, &id, &audience).await {
+        Ok(Some(thread)) => HttpResponse::Ok().json(thread),
+        Ok(None) => HttpResponse::NotFound().body("Post not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn update_post(
+    id: web::Path<String>,
+    post: web::Json<PostRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let editor_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+    if post.status.is_some() || post.publish_at.is_some() {
+        if let Err(err) = post.schedule() {
+            return HttpResponse::BadRequest().body(err);
+        }
+    }
+
     let db = get_database().await;
     let collection: Collection<Post> = db.collection("posts");
-    match post_service::update_post_service(&collection, &id, post.into_inner()).await {
+    if let Err(response) = authored_post(&collection, &id, editor_id, "edit").await {
+        return response;
+    }
+    let tags: Collection<Tag> = db.collection("tags");
+    let users: Collection<User> = db.collection("users");
+    let follows: Collection<Follow> = db.collection("follows");
+    let notifications: Collection<Notification> = db.collection("notifications");
+    let revisions: Collection<PostRevision> = db.collection("post_revisions");
+    match post_service::update_post_service(
+        &collection,
+        &tags,
+        &users,
+        &follows,
+        &notifications,
+        &revisions,
+        &id,
+        post.into_inner(),
+        editor_id,
+    )
+    .await
+    {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Authors trash their own posts, admins any post
+async fn delete_post(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let user_id = match ObjectId::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let tags: Collection<Tag> = db.collection("tags");
+    if claims.role != Role::Admin.as_str() {
+        if let Err(response) = authored_post(&collection, &id, user_id, "delete").await {
+            return response;
+        }
+    }
+    match post_service::delete_post_service(&collection, &tags, &id).await {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn delete_post(id: web::Path<String>) -> impl Responder {
+// Take a post out of the current user's trash
+async fn restore_post(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let author_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid author_id"),
+    };
+    let post_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid Post ID"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let tags: Collection<Tag> = db.collection("tags");
+    match post_service::restore_post_service(&collection, &tags, post_id, author_id).await {
+        Ok(Some(_)) => HttpResponse::Ok().json(serde_json::json!({ "restored": true })),
+        Ok(None) => HttpResponse::NotFound().body("Post not found in trash"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// The post, provided `user_id` wrote it
+async fn authored_post(
+    collection: &Collection<Post>,
+    post_id: &str,
+    user_id: ObjectId,
+    action: &str,
+) -> Result<Post, HttpResponse> {
+    let post_id = ObjectId::parse_str(post_id)
+        .map_err(|_| HttpResponse::BadRequest().body("Invalid Post ID"))?;
+    match post_service::find_post_service(collection, &post_id).await {
+        Ok(Some(post)) if post.author_id == user_id => Ok(post),
+        Ok(Some(_)) => {
+            Err(HttpResponse::Forbidden().body(format!("Only the author can {} a post", action)))
+        }
+        Ok(None) => Err(HttpResponse::NotFound().body("Post not found")),
+        Err(err) => Err(HttpResponse::InternalServerError().body(err.to_string())),
+    }
+}
+
+// A post the current user can see and wrote. Revisions keep content and
+// visibility the post has since left behind, so only the author reads them.
+async fn revised_post(
+    collection: &Collection<Post>,
+    post_id: &str,
+    req: HttpRequest,
+    action: &str,
+) -> Result<(ObjectId, Post), HttpResponse> {
+    let (user_id, id) = user_and_post(collection, post_id, req).await?;
+    let post = authored_post(collection, post_id, user_id, action).await?;
+    Ok((id, post))
+}
+
+// Resolve the current user and a post id without checking the post is visible,
+// so reposts and bookmarks can still be removed once the post is hidden or trashed
+async fn user_and_post_id(
+    post_id: &str,
+    req: HttpRequest,
+) -> Result<(ObjectId, ObjectId), HttpResponse> {
+    let claims = handler(req).await?;
+    let user_id = ObjectId::parse_str(&claims.sub)
+        .map_err(|_| HttpResponse::InternalServerError().body("Invalid user ID"))?;
+    let post_id = ObjectId::parse_str(post_id)
+        .map_err(|_| HttpResponse::BadRequest().body("Invalid Post ID"))?;
+    Ok((user_id, post_id))
+}
+
+// Resolve the current user and a post they can see from the request
+async fn user_and_post(
+    collection: &Collection<Post>,
+    post_id: &str,
+    req: HttpRequest,
+) -> Result<(ObjectId, ObjectId), HttpResponse> {
+    let audience = viewer_audience(req).await?;
+    let post_id = ObjectId::parse_str(post_id)
+        .map_err(|_| HttpResponse::BadRequest().body("Invalid Post ID"))?;
+    match post_service::find_visible_post_service(collection, &post_id, &audience).await {
+        Ok(Some(_)) => Ok((audience.viewer_id, post_id)),
+        Ok(None) => Err(HttpResponse::NotFound().body("Post not found")),
+        Err(err) => Err(HttpResponse::InternalServerError().body(err.to_string())),
+    }
+}
+
+async fn repost_post(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let reposts: Collection<Repost> = db.collection("reposts");
+    let (user_id, post_id) = match user_and_post(&collection, &id, req).await {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+    match post_service::repost_post_service(&collection, &reposts, user_id, post_id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "reposted": true })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn undo_repost(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let reposts: Collection<Repost> = db.collection("reposts");
+    let (user_id, post_id) = match user_and_post_id(&id, req).await {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+    match post_service::undo_repost_service(&collection, &reposts, user_id, post_id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "reposted": false })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Bookmarking an already bookmarked post moves it to the requested folder
+async fn bookmark_post(
+    id: web::Path<String>,
+    body: Option<web::Json<BookmarkRequest>>,
+    req: HttpRequest,
+) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let bookmarks: Collection<Bookmark> = db.collection("bookmarks");
+    let folders: Collection<BookmarkFolder> = db.collection("bookmark_folders");
+    let (user_id, post_id) = match user_and_post(&collection, &id, req).await {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+
+    let folder_id = match body.and_then(|b| b.into_inner().folder_id) {
+        Some(folder_id) => match ObjectId::parse_str(folder_id) {
+            Ok(folder_id) => Some(folder_id),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid folder ID"),
+        },
+        None => None,
+    };
+    if let Some(folder_id) = folder_id {
+        match bookmark_service::get_folder_service(&folders, user_id, folder_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::NotFound().body("Folder not found"),
+            Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+        }
+    }
+
+    match bookmark_service::bookmark_post_service(&bookmarks, user_id, post_id, folder_id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "bookmarked": true })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn remove_bookmark(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let bookmarks: Collection<Bookmark> = db.collection("bookmarks");
+    let (user_id, post_id) = match user_and_post_id(&id, req).await {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+    match bookmark_service::remove_bookmark_service(&bookmarks, user_id, post_id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "bookmarked": false })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Previous versions of the user's own post, newest first by default
+async fn get_revisions(
+    id: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let pagination = match pagination.resolve(&["revision"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let revisions: Collection<PostRevision> = db.collection("post_revisions");
+    let post_id = match revised_post(&collection, &id, req, "view the history of").await {
+        Ok((post_id, _)) => post_id,
+        Err(response) => return response,
+    };
+    match revision_service::get_revisions_service(&revisions, post_id, &pagination).await {
+        Ok(page) => HttpResponse::Ok().json(Paginated {
+            data: page
+                .data
+                .into_iter()
+                .map(PostRevision::to_revision)
+                .collect(),
+            next_cursor: page.next_cursor,
+            limit: page.limit,
+        }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_revision(path: web::Path<(String, i32)>, req: HttpRequest) -> impl Responder {
+    let (id, revision) = path.into_inner();
     let db = get_database().await;
     let collection: Collection<Post> = db.collection("posts");
-    match post_service::delete_post_service(&collection, &id).await {
+    let revisions: Collection<PostRevision> = db.collection("post_revisions");
+    let post_id = match revised_post(&collection, &id, req, "view the history of").await {
+        Ok((post_id, _)) => post_id,
+        Err(response) => return response,
+    };
+    match revision_service::find_revision_service(&revisions, post_id, revision).await {
+        Ok(Some(revision)) => HttpResponse::Ok().json(PostRevision::to_revision(revision)),
+        Ok(None) => HttpResponse::NotFound().body("Revision not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// What the edit replacing the revision changed in the content
+async fn get_revision_diff(path: web::Path<(String, i32)>, req: HttpRequest) -> impl Responder {
+    let (id, revision) = path.into_inner();
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let revisions: Collection<PostRevision> = db.collection("post_revisions");
+    let (post_id, post) = match revised_post(&collection, &id, req, "view the history of").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    match revision_service::diff_revision_service(&revisions, &post, post_id, revision).await {
+        Ok(Some(diff)) => HttpResponse::Ok().json(diff),
+        Ok(None) => HttpResponse::NotFound().body("Revision not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Make a previous version current again; the replaced version becomes a new revision
+async fn restore_revision(path: web::Path<(String, i32)>, req: HttpRequest) -> impl Responder {
+    let (id, revision) = path.into_inner();
+    let db = get_database().await;
+    let collection: Collection<Post> = db.collection("posts");
+    let revisions: Collection<PostRevision> = db.collection("post_revisions");
+    let (post_id, post) = match revised_post(&collection, &id, req, "restore").await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let user_id = post.author_id;
+    let revision = match revision_service::find_revision_service(&revisions, post_id, revision).await
+    {
+        Ok(Some(revision)) => revision,
+        Ok(None) => return HttpResponse::NotFound().body("Revision not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    let restored = PostRequest {
+        content: revision.content,
+        media: revision.media,
+        tags: revision.tag_ids,
+        reply_to: None,
+        quoted_post_id: None,
+        visibility: Some(revision.visibility),
+        status: None,
+        publish_at: None,
+    };
+    let tags: Collection<Tag> = db.collection("tags");
+    let users: Collection<User> = db.collection("users");
+    let follows: Collection<Follow> = db.collection("follows");
+    let notifications: Collection<Notification> = db.collection("notifications");
+    match post_service::update_post_service(
+        &collection,
+        &tags,
+        &users,
+        &follows,
+        &notifications,
+        &revisions,
+        &id,
+        restored,
+        user_id,
+    )
+    .await
+    {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
@@ -0,0 +1,39 @@
+use crate::database::mongodb::get_database;
+use crate::pagination::PaginationQuery;
+use crate::search::SearchQuery;
+use crate::{search_service, viewer_audience, Authentication};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/search")
+            .wrap(Authentication::scoped("search"))
+            .route("", web::get().to(search)),
+    );
+}
+
+async fn search(
+    query: web::Query<SearchQuery>,
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return HttpResponse::BadRequest().body("q must not be empty");
+    }
+    let (offset, limit) = match pagination.resolve_offset() {
+        Ok(page) => page,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    match search_service::search_service(&db, q, query.search_type, &audience, offset, limit).await
+    {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -1,19 +1,40 @@
 use crate::database::mongodb::get_database;
-use crate::tag::{Tag, TagRequest};
-use crate::{handler, tag_repository, tag_service, Authentication};
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use mongodb::bson::oid::ObjectId;
+use crate::pagination::PaginationQuery;
+use crate::errors::is_duplicate_key;
+use crate::helps::normalize_tag_name;
+use crate::post::{Post, POST_SORT_FIELDS};
+use crate::tag::{
+    Tag, TagFilter, TagMergeRequest, TagRequest, TagSuggestQuery, TagSuggestRequest, TAG_SORT_FIELDS,
+};
+use crate::tag_subscription::{SubscriptionKind, TagSubscription};
+use crate::{
+    handler, post_service, require_admin, tag_service, tag_subscription_service, viewer_audience,
+    Authentication,
+};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use mongodb::bson::{doc, oid::ObjectId};
 use mongodb::Collection;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/tags")
-            .wrap(Authentication)
+            .wrap(Authentication::scoped("tags"))
             .route("", web::post().to(create_tag))
             .route("", web::get().to(get_tags))
+            .route("/suggest", web::get().to(suggest_tags))
+            .route("/suggest", web::post().to(suggest_tags_for_content))
+            .route("/slug/{slug}", web::get().to(get_tag_by_slug))
+            .route("/{slug}/posts", web::get().to(get_tag_posts))
             .route("/{id}", web::get().to(get_tag))
             .route("/{id}", web::put().to(update_tag))
-            .route("/{id}", web::delete().to(delete_tag)),
+            .route("/{id}", web::delete().to(delete_tag))
+            .route("/{id}/taxonomy", web::get().to(get_tag_taxonomy))
+            .route("/{id}/restore", web::post().to(restore_tag))
+            .route("/{id}/merge", web::post().to(merge_tag))
+            .route("/{id}/follow", web::put().to(follow_tag))
+            .route("/{id}/follow", web::delete().to(unfollow_tag))
+            .route("/{id}/mute", web::put().to(mute_tag))
+            .route("/{id}/mute", web::delete().to(unmute_tag)),
     );
 }
 
@@ -26,24 +47,36 @@ async fn create_tag(tag: web::Json<TagRequest>, req: HttpRequest) -> impl Respon
         Ok(id) => id,
         Err(_) => return HttpResponse::InternalServerError().body("Invalid author_id"),
     };
+    if normalize_tag_name(&tag.name).is_empty() {
+        return HttpResponse::BadRequest().body("Tag name must not be empty");
+    }
 
     let post = Tag {
         id: None,
-        owner_id: owner_id.clone(),
+        owner_id,
         name: tag.name.to_owned(),
         ..Default::default()
     };
 
-    match tag_repository::create_tag(&collection, post).await {
+    match tag_service::create_tag_service(&collection, post).await {
         Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) if is_duplicate_key(&err) => HttpResponse::Conflict().body("Tag already exists"),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn get_tags() -> impl Responder {
+async fn get_tags(
+    pagination: web::Query<PaginationQuery>,
+    filter: web::Query<TagFilter>,
+) -> impl Responder {
+    let pagination = match pagination.resolve(TAG_SORT_FIELDS) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
     let db = get_database().await;
     let collection: Collection<Tag> = db.collection("tags");
-    match tag_service::get_all_tags_service(&collection).await {
+    match tag_service::get_all_tags_service(&collection, filter.to_document(), &pagination).await {
         Ok(tags) => HttpResponse::Ok().json(tags),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
@@ -59,20 +92,275 @@ async fn get_tag(id: web::Path<String>) -> impl Responder {
     }
 }
 
-async fn update_tag(id: web::Path<String>, tag: web::Json<TagRequest>) -> impl Responder {
+// Completions for a tag being typed, favouring the user's own tags
+async fn suggest_tags(query: web::Query<TagSuggestQuery>, req: HttpRequest) -> impl Responder {
+    let limit = match query.resolve_limit() {
+        Ok(limit) => limit,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let claims = handler(req).await.expect("User not found");
+    let user_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+
     let db = get_database().await;
     let collection: Collection<Tag> = db.collection("tags");
+    let posts: Collection<Post> = db.collection("posts");
+    match tag_service::suggest_tags_service(&collection, &posts, user_id, &query.prefix, limit).await {
+        Ok(suggestions) => HttpResponse::Ok().json(suggestions),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Tags for the content of a post being composed
+async fn suggest_tags_for_content(
+    body: web::Json<TagSuggestRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let limit = match body.resolve_limit() {
+        Ok(limit) => limit,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    match tag_service::suggest_tags_for_content_service(&db, &body.content, &audience, limit).await {
+        Ok(suggestions) => HttpResponse::Ok().json(suggestions),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Parent chain, children and synonyms of the tag
+async fn get_tag_taxonomy(id: web::Path<String>) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    let tag = match tag_service::get_tag_by_id_service(&collection, &id).await {
+        Ok(Some(tag)) => tag,
+        Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    };
+    match tag_service::get_taxonomy_service(&collection, tag).await {
+        Ok(taxonomy) => HttpResponse::Ok().json(taxonomy),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Old slugs of renamed or merged tags redirect to the current one
+async fn get_tag_by_slug(slug: web::Path<String>) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    match tag_service::find_tag_by_slug_service(&collection, &slug).await {
+        Ok(Some((tag, true))) => HttpResponse::PermanentRedirect()
+            .insert_header((header::LOCATION, format!("/tags/slug/{}", tag.slug)))
+            .finish(),
+        Ok(Some((tag, false))) => HttpResponse::Ok().json(tag),
+        Ok(None) => HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Posts carrying the tag, visible to the current user
+async fn get_tag_posts(
+    slug: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    let tag = match tag_service::find_tag_by_slug_service(&collection, &slug).await {
+        Ok(Some((tag, true))) => {
+            let query = req.query_string();
+            let location = match query.is_empty() {
+                true => format!("/tags/{}/posts", tag.slug),
+                false => format!("/tags/{}/posts?{}", tag.slug, query),
+            };
+            return HttpResponse::PermanentRedirect()
+                .insert_header((header::LOCATION, location))
+                .finish();
+        }
+        Ok(Some((tag, false))) => tag,
+        Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    // Posts filed under child tags and synonyms belong to the tag too
+    let tag_ids = match tag_service::expand_tag_service(&collection, &tag).await {
+        Ok(ids) => ids,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    let pagination = match pagination.resolve(POST_SORT_FIELDS) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
+    let posts: Collection<Post> = db.collection("posts");
+    let filter = doc! { "tags": { "$in": tag_ids } };
+    match post_service::get_all_posts_service(&posts, filter, &audience, &pagination).await {
+        Ok(posts) => HttpResponse::Ok().json(posts),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Owners rename their own tags, admins any tag
+async fn update_tag(
+    id: web::Path<String>,
+    tag: web::Json<TagRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if normalize_tag_name(&tag.name).is_empty() {
+        return HttpResponse::BadRequest().body("Tag name must not be empty");
+    }
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    match tag_service::get_tag_by_id_service(&collection, &id).await {
+        Ok(Some(existing)) if existing.is_managed_by(&claims) => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().body("Only the owner can rename a tag"),
+        Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    }
     match tag_service::update_tag_service(&collection, &id, tag.into_inner()).await {
         Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) if is_duplicate_key(&err) => HttpResponse::Conflict().body("Tag already exists"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Owners trash their own tags, admins any tag
+async fn delete_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    match tag_service::get_tag_by_id_service(&collection, &id).await {
+        Ok(Some(tag)) if tag.is_managed_by(&claims) => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().body("Only the owner can delete a tag"),
+        Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    }
+    match tag_service::delete_tag_service(&collection, &id).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn restore_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let owner_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid owner_id"),
+    };
+    let tag_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid TAG ID"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    match tag_service::restore_tag_service(&collection, tag_id, owner_id).await {
+        Ok(Some(tag)) => HttpResponse::Ok().json(tag),
+        Ok(None) => HttpResponse::NotFound().body("TAG not found in trash"),
+        Err(err) if is_duplicate_key(&err) => {
+            HttpResponse::Conflict().body("Another tag with this name exists")
+        }
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
-async fn delete_tag(id: web::Path<String>) -> impl Responder {
+// Merge the tag into another one; its posts are moved over and it is removed.
+// It rewrites every post with the tag, so only admins may.
+async fn merge_tag(
+    id: web::Path<String>,
+    body: web::Json<TagMergeRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = require_admin(req).await {
+        return response;
+    }
+    if id.as_str() == body.into.as_str() {
+        return HttpResponse::BadRequest().body("A tag can't be merged into itself");
+    }
     let db = get_database().await;
     let collection: Collection<Tag> = db.collection("tags");
-    match tag_repository::delete_tag(&collection, &id).await {
+    let posts: Collection<Post> = db.collection("posts");
+    let subscriptions: Collection<TagSubscription> = db.collection("tag_subscriptions");
+
+    let mut tags = vec![];
+    for tag_id in [id.as_str(), body.into.as_str()] {
+        match tag_service::get_tag_by_id_service(&collection, tag_id).await {
+            Ok(Some(tag)) => tags.push(tag),
+            Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+            Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+        }
+    }
+    let busy = tags[1].merging_into.is_some()
+        || tags[0].merging_into.is_some_and(|target_id| Some(target_id) != tags[1].id);
+    if busy {
+        return HttpResponse::Conflict().body("One of the tags is being merged into another tag");
+    }
+    match tag_service::merge_tags_service(&collection, &posts, &subscriptions, &tags[0], &tags[1]).await {
         Ok(result) => HttpResponse::Ok().json(result),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
+
+async fn follow_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_subscription(id.as_str(), req, SubscriptionKind::Follow, true).await
+}
+
+async fn unfollow_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_subscription(id.as_str(), req, SubscriptionKind::Follow, false).await
+}
+
+async fn mute_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_subscription(id.as_str(), req, SubscriptionKind::Mute, true).await
+}
+
+async fn unmute_tag(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_subscription(id.as_str(), req, SubscriptionKind::Mute, false).await
+}
+
+// Following a tag unmutes it and the other way round
+async fn change_subscription(
+    id: &str,
+    req: HttpRequest,
+    kind: SubscriptionKind,
+    subscribe: bool,
+) -> HttpResponse {
+    let claims = handler(req).await.expect("User not found");
+    let user_id = match ObjectId::parse_str(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Tag> = db.collection("tags");
+    let subscriptions: Collection<TagSubscription> = db.collection("tag_subscriptions");
+    let tag_id = match tag_service::get_tag_by_id_service(&collection, id).await {
+        Ok(Some(tag)) => tag.id.unwrap(),
+        Ok(None) => return HttpResponse::NotFound().body("TAG not found"),
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    };
+
+    let result = if subscribe {
+        tag_subscription_service::subscribe_tag_service(&subscriptions, user_id, tag_id, kind).await
+    } else {
+        tag_subscription_service::unsubscribe_tag_service(&subscriptions, user_id, tag_id, kind).await
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ kind.as_str(): subscribe })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -0,0 +1,53 @@
+use crate::database::mongodb::get_database;
+use crate::post::Post;
+use crate::tag::Tag;
+use crate::trending::{TrendingQuery, TrendingSnapshot};
+use crate::{trending_service, viewer_audience, Authentication};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::Collection;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/trending")
+            .wrap(Authentication::scoped("posts"))
+            .route("/tags", web::get().to(get_trending_tags))
+            .route("/posts", web::get().to(get_trending_posts)),
+    );
+}
+
+// Rankings come from the snapshot of the last trending job run
+async fn get_trending_tags(query: web::Query<TrendingQuery>) -> impl Responder {
+    let limit = match query.resolve_limit() {
+        Ok(limit) => limit,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let snapshots: Collection<TrendingSnapshot> = db.collection("trending");
+    let tags: Collection<Tag> = db.collection("tags");
+    match trending_service::get_trending_tags_service(&snapshots, &tags, query.window, limit).await {
+        Ok(trending) => HttpResponse::Ok().json(trending),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_trending_posts(query: web::Query<TrendingQuery>, req: HttpRequest) -> impl Responder {
+    let limit = match query.resolve_limit() {
+        Ok(limit) => limit,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let snapshots: Collection<TrendingSnapshot> = db.collection("trending");
+    let posts: Collection<Post> = db.collection("posts");
+    match trending_service::get_trending_posts_service(&snapshots, &posts, query.window, limit, &audience)
+        .await
+    {
+        Ok(trending) => HttpResponse::Ok().json(trending),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -1,11 +1,88 @@
+use actix_session::Session;
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::bson::{oid::ObjectId, DateTime};
 use mongodb::Collection;
 
-use crate::{get_database, handler, user::User, user_service, Authentication};
+use crate::access_token::{AccessToken, AccessTokenRequest};
+use crate::bookmark::{Bookmark, BookmarkFilter, BookmarkFolder, BookmarkFolderRequest};
+use crate::email::EmailLog;
+use crate::follow::Follow;
+use crate::notification::Notification;
+use crate::passkey::{Passkey, PasskeyRegistrationRequest, PasskeyRenameRequest, WebauthnChallenge};
+use crate::pagination::{Paginated, PaginationQuery};
+use crate::password::{ChangePasswordRequest, PasswordReset};
+use crate::item::Item;
+use crate::post::Post;
+use crate::tag::Tag;
+use crate::tag_subscription::{SubscriptionKind, TagSubscription};
+use crate::trash::{TrashQuery, TrashType};
+use crate::two_factor::{RecoveryCodesResponse, TwoFactorCodeRequest};
+use crate::user::{
+    validate_password, AvatarRequest, PrivacyUpdateRequest, ProfileUpdateRequest, Status,
+};
+use crate::{
+    access_token_service, auth_service, bookmark_service, follow_service, get_database, handler,
+    item_service, notification_service, passkey_service, post_service, tag_service,
+    tag_subscription_service, two_factor_service, user::User, user_service, viewer_audience,
+    Authentication,
+};
 
 // Function to configure user routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/users").wrap(Authentication).route("/me", web::get().to(get_user)));
+    cfg.service(
+        web::scope("/users")
+            .wrap(Authentication::scoped("users"))
+            .route("/me", web::get().to(get_user))
+            .route("/me", web::patch().to(update_profile))
+            .route("/me/privacy", web::patch().to(update_privacy))
+            .route("/me/email/verification", web::post().to(resend_verification))
+            .route("/me/password", web::put().to(change_password))
+            .route("/me/2fa/setup", web::post().to(start_two_factor_setup))
+            .route("/me/2fa/confirm", web::post().to(confirm_two_factor_setup))
+            .route("/me/2fa", web::delete().to(disable_two_factor))
+            .route("/me/passkeys", web::get().to(get_passkeys))
+            .route("/me/tokens", web::get().to(get_access_tokens))
+            .route("/me/tokens", web::post().to(create_access_token))
+            .route("/me/tokens/{id}", web::delete().to(delete_access_token))
+            .route("/me/passkeys/register/start", web::post().to(start_passkey_registration))
+            .route("/me/passkeys/register/finish", web::post().to(finish_passkey_registration))
+            .route("/me/passkeys/{id}", web::patch().to(rename_passkey))
+            .route("/me/passkeys/{id}", web::delete().to(delete_passkey))
+            .route("/me/avatar", web::put().to(set_avatar))
+            .route("/me/avatar", web::delete().to(remove_avatar))
+            .route("/me/bookmarks", web::get().to(get_bookmarks))
+            .route("/me/bookmarks/folders", web::get().to(get_bookmark_folders))
+            .route("/me/bookmarks/folders", web::post().to(create_bookmark_folder))
+            .route("/me/bookmarks/folders/{id}", web::delete().to(delete_bookmark_folder))
+            .route("/me/notifications", web::get().to(get_notifications))
+            .route("/me/notifications/read", web::post().to(mark_notifications_read))
+            .route("/me/trash", web::get().to(get_trash))
+            .route("/me/tags/followed", web::get().to(get_followed_tags))
+            .route("/me/tags/muted", web::get().to(get_muted_tags))
+            .route("/me/close-friends", web::get().to(get_close_friends))
+            .route("/me/close-friends/{id}", web::put().to(add_close_friend))
+            .route("/me/close-friends/{id}", web::delete().to(remove_close_friend))
+            .route("/{username}", web::get().to(get_profile))
+            .route("/{id}/follow", web::put().to(follow_user))
+            .route("/{id}/follow", web::delete().to(unfollow_user)),
+    );
+}
+
+// Id of the authenticated user
+async fn current_user_id(req: HttpRequest) -> Result<ObjectId, HttpResponse> {
+    let claims = handler(req).await?;
+    ObjectId::parse_str(claims.sub)
+        .map_err(|_| HttpResponse::InternalServerError().body("Invalid user_id"))
+}
+
+// The authenticated user's account
+async fn current_user(req: HttpRequest, collection: &Collection<User>) -> Result<User, HttpResponse> {
+    let claims = handler(req).await?;
+    match user_service::get_user_by_id_service(collection, &claims.sub).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().body("User not found")),
+        Err(err) => Err(HttpResponse::InternalServerError().body(err.to_string())),
+    }
 }
 
 // Handler to get an user
@@ -20,3 +97,648 @@ async fn get_user(req: HttpRequest) -> impl Responder {
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
+
+async fn update_profile(body: web::Json<ProfileUpdateRequest>, req: HttpRequest) -> impl Responder {
+    let update = match body.into_inner().validate() {
+        Ok(update) => update,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let claims = handler(req).await.expect("User not found");
+
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let user = match user_service::get_user_by_id_service(&collection, &claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    match user_service::update_profile_service(&collection, &user, update).await {
+        Ok(Ok(user)) => HttpResponse::Ok().json(User::to_user(user)),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Choose which optional profile fields others see
+async fn update_privacy(body: web::Json<PrivacyUpdateRequest>, req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if body.to_document().is_empty() {
+        return HttpResponse::BadRequest().body("Nothing to update");
+    }
+
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match user_service::update_privacy_service(&collection, user_id, &body).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(User::to_user(user)),
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Send the verification email again, within the per-user email limits
+async fn resend_verification(req: HttpRequest) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let email_logs: Collection<EmailLog> = db.collection("email_logs");
+    let user = match user_service::get_user_by_id_service(&collection, &claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    if user.is_verified {
+        return HttpResponse::Conflict().body("Email is already verified");
+    }
+    match auth_service::send_verification_service(&email_logs, &user).await {
+        Ok(true) => HttpResponse::Accepted().body("Verification email sent"),
+        Ok(false) => HttpResponse::TooManyRequests().body("Too many verification emails, try again later"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Signs out every other session of the user
+async fn change_password(
+    body: web::Json<ChangePasswordRequest>,
+    req: HttpRequest,
+    session: Session,
+) -> impl Responder {
+    let claims = match handler(req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let resets: Collection<PasswordReset> = db.collection("password_resets");
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    let user = match user_service::get_user_by_id_service(&collection, &claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    if let Err(reason) = validate_password(&body.new_password, &user.username) {
+        return HttpResponse::BadRequest().body(reason);
+    }
+    match auth_service::change_password_service(
+        &collection,
+        &resets,
+        &tokens,
+        &user,
+        &body.current_password,
+        &body.new_password,
+        session,
+    )
+    .await
+    {
+        Ok(Ok(token)) => HttpResponse::Ok().json(serde_json::json!({ "access_token": token })),
+        Ok(Err(reason)) => HttpResponse::Forbidden().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// New TOTP secret to add to an authenticator app; enabled once confirmed
+async fn start_two_factor_setup(req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match two_factor_service::start_setup_service(&collection, &user).await {
+        Ok(Ok(setup)) => HttpResponse::Ok().json(setup),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn confirm_two_factor_setup(body: web::Json<TwoFactorCodeRequest>, req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match two_factor_service::confirm_setup_service(&collection, &user, &body.code).await {
+        Ok(Ok(recovery_codes)) => HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Takes a current TOTP or recovery code
+async fn disable_two_factor(body: web::Json<TwoFactorCodeRequest>, req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if !user.two_factor.enabled {
+        return HttpResponse::Conflict().body("Two-factor authentication is not enabled");
+    }
+    match two_factor_service::disable_service(&collection, &user, &body.code).await {
+        Ok(Ok(())) => HttpResponse::Ok().body("Two-factor authentication disabled"),
+        Ok(Err(reason)) => HttpResponse::Forbidden().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_passkeys(req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let db = get_database().await;
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    match passkey_service::get_passkeys_service(&passkeys, user_id).await {
+        Ok(passkeys) => HttpResponse::Ok().json(passkeys),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Options for the browser's prompt to create a passkey
+async fn start_passkey_registration(req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    let challenges: Collection<WebauthnChallenge> = db.collection("webauthn_challenges");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match passkey_service::start_registration_service(&passkeys, &challenges, &user).await {
+        Ok(Ok(options)) => HttpResponse::Ok().json(options),
+        Ok(Err(reason)) => HttpResponse::Conflict().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn finish_passkey_registration(
+    body: web::Json<PasskeyRegistrationRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    let challenges: Collection<WebauthnChallenge> = db.collection("webauthn_challenges");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match passkey_service::finish_registration_service(&passkeys, &challenges, &user, body.into_inner())
+        .await
+    {
+        Ok(Ok(passkey)) => HttpResponse::Created().json(passkey),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn rename_passkey(
+    id: web::Path<String>,
+    body: web::Json<PasskeyRenameRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let passkey_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid passkey ID"),
+    };
+    let db = get_database().await;
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    match passkey_service::rename_passkey_service(&passkeys, user_id, passkey_id, &body.name).await {
+        Ok(Ok(Some(passkey))) => HttpResponse::Ok().json(passkey),
+        Ok(Ok(None)) => HttpResponse::NotFound().body("Passkey not found"),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn delete_passkey(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let passkey_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid passkey ID"),
+    };
+    let db = get_database().await;
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    match passkey_service::delete_passkey_service(&passkeys, user_id, passkey_id).await {
+        Ok(true) => HttpResponse::Ok().body("Passkey deleted"),
+        Ok(false) => HttpResponse::NotFound().body("Passkey not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_access_tokens(req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let db = get_database().await;
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    match access_token_service::get_tokens_service(&tokens, user_id).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// The token is in this response only; keep it somewhere safe
+async fn create_access_token(
+    body: web::Json<AccessTokenRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    let user = match current_user(req, &collection).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    match access_token_service::create_token_service(&tokens, &user, body.into_inner()).await {
+        Ok(Ok(created)) => HttpResponse::Created().json(created),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn delete_access_token(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let token_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid token ID"),
+    };
+    let db = get_database().await;
+    let tokens: Collection<AccessToken> = db.collection("access_tokens");
+    match access_token_service::delete_token_service(&tokens, user_id, token_id).await {
+        Ok(true) => HttpResponse::Ok().body("Token deleted"),
+        Ok(false) => HttpResponse::NotFound().body("Token not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Use a file uploaded through /upload_chunk as the avatar
+async fn set_avatar(body: web::Json<AvatarRequest>, req: HttpRequest) -> impl Responder {
+    change_avatar(req, Some(body.upload_id.as_str())).await
+}
+
+async fn remove_avatar(req: HttpRequest) -> impl Responder {
+    change_avatar(req, None).await
+}
+
+async fn change_avatar(req: HttpRequest, upload_id: Option<&str>) -> HttpResponse {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match user_service::set_avatar_service(&collection, user_id, upload_id).await {
+        Ok(Ok(user)) => HttpResponse::Ok().json(User::to_user(user)),
+        Ok(Err(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Public profile of a user
+async fn get_profile(username: web::Path<String>) -> impl Responder {
+    let db = get_database().await;
+    let collection: Collection<User> = db.collection("users");
+    match user_service::get_user_by_username_service(&collection, &username).await {
+        Ok(Some(user)) if user.status != Status::Deleted => {
+            HttpResponse::Ok().json(User::to_public(user))
+        }
+        Ok(_) => HttpResponse::NotFound().body("User not found"),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Handler to list the user's bookmarks, optionally within a folder
+async fn get_bookmarks(
+    pagination: web::Query<PaginationQuery>,
+    filter: web::Query<BookmarkFilter>,
+    req: HttpRequest,
+) -> impl Responder {
+    let audience = match viewer_audience(req).await {
+        Ok(audience) => audience,
+        Err(response) => return response,
+    };
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+    let filter = match filter.to_document(audience.viewer_id) {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let bookmarks: Collection<Bookmark> = db.collection("bookmarks");
+    let posts: Collection<Post> = db.collection("posts");
+    // Bookmarked posts that are no longer visible to the user are left out
+    match bookmark_service::get_bookmarks_service(&bookmarks, &posts, filter, &audience, &pagination)
+        .await
+    {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_bookmark_folders(req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let collection: Collection<BookmarkFolder> = db.collection("bookmark_folders");
+    match bookmark_service::get_folders_service(&collection, user_id).await {
+        Ok(folders) => HttpResponse::Ok().json(
+            folders
+                .into_iter()
+                .map(BookmarkFolder::to_folder)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn create_bookmark_folder(
+    folder: web::Json<BookmarkFolderRequest>,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let name = folder.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Folder name must not be empty");
+    }
+
+    let db = get_database().await;
+    let collection: Collection<BookmarkFolder> = db.collection("bookmark_folders");
+    let folder = BookmarkFolder {
+        id: None,
+        user_id,
+        name: name.to_string(),
+        created_at: DateTime::now(),
+    };
+    match bookmark_service::create_folder_service(&collection, folder).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Bookmarks in the deleted folder are kept as unfiled
+async fn delete_bookmark_folder(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let folder_id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid folder ID"),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<BookmarkFolder> = db.collection("bookmark_folders");
+    let bookmarks: Collection<Bookmark> = db.collection("bookmarks");
+    match bookmark_service::delete_folder_service(&collection, &bookmarks, user_id, folder_id).await
+    {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_notifications(
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Notification> = db.collection("notifications");
+    match notification_service::get_notifications_service(&collection, user_id, &pagination).await {
+        Ok(page) => HttpResponse::Ok().json(Paginated {
+            data: page
+                .data
+                .into_iter()
+                .map(Notification::to_notification)
+                .collect(),
+            next_cursor: page.next_cursor,
+            limit: page.limit,
+        }),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn mark_notifications_read(req: HttpRequest) -> impl Responder {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let db = get_database().await;
+    let collection: Collection<Notification> = db.collection("notifications");
+    match notification_service::mark_all_read_service(&collection, user_id).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+// Posts, items or tags the user deleted that haven't been purged yet
+async fn get_trash(
+    query: web::Query<TrashQuery>,
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = handler(req).await.expect("User not found");
+    let user_id = match ObjectId::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().body("Invalid user_id"),
+    };
+    let pagination = match pagination.resolve(&["deleted_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let result = match query.trash_type {
+        TrashType::Posts => {
+            let collection: Collection<Post> = db.collection("posts");
+            post_service::get_deleted_posts_service(&collection, user_id, &pagination)
+                .await
+                .map(|page| HttpResponse::Ok().json(page))
+        }
+        TrashType::Items => {
+            let collection: Collection<Item> = db.collection("items");
+            item_service::get_deleted_items_service(&collection, &claims.sub, &pagination)
+                .await
+                .map(|page| HttpResponse::Ok().json(page))
+        }
+        TrashType::Tags => {
+            let collection: Collection<Tag> = db.collection("tags");
+            tag_service::get_deleted_tags_service(&collection, user_id, &pagination)
+                .await
+                .map(|page| HttpResponse::Ok().json(page))
+        }
+    };
+    result.unwrap_or_else(|err| HttpResponse::InternalServerError().body(err.to_string()))
+}
+
+async fn get_followed_tags(pagination: web::Query<PaginationQuery>, req: HttpRequest) -> impl Responder {
+    get_subscribed_tags(pagination, req, SubscriptionKind::Follow).await
+}
+
+async fn get_muted_tags(pagination: web::Query<PaginationQuery>, req: HttpRequest) -> impl Responder {
+    get_subscribed_tags(pagination, req, SubscriptionKind::Mute).await
+}
+
+async fn get_subscribed_tags(
+    pagination: web::Query<PaginationQuery>,
+    req: HttpRequest,
+    kind: SubscriptionKind,
+) -> HttpResponse {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let pagination = match pagination.resolve(&["created_at"]) {
+        Ok(pagination) => pagination,
+        Err(err) => return HttpResponse::BadRequest().body(err),
+    };
+
+    let db = get_database().await;
+    let subscriptions: Collection<TagSubscription> = db.collection("tag_subscriptions");
+    let tags: Collection<Tag> = db.collection("tags");
+    match tag_subscription_service::get_subscribed_tags_service(&subscriptions, &tags, user_id, kind, &pagination)
+        .await
+    {
+        Ok(subscriptions) => HttpResponse::Ok().json(subscriptions),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn get_close_friends(req: HttpRequest) -> impl Responder {
+    let db = get_database().await;
+    let claims = handler(req).await.expect("User not found");
+
+    let collection: Collection<User> = db.collection("users");
+    let user = match user_service::get_user_by_id_service(&collection, &claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    match user_service::get_users_by_ids_service(&collection, &user.close_friend_ids).await {
+        Ok(users) => {
+            HttpResponse::Ok().json(users.into_iter().map(User::to_public).collect::<Vec<_>>())
+        }
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn add_close_friend(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_close_friend(id.as_str(), req, true).await
+}
+
+async fn remove_close_friend(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_close_friend(id.as_str(), req, false).await
+}
+
+// Close friends can read the user's posts with `close_friends` visibility
+async fn change_close_friend(id: &str, req: HttpRequest, add: bool) -> HttpResponse {
+    let user_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let friend_id = match ObjectId::parse_str(id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
+    };
+    if user_id == friend_id {
+        return HttpResponse::BadRequest().body("Users can't add themselves as close friends");
+    }
+
+    let db = get_database().await;
+    let users: Collection<User> = db.collection("users");
+    match user_service::get_user_by_id_service(&users, id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    }
+
+    let result = if add {
+        user_service::add_close_friend_service(&users, user_id, friend_id).await
+    } else {
+        user_service::remove_close_friend_service(&users, user_id, friend_id).await
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "close_friend": add })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+async fn follow_user(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_follow(id.as_str(), req, true).await
+}
+
+async fn unfollow_user(id: web::Path<String>, req: HttpRequest) -> impl Responder {
+    change_follow(id.as_str(), req, false).await
+}
+
+async fn change_follow(id: &str, req: HttpRequest, follow: bool) -> HttpResponse {
+    let follower_id = match current_user_id(req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let following_id = match ObjectId::parse_str(id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
+    };
+    if follower_id == following_id {
+        return HttpResponse::BadRequest().body("Users can't follow themselves");
+    }
+
+    let db = get_database().await;
+    let follows: Collection<Follow> = db.collection("follows");
+    let users: Collection<User> = db.collection("users");
+    match user_service::get_user_by_id_service(&users, id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    }
+
+    let result = if follow {
+        follow_service::follow_user_service(&follows, &users, follower_id, following_id).await
+    } else {
+        follow_service::unfollow_user_service(&follows, &users, follower_id, following_id).await
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "following": follow })),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
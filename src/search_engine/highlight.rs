@@ -0,0 +1,74 @@
+// Characters kept before the first match so the snippet has some context
+const CONTEXT_BEFORE: usize = 60;
+const SNIPPET_LENGTH: usize = 200;
+
+// Build an HTML snippet of `text` around the first match, wrapping every word
+// that starts with one of `terms` in <mark>. The text itself is HTML escaped.
+pub fn highlight(text: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let words = words(&chars);
+    let is_match = |(start, end): &(usize, usize)| {
+        let word: String = chars[*start..*end]
+            .iter()
+            .collect::<String>()
+            .to_lowercase();
+        terms.iter().any(|term| word.starts_with(term.as_str()))
+    };
+
+    let first = words.iter().find(|w| is_match(w)).map(|w| w.0).unwrap_or(0);
+    let start = first.saturating_sub(CONTEXT_BEFORE);
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = start;
+    for word in words
+        .iter()
+        .filter(|w| w.0 >= start && w.1 <= end && is_match(w))
+    {
+        push_escaped(&mut snippet, &chars[position..word.0]);
+        snippet.push_str("<mark>");
+        push_escaped(&mut snippet, &chars[word.0..word.1]);
+        snippet.push_str("</mark>");
+        position = word.1;
+    }
+    push_escaped(&mut snippet, &chars[position..end]);
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+// Char ranges of the alphanumeric words in `chars`
+fn words(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in chars.iter().enumerate() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, chars.len()));
+    }
+    words
+}
+
+fn push_escaped(out: &mut String, chars: &[char]) {
+    for c in chars {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(*c),
+        }
+    }
+}
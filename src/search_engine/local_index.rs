@@ -0,0 +1,132 @@
+use std::sync::{Mutex, OnceLock};
+
+use mongodb::bson::oid::ObjectId;
+use tantivy::{
+    collector::{Count, TopDocs},
+    directory::MmapDirectory,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, TantivyDocument, Term,
+};
+
+use crate::{
+    get_config,
+    models::search::{SearchDocument, SearchType},
+};
+
+const WRITER_MEMORY_BUDGET: usize = 50_000_000;
+
+static LOCAL_INDEX: OnceLock<LocalIndex> = OnceLock::new();
+
+// Embedded tantivy index used when SEARCH_BACKEND=local
+pub struct LocalIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    id: Field,
+    kind: Field,
+    title: Field,
+    body: Field,
+}
+
+pub fn local_index() -> &'static LocalIndex {
+    LOCAL_INDEX.get_or_init(|| {
+        LocalIndex::open(&get_config().search_index_path).expect("Failed to open search index")
+    })
+}
+
+impl LocalIndex {
+    fn open(path: &str) -> tantivy::Result<LocalIndex> {
+        let mut builder = Schema::builder();
+        let id = builder.add_text_field("id", STRING | STORED);
+        let kind = builder.add_text_field("type", STRING | STORED);
+        let title = builder.add_text_field("title", TEXT | STORED);
+        let body = builder.add_text_field("body", TEXT | STORED);
+
+        std::fs::create_dir_all(path)?;
+        let index = Index::open_or_create(MmapDirectory::open(path)?, builder.build())?;
+        let reader = index.reader()?;
+        let writer = index.writer(WRITER_MEMORY_BUDGET)?;
+
+        Ok(LocalIndex {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            id,
+            kind,
+            title,
+            body,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.searcher().num_docs() == 0
+    }
+
+    // Add or replace documents, keyed by their id
+    pub fn upsert(&self, documents: &[SearchDocument]) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().expect("search index writer poisoned");
+        for document in documents {
+            let id = document.id.to_hex();
+            writer.delete_term(Term::from_field_text(self.id, &id));
+            writer.add_document(doc!(
+                self.id => id,
+                self.kind => document.search_type.as_str(),
+                self.title => document.title.as_str(),
+                self.body => document.body.as_str(),
+            ))?;
+        }
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    pub fn remove(&self, id: ObjectId) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().expect("search index writer poisoned");
+        writer.delete_term(Term::from_field_text(self.id, &id.to_hex()));
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    // Drop every document, used before a full rebuild
+    pub fn clear(&self) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().expect("search index writer poisoned");
+        writer.delete_all_documents()?;
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    // Every match, best first. The index doesn't know who may read a post, so
+    // callers filter the hits before counting and paging them.
+    pub fn search(&self, query: &str) -> tantivy::Result<Vec<(f64, SearchDocument)>> {
+        let mut parser = QueryParser::for_index(&self.index, vec![self.title, self.body]);
+        parser.set_field_boost(self.title, 2.0);
+        let (query, _) = parser.parse_query_lenient(query);
+
+        let searcher = self.reader.searcher();
+        let matches = searcher.search(&query, &Count)?;
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(matches.max(1)))?;
+
+        let mut hits = vec![];
+        for (score, address) in top_docs {
+            let stored: TantivyDocument = searcher.doc(address)?;
+            let text = |field: Field| {
+                stored
+                    .get_first(field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+            };
+            let (Some(search_type), Ok(id)) = (
+                SearchType::parse(text(self.kind)),
+                ObjectId::parse_str(text(self.id)),
+            ) else {
+                continue;
+            };
+            hits.push((
+                score as f64,
+                SearchDocument::new(search_type, id, text(self.title), text(self.body)),
+            ));
+        }
+        Ok(hits)
+    }
+}
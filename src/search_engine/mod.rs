@@ -0,0 +1,51 @@
+pub mod highlight;
+pub mod local_index;
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::{get_config, models::search::SearchDocument};
+use local_index::local_index;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchBackend {
+    MongoDb,
+    Local,
+}
+
+pub fn search_backend() -> SearchBackend {
+    match get_config().search_backend.as_str() {
+        "local" => SearchBackend::Local,
+        _ => SearchBackend::MongoDb,
+    }
+}
+
+// Keep the local index in sync after a create or update.
+// MongoDB text indexes are maintained by the database itself.
+pub async fn index_document(document: SearchDocument) {
+    if search_backend() == SearchBackend::Local {
+        let result = tokio::task::spawn_blocking(move || local_index().upsert(&[document])).await;
+        log_failure(
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string())),
+        );
+    }
+}
+
+pub async fn remove_document(id: ObjectId) {
+    if search_backend() == SearchBackend::Local {
+        let result = tokio::task::spawn_blocking(move || local_index().remove(id)).await;
+        log_failure(
+            result
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string())),
+        );
+    }
+}
+
+// Indexing failures must not fail the write that triggered them
+fn log_failure(result: Result<(), String>) {
+    if let Err(err) = result {
+        println!("Failed to update search index: {}", err);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::models::access_token::{
+    AccessToken, AccessTokenRequest, AccessTokenResponse, CreatedAccessTokenResponse,
+    ACCESS_TOKEN_MAX_DAYS, ACCESS_TOKEN_MAX_PER_USER, ACCESS_TOKEN_NAME_MAX_LENGTH,
+    ACCESS_TOKEN_PREFIX, ACCESS_TOKEN_SCOPES,
+};
+use crate::models::user::User;
+use crate::repositories::access_token_repository;
+use crate::utils::helps::{now_rfc3339, seconds_ago_rfc3339, seconds_from_now_rfc3339};
+use crate::utils::random_token::{generate_token, hash_token};
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// How often last_used_at is brought up to date
+const LAST_USED_PRECISION_SECS: i64 = 60;
+
+// Create a token for the user. The token itself is only in this response. The
+// inner error is a request the rules refuse.
+pub async fn create_token_service(
+    collection: &Collection<AccessToken>,
+    user: &User,
+    req: AccessTokenRequest,
+) -> Result<Result<CreatedAccessTokenResponse, String>, Error> {
+    let name = req.name.trim();
+    if name.is_empty() || name.chars().count() > ACCESS_TOKEN_NAME_MAX_LENGTH {
+        return Ok(Err(format!(
+            "Name must be 1 to {} characters",
+            ACCESS_TOKEN_NAME_MAX_LENGTH
+        )));
+    }
+    if req.scopes.is_empty() {
+        return Ok(Err("At least one scope is required".to_string()));
+    }
+    if let Some(scope) = req
+        .scopes
+        .iter()
+        .find(|scope| !ACCESS_TOKEN_SCOPES.contains(&scope.as_str()))
+    {
+        return Ok(Err(format!("Unknown scope: {}", scope)));
+    }
+    let mut scopes = req.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+    let expires_at = match req.expires_in_days {
+        Some(days) if !(1..=ACCESS_TOKEN_MAX_DAYS).contains(&days) => {
+            return Ok(Err(format!(
+                "Tokens expire in 1 to {} days",
+                ACCESS_TOKEN_MAX_DAYS
+            )));
+        }
+        Some(days) => Some(seconds_from_now_rfc3339(days * 24 * 60 * 60)),
+        None => None,
+    };
+    if access_token_repository::count_user_tokens(collection, user.id).await? as usize
+        >= ACCESS_TOKEN_MAX_PER_USER
+    {
+        return Ok(Err(format!(
+            "No more than {} tokens per account",
+            ACCESS_TOKEN_MAX_PER_USER
+        )));
+    }
+
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token());
+    let access_token = AccessToken {
+        id: None,
+        user_id: user.id,
+        name: name.to_string(),
+        token_hash: hash_token(&token),
+        prefix: token.chars().take(ACCESS_TOKEN_PREFIX.len() + 8).collect(),
+        scopes,
+        token_version: user.token_version,
+        expires_at,
+        last_used_at: None,
+        created_at: now_rfc3339(),
+    };
+    let id = access_token_repository::create_token(collection, access_token.clone()).await?;
+    Ok(Ok(CreatedAccessTokenResponse {
+        token,
+        access_token: AccessToken::to_access_token(AccessToken {
+            id: Some(id),
+            ..access_token
+        }),
+    }))
+}
+
+pub async fn get_tokens_service(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+) -> Result<Vec<AccessTokenResponse>, Error> {
+    let tokens = access_token_repository::get_user_tokens(collection, user_id).await?;
+    Ok(tokens
+        .into_iter()
+        .map(AccessToken::to_access_token)
+        .collect())
+}
+
+pub async fn delete_token_service(
+    collection: &Collection<AccessToken>,
+    user_id: ObjectId,
+    token_id: ObjectId,
+) -> Result<bool, Error> {
+    access_token_repository::delete_token(collection, user_id, token_id).await
+}
+
+// The unexpired token a request presented, noting that it was used
+pub async fn authenticate_token_service(
+    collection: &Collection<AccessToken>,
+    token: &str,
+) -> Result<Option<AccessToken>, Error> {
+    if !token.starts_with(ACCESS_TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let access_token =
+        match access_token_repository::find_active_token(collection, &hash_token(token)).await? {
+            Some(access_token) => access_token,
+            None => return Ok(None),
+        };
+    access_token_repository::touch_token(
+        collection,
+        access_token.id.unwrap(),
+        &seconds_ago_rfc3339(LAST_USED_PRECISION_SECS),
+    )
+    .await?;
+    Ok(Some(access_token))
+}
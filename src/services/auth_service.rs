@@ -1,51 +1,477 @@
+use crate::access_token::AccessToken;
+use crate::email::{EmailLog, EmailPurpose};
 use crate::jwt::create_jwt;
-use crate::models::user::{LoginRequest, RegisterRequest, User};
-use crate::repositories::user_repository;
+use crate::login::{LoginAttempt, LoginClient, LoginRejection, LoginResult, LoginThrottle};
+use crate::models::passkey::{AuthenticationCredential, Passkey, WebauthnChallenge};
+use crate::models::two_factor::TwoFactorLoginRequest;
+use crate::{login_throttle_service, passkey_service, two_factor_service};
+use crate::mailer::{self, Email};
+use crate::models::password::PasswordReset;
+use crate::models::user::{validate_password, LoginRequest, RegisterRequest, Status, User};
+use crate::repositories::{
+    access_token_repository, email_repository, password_reset_repository, user_repository,
+};
+use crate::search::{SearchDocument, SearchType};
+use crate::search_engine;
 use crate::session::set_session;
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::{
+    days_ago_rfc3339, now_rfc3339, seconds_ago_rfc3339, seconds_from_now_rfc3339, username_key,
+};
+use crate::utils::random_token::{generate_token, hash_token};
+use crate::utils::{hashing, signed_token};
+use crate::get_config;
 use actix_session::Session;
-use bcrypt::{hash, verify};
+use mongodb::bson::oid::ObjectId;
 use mongodb::results::InsertOneResult;
-use mongodb::{error::Error, Collection};
+use mongodb::{error::Error, Collection, Database};
+use std::sync::OnceLock;
 use serde_json::Value;
 
-// Register a new user
+// Hashing is slow on purpose, so it runs off the async workers
+async fn hash_password(password: &str) -> Result<String, Error> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hashing::hash_password(&password))
+        .await
+        .map_err(|err| Error::custom(err.to_string()))?
+        .map_err(Error::custom)
+}
+
+async fn verify_password(password: &str, hash: &str) -> Result<bool, Error> {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    tokio::task::spawn_blocking(move || hashing::verify_password(&password, &hash))
+        .await
+        .map_err(|err| Error::custom(err.to_string()))?
+        .map_err(Error::custom)
+}
+
+// Register a new user and email them a verification link. The outer error is a
+// database failure, the inner one a reason to refuse the registration.
 pub async fn register_user_service(
     collection: &Collection<User>,
+    email_logs: &Collection<EmailLog>,
     req: RegisterRequest,
-) -> Result<InsertOneResult, Error> {
-    let hashed_password = match hash(&req.password, 4) {
-        Ok(pwd) => pwd,
-        Err(_) => return Err(Error::custom("Invalid to register user")),
-    };
+) -> Result<Result<InsertOneResult, String>, Error> {
+    if user_repository::find_user_by_email(collection, &req.email).await?.is_some() {
+        return Ok(Err("Email is already registered".to_string()));
+    }
+    if user_repository::username_taken(collection, &req.username, None).await? {
+        return Ok(Err("Username is already taken".to_string()));
+    }
+    let hashed_password = hash_password(&req.password).await?;
 
     let new_user = User {
+        username_key: username_key(&req.username),
         username: req.username,
+        email: req.email,
         password: hashed_password,
         ..Default::default()
     };
-    user_repository::create_user(collection, new_user).await
+    let search_document = SearchDocument::new(SearchType::Users, new_user.id, &new_user.username, "");
+    let result = match user_repository::create_user(collection, new_user.clone()).await {
+        Ok(result) => result,
+        // Registered concurrently
+        Err(err) if is_duplicate_key(&err) => {
+            return Ok(Err("Username or email is already taken".to_string()))
+        }
+        Err(err) => return Err(err),
+    };
+    search_engine::index_document(search_document).await;
+    send_verification_service(email_logs, &new_user).await?;
+    Ok(Ok(result))
+}
+
+// Email the user a link to verify their address. Returns false when they were
+// sent one too recently or too many times today.
+pub async fn send_verification_service(
+    email_logs: &Collection<EmailLog>,
+    user: &User,
+) -> Result<bool, Error> {
+    let config = get_config();
+    let allowed = email_repository::claim_send(
+        email_logs,
+        user.id,
+        EmailPurpose::Verification,
+        &seconds_ago_rfc3339(config.email_resend_cooldown_secs),
+        &days_ago_rfc3339(1),
+        config.email_max_per_day,
+    )
+    .await?;
+    if !allowed {
+        return Ok(false);
+    }
+
+    // Bound to the address, so the link stops working if the email changes
+    let token = signed_token::sign(
+        EmailPurpose::Verification.as_str(),
+        &format!("{}:{}", user.id.to_hex(), user.email),
+        config.verification_ttl_hours * 60 * 60,
+    );
+    mailer::send(Email {
+        template: EmailPurpose::Verification.as_str(),
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nConfirm your email address by opening this link:\n{}/auth/verify-email?token={}\n\nThe link expires in {} hours.",
+            user.username, config.app_url, token, config.verification_ttl_hours
+        ),
+    })
+    .await;
+    Ok(true)
+}
+
+// Verify the email a token was issued for. Returns false for an invalid or
+// expired token, or one for an email the account no longer has.
+pub async fn verify_email_service(collection: &Collection<User>, token: &str) -> Result<bool, Error> {
+    let payload = match signed_token::verify(EmailPurpose::Verification.as_str(), token) {
+        Some(payload) => payload,
+        None => return Ok(false),
+    };
+    let (user_id, email) = match payload.split_once(':') {
+        Some(parts) => parts,
+        None => return Ok(false),
+    };
+    let user_id = match ObjectId::parse_str(user_id) {
+        Ok(id) => id,
+        Err(_) => return Ok(false),
+    };
+    let result = user_repository::verify_email(collection, user_id, email).await?;
+    Ok(result.matched_count == 1)
+}
+
+const TWO_FACTOR_CHALLENGE: &str = "two_factor_challenge";
+
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+// Checked against when there is no such user, so that takes as long as a wrong password
+async fn dummy_hash() -> Result<&'static str, Error> {
+    if let Some(hash) = DUMMY_HASH.get() {
+        return Ok(hash);
+    }
+    let hash = hash_password("not the password of any account").await?;
+    Ok(DUMMY_HASH.get_or_init(|| hash))
 }
 
-// Login user and generate JWT
+// Login user and generate JWT. Failures are counted per account and per IP,
+// refusing further attempts for a growing time, and every attempt is recorded.
 pub async fn login_user_service(
-    collection: &Collection<User>,
+    db: &Database,
     req: LoginRequest,
+    client: LoginClient,
     session: Session,
-) -> Result<Value, Box<dyn std::error::Error>> {
-    let user_opt = user_repository::find_user_by_username(collection, &req.username).await?;
-    if let Some(user) = user_opt {
-        if verify(&req.password, &user.password)? {
-            let token = create_jwt(&user.id.to_hex(), "USER")?;
-
-            set_session(session.clone(), "token".to_string(), token.to_owned()).await?;
-
-            Ok(serde_json::json!({
-                "access_token": token
-            }))
-        } else {
-            Err("Invalid credentials".into())
+) -> Result<Result<Value, LoginRejection>, Box<dyn std::error::Error>> {
+    let collection: Collection<User> = db.collection("users");
+    let attempts: Collection<LoginAttempt> = db.collection("login_attempts");
+    let throttles: Collection<LoginThrottle> = db.collection("login_throttles");
+    let email_logs: Collection<EmailLog> = db.collection("email_logs");
+
+    let account_key = login_throttle_service::account_key(&req.username);
+    let ip_key = login_throttle_service::ip_key(&client.ip);
+    let attempt = |user_id, result| LoginAttempt::new(&req.username, user_id, &client, result);
+
+    let keys = [account_key.clone(), ip_key.clone()];
+    let retry_after = login_throttle_service::check_throttle_service(&throttles, &keys).await?;
+    if let Some(retry_after_secs) = retry_after {
+        let throttled = attempt(None, LoginResult::Throttled);
+        login_throttle_service::record_attempt_service(&attempts, throttled).await?;
+        return Ok(Err(LoginRejection::Throttled { retry_after_secs }));
+    }
+
+    let key = username_key(&req.username);
+    let mut user_opt = user_repository::find_user_by_username_key(&collection, &key).await?;
+    if user_opt.is_none() {
+        // Accounts whose username_key couldn't be filled in
+        user_opt = user_repository::find_user_by_username(&collection, &req.username).await?;
+    }
+    // Deleted accounts can't be logged into, as if they didn't exist
+    let user_opt = user_opt.filter(|user| user.status != Status::Deleted);
+    let valid = match &user_opt {
+        Some(user) => verify_password(&req.password, &user.password).await?,
+        None => {
+            verify_password(&req.password, dummy_hash().await?).await?;
+            false
+        }
+    };
+    let user = match user_opt {
+        Some(user) if valid => user,
+        user => {
+            let failed = attempt(user.as_ref().map(|user| user.id), LoginResult::InvalidCredentials);
+            login_throttle_service::record_attempt_service(&attempts, failed).await?;
+            login_throttle_service::record_failure_service(
+                &throttles,
+                &email_logs,
+                &account_key,
+                &ip_key,
+                user.as_ref(),
+            )
+            .await?;
+            return Ok(Err(LoginRejection::InvalidCredentials));
         }
-    } else {
-        Err("User not found".into())
+    };
+
+    // Upgrade hashes made with an older algorithm or cost while the plain
+    // password is at hand
+    if hashing::needs_rehash(&user.password) {
+        let rehashed = hash_password(&req.password).await?;
+        user_repository::rehash_password(&collection, user.id, &user.password, &rehashed).await?;
     }
+
+    if user.two_factor.enabled {
+        // Failures keep counting until the second factor is in too
+        let pending = attempt(Some(user.id), LoginResult::TwoFactorRequired);
+        login_throttle_service::record_attempt_service(&attempts, pending).await?;
+        // Bound to the token version, so a password change voids it
+        let challenge = signed_token::sign(
+            TWO_FACTOR_CHALLENGE,
+            &format!("{} {}", user.id.to_hex(), user.token_version),
+            get_config().two_factor_challenge_minutes * 60,
+        );
+        return Ok(Ok(serde_json::json!({
+            "two_factor_required": true,
+            "challenge": challenge
+        })));
+    }
+
+    login_throttle_service::clear_account_service(&throttles, &account_key).await?;
+    let succeeded = attempt(Some(user.id), LoginResult::Success);
+    login_throttle_service::record_attempt_service(&attempts, succeeded).await?;
+    Ok(Ok(start_session(&user, session).await?))
+}
+
+// Second step of a login to an account with two-factor authentication: the
+// challenge from the first step and a TOTP or recovery code. Wrong codes count
+// as failed logins.
+pub async fn complete_two_factor_login_service(
+    db: &Database,
+    req: TwoFactorLoginRequest,
+    client: LoginClient,
+    session: Session,
+) -> Result<Result<Value, LoginRejection>, Box<dyn std::error::Error>> {
+    let collection: Collection<User> = db.collection("users");
+    let attempts: Collection<LoginAttempt> = db.collection("login_attempts");
+    let throttles: Collection<LoginThrottle> = db.collection("login_throttles");
+    let email_logs: Collection<EmailLog> = db.collection("email_logs");
+
+    let challenge = signed_token::verify(TWO_FACTOR_CHALLENGE, &req.challenge);
+    let (user_id, version) = match challenge.as_deref().and_then(|payload| payload.split_once(' ')) {
+        Some((user_id, version)) => (user_id.to_string(), version.to_string()),
+        None => return Ok(Err(LoginRejection::InvalidChallenge)),
+    };
+    if ObjectId::parse_str(&user_id).is_err() {
+        return Ok(Err(LoginRejection::InvalidChallenge));
+    }
+    let user = match user_repository::get_user_by_id_service(&collection, &user_id).await? {
+        Some(user) if user.two_factor.enabled && user.token_version.to_string() == version => user,
+        _ => return Ok(Err(LoginRejection::InvalidChallenge)),
+    };
+
+    let account_key = login_throttle_service::account_key(&user.username);
+    let ip_key = login_throttle_service::ip_key(&client.ip);
+    let attempt = |result| LoginAttempt::new(&user.username, Some(user.id), &client, result);
+
+    let keys = [account_key.clone(), ip_key.clone()];
+    let retry_after = login_throttle_service::check_throttle_service(&throttles, &keys).await?;
+    if let Some(retry_after_secs) = retry_after {
+        login_throttle_service::record_attempt_service(&attempts, attempt(LoginResult::Throttled)).await?;
+        return Ok(Err(LoginRejection::Throttled { retry_after_secs }));
+    }
+
+    if !two_factor_service::verify_code_service(&collection, &user, &req.code).await? {
+        let failed = attempt(LoginResult::InvalidTwoFactorCode);
+        login_throttle_service::record_attempt_service(&attempts, failed).await?;
+        login_throttle_service::record_failure_service(
+            &throttles,
+            &email_logs,
+            &account_key,
+            &ip_key,
+            Some(&user),
+        )
+        .await?;
+        return Ok(Err(LoginRejection::InvalidCredentials));
+    }
+
+    login_throttle_service::clear_account_service(&throttles, &account_key).await?;
+    login_throttle_service::record_attempt_service(&attempts, attempt(LoginResult::Success)).await?;
+    Ok(Ok(start_session(&user, session).await?))
+}
+
+// Log in with a passkey, answering a challenge from the passkey login start.
+// Passkeys verify the user themselves, so no second factor is asked for.
+// Failures count against the IP, as there's no account to count them against
+// until the assertion checks out; an account that is locked or held off stays so.
+pub async fn login_with_passkey_service(
+    db: &Database,
+    credential: AuthenticationCredential,
+    client: LoginClient,
+    session: Session,
+) -> Result<Result<Value, LoginRejection>, Box<dyn std::error::Error>> {
+    let collection: Collection<User> = db.collection("users");
+    let attempts: Collection<LoginAttempt> = db.collection("login_attempts");
+    let throttles: Collection<LoginThrottle> = db.collection("login_throttles");
+    let passkeys: Collection<Passkey> = db.collection("passkeys");
+    let challenges: Collection<WebauthnChallenge> = db.collection("webauthn_challenges");
+
+    let ip_key = login_throttle_service::ip_key(&client.ip);
+    let retry_after =
+        login_throttle_service::check_throttle_service(&throttles, std::slice::from_ref(&ip_key)).await?;
+    if let Some(retry_after_secs) = retry_after {
+        let throttled = LoginAttempt::new("", None, &client, LoginResult::Throttled);
+        login_throttle_service::record_attempt_service(&attempts, throttled).await?;
+        return Ok(Err(LoginRejection::Throttled { retry_after_secs }));
+    }
+
+    let verified = passkey_service::verify_assertion_service(&passkeys, &challenges, &credential).await?;
+    let user = match verified {
+        Some(passkey) => {
+            user_repository::get_user_by_id_service(&collection, &passkey.user_id.to_hex()).await?
+        }
+        None => None,
+    };
+    let user = match user {
+        Some(user) if user.status != Status::Deleted => user,
+        _ => {
+            let failed = LoginAttempt::new("", None, &client, LoginResult::InvalidPasskey);
+            login_throttle_service::record_attempt_service(&attempts, failed).await?;
+            login_throttle_service::record_ip_failure_service(&throttles, &ip_key).await?;
+            return Ok(Err(LoginRejection::InvalidCredentials));
+        }
+    };
+
+    // A locked account stays locked for passkeys too
+    let account_key = login_throttle_service::account_key(&user.username);
+    let keys = std::slice::from_ref(&account_key);
+    let retry_after = login_throttle_service::check_throttle_service(&throttles, keys).await?;
+    if let Some(retry_after_secs) = retry_after {
+        let throttled =
+            LoginAttempt::new(&user.username, Some(user.id), &client, LoginResult::Throttled);
+        login_throttle_service::record_attempt_service(&attempts, throttled).await?;
+        return Ok(Err(LoginRejection::Throttled { retry_after_secs }));
+    }
+
+    let succeeded = LoginAttempt::new(&user.username, Some(user.id), &client, LoginResult::Success);
+    login_throttle_service::record_attempt_service(&attempts, succeeded).await?;
+    Ok(Ok(start_session(&user, session).await?))
+}
+
+// Sign the user in on this session
+async fn start_session(user: &User, session: Session) -> Result<Value, Box<dyn std::error::Error>> {
+    let token = create_jwt(&user.id.to_hex(), user.role.as_str(), user.token_version)?;
+
+    set_session(session, "token".to_string(), token.to_owned()).await?;
+
+    Ok(serde_json::json!({
+        "access_token": token
+    }))
+}
+
+// Email a password reset link to the account with this email, if there is one.
+// Nothing tells the caller whether there was, or whether the email was rate limited.
+pub async fn forgot_password_service(
+    collection: &Collection<User>,
+    resets: &Collection<PasswordReset>,
+    email_logs: &Collection<EmailLog>,
+    email: &str,
+) -> Result<(), Error> {
+    let user = match user_repository::find_user_by_email(collection, email).await? {
+        Some(user) if user.status != Status::Deleted => user,
+        _ => return Ok(()),
+    };
+    let config = get_config();
+    let allowed = email_repository::claim_send(
+        email_logs,
+        user.id,
+        EmailPurpose::PasswordReset,
+        &seconds_ago_rfc3339(config.email_resend_cooldown_secs),
+        &days_ago_rfc3339(1),
+        config.email_max_per_day,
+    )
+    .await?;
+    if !allowed {
+        return Ok(());
+    }
+
+    let token = generate_token();
+    let reset = PasswordReset {
+        id: None,
+        user_id: user.id,
+        token_hash: hash_token(&token),
+        expires_at: seconds_from_now_rfc3339(config.password_reset_ttl_minutes * 60),
+        used_at: None,
+        created_at: now_rfc3339(),
+    };
+    password_reset_repository::create_reset(resets, reset).await?;
+    mailer::send(Email {
+        template: EmailPurpose::PasswordReset.as_str(),
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to reset the password of your account. If it was you, choose a new password here:\n{}/auth/password/reset?token={}\n\nThe link expires in {} minutes. If you didn't ask for it, you can ignore this email.",
+            user.username, config.app_url, token, config.password_reset_ttl_minutes
+        ),
+    })
+    .await;
+    Ok(())
+}
+
+// Set a new password with a reset token. The inner error is an unknown, used or
+// expired token, or a password the policy refuses; the token is only used up
+// once the password is accepted. Sessions and access tokens are revoked.
+pub async fn reset_password_service(
+    collection: &Collection<User>,
+    resets: &Collection<PasswordReset>,
+    tokens: &Collection<AccessToken>,
+    token: &str,
+    new_password: &str,
+) -> Result<Result<(), String>, Error> {
+    let invalid = || Ok(Err("Invalid or expired reset link".to_string()));
+    let token_hash = hash_token(token);
+    let reset = match password_reset_repository::find_reset(resets, &token_hash).await? {
+        Some(reset) => reset,
+        None => return invalid(),
+    };
+    let user = match user_repository::get_user_by_id_service(collection, &reset.user_id.to_hex()).await? {
+        Some(user) if user.status != Status::Deleted => user,
+        _ => return invalid(),
+    };
+    if let Err(reason) = validate_password(new_password, &user.username) {
+        return Ok(Err(reason));
+    }
+    if password_reset_repository::consume_reset(resets, &token_hash).await?.is_none() {
+        return invalid();
+    }
+    let password = hash_password(new_password).await?;
+    user_repository::set_password(collection, user.id, &password).await?;
+    // Other links sent before this one are void now
+    password_reset_repository::delete_resets(resets, user.id).await?;
+    access_token_repository::delete_user_tokens(tokens, user.id).await?;
+    Ok(Ok(()))
+}
+
+// Change the password of a signed in user. Every other session is signed out
+// and access tokens are revoked; the current session gets a fresh token, which
+// is returned. The inner error means the current password was wrong.
+pub async fn change_password_service(
+    collection: &Collection<User>,
+    resets: &Collection<PasswordReset>,
+    tokens: &Collection<AccessToken>,
+    user: &User,
+    current_password: &str,
+    new_password: &str,
+    session: Session,
+) -> Result<Result<String, String>, Box<dyn std::error::Error>> {
+    if !verify_password(current_password, &user.password).await? {
+        return Ok(Err("Current password is incorrect".to_string()));
+    }
+    let password = hash_password(new_password).await?;
+    let updated = match user_repository::set_password(collection, user.id, &password).await? {
+        Some(updated) => updated,
+        None => return Err("User not found".into()),
+    };
+    // A reset link sent before the change shouldn't undo it
+    password_reset_repository::delete_resets(resets, user.id).await?;
+    access_token_repository::delete_user_tokens(tokens, user.id).await?;
+
+    let token = create_jwt(&updated.id.to_hex(), updated.role.as_str(), updated.token_version)?;
+    set_session(session, "token".to_string(), token.to_owned()).await?;
+    Ok(Ok(token))
 }
@@ -0,0 +1,92 @@
+use crate::models::bookmark::{Bookmark, BookmarkFolder, BookmarkResponse};
+use crate::models::post::{Audience, Post};
+use crate::pagination::{Paginated, Pagination};
+use crate::repositories::{bookmark_repository, post_repository};
+use mongodb::bson::{oid::ObjectId, Document};
+use mongodb::error::Error;
+use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+use mongodb::Collection;
+
+pub async fn bookmark_post_service(
+    collection: &Collection<Bookmark>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+    folder_id: Option<ObjectId>,
+) -> Result<UpdateResult, Error> {
+    bookmark_repository::upsert_bookmark(collection, user_id, post_id, folder_id).await
+}
+
+pub async fn remove_bookmark_service(
+    collection: &Collection<Bookmark>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    bookmark_repository::delete_bookmark(collection, user_id, post_id).await
+}
+
+// A page of bookmarks with their posts; bookmarks of deleted or no longer
+// visible posts are skipped
+pub async fn get_bookmarks_service(
+    collection: &Collection<Bookmark>,
+    posts: &Collection<Post>,
+    filter: Document,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Result<Paginated<BookmarkResponse>, Error> {
+    let (bookmarks, next_cursor) =
+        bookmark_repository::get_bookmarks(collection, filter, pagination).await?;
+    let post_ids: Vec<ObjectId> = bookmarks.iter().map(|b| b.post_id).collect();
+    let mut found = post_repository::get_posts_by_ids(posts, &post_ids, audience).await?;
+
+    let data = bookmarks
+        .into_iter()
+        .filter_map(|bookmark| {
+            let index = found.iter().position(|p| p.id == bookmark.post_id)?;
+            Some(BookmarkResponse {
+                folder_id: bookmark.folder_id,
+                post: found.swap_remove(index),
+                created_at: bookmark.created_at.to_string(),
+            })
+        })
+        .collect();
+    Ok(Paginated {
+        data,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
+
+pub async fn create_folder_service(
+    collection: &Collection<BookmarkFolder>,
+    new_folder: BookmarkFolder,
+) -> Result<InsertOneResult, Error> {
+    bookmark_repository::create_folder(collection, new_folder).await
+}
+
+pub async fn get_folder_service(
+    collection: &Collection<BookmarkFolder>,
+    user_id: ObjectId,
+    folder_id: ObjectId,
+) -> Result<Option<BookmarkFolder>, Error> {
+    bookmark_repository::get_folder(collection, user_id, folder_id).await
+}
+
+pub async fn get_folders_service(
+    collection: &Collection<BookmarkFolder>,
+    user_id: ObjectId,
+) -> Result<Vec<BookmarkFolder>, Error> {
+    bookmark_repository::get_folders(collection, user_id).await
+}
+
+pub async fn delete_folder_service(
+    collection: &Collection<BookmarkFolder>,
+    bookmarks: &Collection<Bookmark>,
+    user_id: ObjectId,
+    folder_id: ObjectId,
+) -> Result<DeleteResult, Error> {
+    let result = bookmark_repository::delete_folder(collection, user_id, folder_id).await?;
+    if result.deleted_count == 1 {
+        bookmark_repository::unfile_bookmarks(bookmarks, folder_id).await?;
+    }
+    Ok(result)
+}
@@ -0,0 +1,113 @@
+use crate::entities::extract_entities;
+use crate::models::post::{EntityKind, PostEntity};
+use crate::models::tag::Tag;
+use crate::models::user::User;
+use crate::repositories::{tag_repository, user_repository};
+use crate::services::tag_service;
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::{slugify, username_key};
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// Entities of a post's content linked to their tags and users
+pub struct LinkedEntities {
+    pub entities: Vec<PostEntity>,
+    pub tag_ids: Vec<ObjectId>,
+    pub mentioned_ids: Vec<ObjectId>,
+}
+
+// Parse #hashtags and @mentions, creating tags owned by `author_id` for unknown hashtags
+pub async fn link_entities_service(
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    author_id: ObjectId,
+    content: &str,
+) -> Result<LinkedEntities, Error> {
+    let raw = extract_entities(content);
+
+    let mut tag_ids: Vec<(String, ObjectId)> = vec![];
+    for entity in raw.iter().filter(|e| e.kind == EntityKind::Hashtag) {
+        let name = entity.text.to_lowercase();
+        if tag_ids.iter().any(|(n, _)| *n == name) {
+            continue;
+        }
+        // Hashtags of renamed or merged tags link to the tag that took them over
+        let existing = match tag_repository::find_tag_by_name(tags, &entity.text).await? {
+            Some(tag) => Some(tag),
+            None => tag_repository::find_tag_by_previous_slug(tags, &slugify(&entity.text)).await?,
+        };
+        let id = match existing {
+            Some(tag) => tag.id,
+            None => {
+                let new_tag = Tag {
+                    owner_id: author_id,
+                    name: entity.text.clone(),
+                    ..Default::default()
+                };
+                match tag_service::create_tag_service(tags, new_tag).await {
+                    Ok(result) => result.inserted_id.as_object_id(),
+                    // Created concurrently by another post
+                    Err(err) if is_duplicate_key(&err) => {
+                        tag_repository::find_tag_by_name(tags, &entity.text)
+                            .await?
+                            .and_then(|tag| tag.id)
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        if let Some(id) = id {
+            tag_ids.push((name, id));
+        }
+    }
+
+    // Mentions link like logins find accounts: "@Alice" and "@a1ice" both mean alice
+    let keys: Vec<String> = raw
+        .iter()
+        .filter(|e| e.kind == EntityKind::Mention)
+        .map(|e| username_key(&e.text))
+        .collect();
+    let mentioned = user_repository::find_users_by_username_keys(users, &keys).await?;
+
+    let entities = raw
+        .into_iter()
+        .map(|entity| {
+            let target_id = match entity.kind {
+                EntityKind::Hashtag => {
+                    let name = entity.text.to_lowercase();
+                    tag_ids.iter().find(|(n, _)| *n == name).map(|(_, id)| id.to_hex())
+                }
+                EntityKind::Mention => {
+                    let key = username_key(&entity.text);
+                    mentioned
+                        .iter()
+                        .find(|u| u.username_key == key)
+                        .map(|u| u.id.to_hex())
+                }
+            };
+            PostEntity {
+                kind: entity.kind,
+                start: entity.start,
+                end: entity.end,
+                text: entity.text,
+                target_id,
+            }
+        })
+        .collect();
+
+    Ok(LinkedEntities {
+        entities,
+        tag_ids: tag_ids.into_iter().map(|(_, id)| id).collect(),
+        mentioned_ids: mentioned.iter().map(|u| u.id).collect(),
+    })
+}
+
+// Ids of the users mentioned in already linked entities
+pub fn mentioned_ids(entities: &[PostEntity]) -> Vec<ObjectId> {
+    entities
+        .iter()
+        .filter(|e| e.kind == EntityKind::Mention)
+        .filter_map(|e| e.target_id.as_deref())
+        .filter_map(|id| ObjectId::parse_str(id).ok())
+        .collect()
+}
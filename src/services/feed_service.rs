@@ -0,0 +1,141 @@
+use crate::models::feed::{FeedItem, FeedSources};
+use crate::models::follow::Follow;
+use crate::models::post::{Audience, Post};
+use crate::models::tag::Tag;
+use crate::models::tag_subscription::{SubscriptionKind, TagSubscription};
+use crate::models::user::User;
+use crate::pagination::{Paginated, Pagination};
+use crate::repositories::{feed_repository, post_repository, user_repository};
+use crate::services::{post_service, tag_subscription_service};
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// Posts and reposts of the people `user_id` follows, plus the user's own posts.
+// With `include_tags`, posts carrying followed tags are mixed in.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_home_feed_service(
+    posts: &Collection<Post>,
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    subscriptions: &Collection<TagSubscription>,
+    tags: &Collection<Tag>,
+    user_id: ObjectId,
+    include_tags: bool,
+    pagination: &Pagination,
+) -> Result<Paginated<FeedItem>, Error> {
+    let audience = post_service::get_audience_service(follows, users, user_id).await?;
+    let mut author_ids = audience.following_ids.clone();
+    author_ids.push(user_id);
+    let tag_ids = match include_tags {
+        true => {
+            let kind = SubscriptionKind::Follow;
+            tag_subscription_service::get_expanded_tag_ids_service(subscriptions, tags, user_id, kind)
+                .await?
+        }
+        false => vec![],
+    };
+    let muted_tag_ids = tag_subscription_service::get_expanded_tag_ids_service(
+        subscriptions,
+        tags,
+        user_id,
+        SubscriptionKind::Mute,
+    )
+    .await?;
+
+    let sources = FeedSources {
+        viewer_id: user_id,
+        author_ids,
+        tag_ids,
+        muted_tag_ids,
+    };
+    get_feed_page(posts, users, &sources, &audience, pagination).await
+}
+
+// Posts carrying the tags `user_id` follows, or their child tags and synonyms
+pub async fn get_tag_feed_service(
+    posts: &Collection<Post>,
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    subscriptions: &Collection<TagSubscription>,
+    tags: &Collection<Tag>,
+    user_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<FeedItem>, Error> {
+    let audience = post_service::get_audience_service(follows, users, user_id).await?;
+    let sources = FeedSources {
+        viewer_id: user_id,
+        author_ids: vec![],
+        tag_ids: tag_subscription_service::get_expanded_tag_ids_service(
+            subscriptions,
+            tags,
+            user_id,
+            SubscriptionKind::Follow,
+        )
+        .await?,
+        muted_tag_ids: tag_subscription_service::get_expanded_tag_ids_service(
+            subscriptions,
+            tags,
+            user_id,
+            SubscriptionKind::Mute,
+        )
+        .await?,
+    };
+    get_feed_page(posts, users, &sources, &audience, pagination).await
+}
+
+async fn get_feed_page(
+    posts: &Collection<Post>,
+    users: &Collection<User>,
+    sources: &FeedSources,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Result<Paginated<FeedItem>, Error> {
+    let (entries, next_cursor) =
+        feed_repository::get_feed_entries(posts, sources, audience, pagination).await?;
+
+    let post_ids: Vec<ObjectId> = entries
+        .iter()
+        .filter_map(|e| e.get_object_id("post_id").ok())
+        .collect();
+    let reposter_ids: Vec<ObjectId> = entries
+        .iter()
+        .filter_map(|e| e.get_object_id("reposted_by").ok())
+        .collect();
+    let found = post_repository::get_posts_by_ids(posts, &post_ids, audience).await?;
+    let reposters = user_repository::get_users_by_ids(users, &reposter_ids).await?;
+
+    // The query already filtered the entries; one whose post was deleted,
+    // hidden or tagged with a muted tag since then is skipped
+    let data = entries
+        .iter()
+        .filter_map(|entry| {
+            let post_id = entry.get_object_id("post_id").ok()?;
+            let post = found.iter().find(|p| p.id == post_id)?.clone();
+            let own = post.author.as_ref().is_some_and(|author| author.id == sources.viewer_id);
+            let muted = !own
+                && post
+                    .tags
+                    .iter()
+                    .flatten()
+                    .any(|tag| sources.muted_tag_ids.contains(&tag._id));
+            if muted {
+                return None;
+            }
+            let reposted_by = entry
+                .get_object_id("reposted_by")
+                .ok()
+                .and_then(|id| reposters.iter().find(|u| u.id == id))
+                .map(|u| User::to_public(u.clone()));
+            Some(FeedItem {
+                post,
+                reposted_by,
+                created_at: entry.get_str("created_at").unwrap_or_default().to_string(),
+            })
+        })
+        .collect();
+    Ok(Paginated {
+        data,
+        next_cursor,
+        limit: pagination.limit,
+    })
+}
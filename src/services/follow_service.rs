@@ -0,0 +1,35 @@
+use crate::models::follow::Follow;
+use crate::models::user::User;
+use crate::repositories::{follow_repository, user_repository};
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// Follow a user; following someone twice is a no-op
+pub async fn follow_user_service(
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    follower_id: ObjectId,
+    following_id: ObjectId,
+) -> Result<bool, Error> {
+    if follower_id == following_id {
+        return Err(Error::custom("Users can't follow themselves"));
+    }
+    let created = follow_repository::follow(follows, follower_id, following_id).await?;
+    if created {
+        user_repository::increment_follow_counts(users, follower_id, following_id, 1).await?;
+    }
+    Ok(created)
+}
+
+pub async fn unfollow_user_service(
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    follower_id: ObjectId,
+    following_id: ObjectId,
+) -> Result<bool, Error> {
+    let removed = follow_repository::unfollow(follows, follower_id, following_id).await?;
+    if removed {
+        user_repository::increment_follow_counts(users, follower_id, following_id, -1).await?;
+    }
+    Ok(removed)
+}
@@ -1,5 +1,9 @@
-use crate::models::item::Item;
+use crate::models::item::{Item, ItemRequest};
+use crate::pagination::{Paginated, Pagination};
 use crate::repositories::item_repository;
+use crate::search::{SearchDocument, SearchType};
+use crate::search_engine;
+use mongodb::bson::{oid::ObjectId, Document};
 use mongodb::error::Error;
 use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
 use mongodb::Collection;
@@ -9,7 +13,12 @@ pub async fn create_item_service(
     collection: &Collection<Item>,
     new_item: Item,
 ) -> Result<InsertOneResult, Error> {
-    item_repository::create_item(collection, new_item).await
+    let (name, description) = (new_item.name.clone(), new_item.description.clone());
+    let result = item_repository::create_item(collection, new_item).await?;
+    if let Some(id) = result.inserted_id.as_object_id() {
+        search_engine::index_document(SearchDocument::new(SearchType::Items, id, &name, &description)).await;
+    }
+    Ok(result)
 }
 
 // Service to get an item by id
@@ -20,24 +29,73 @@ pub async fn get_item_by_id_service(
     item_repository::get_item_by_id(collection, item_id).await
 }
 
-// Service to get all items
-pub async fn get_all_items_service(collection: &Collection<Item>) -> Result<Vec<Item>, Error> {
-    item_repository::get_all_items(collection).await
+// Service to get a page of items
+pub async fn get_all_items_service(
+    collection: &Collection<Item>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Item>, Error> {
+    item_repository::get_all_items(collection, filter, pagination).await
 }
 
 // Service to update an item
 pub async fn update_item_service(
     collection: &Collection<Item>,
     item_id: &str,
-    updated_item: Item,
+    updated_item: ItemRequest,
 ) -> Result<UpdateResult, Error> {
-    item_repository::update_item(collection, item_id, updated_item).await
+    let (name, description) = (updated_item.name.clone(), updated_item.description.clone());
+    let result = item_repository::update_item(collection, item_id, updated_item).await?;
+    if let (Ok(id), 1..) = (ObjectId::parse_str(item_id), result.matched_count) {
+        search_engine::index_document(SearchDocument::new(SearchType::Items, id, &name, &description)).await;
+    }
+    Ok(result)
 }
 
-// Service to delete an item
+// Service to move an item to the trash
 pub async fn delete_item_service(
     collection: &Collection<Item>,
     item_id: &str,
+) -> Result<UpdateResult, Error> {
+    let result = item_repository::delete_item(collection, item_id).await?;
+    if let Ok(id) = ObjectId::parse_str(item_id) {
+        search_engine::remove_document(id).await;
+    }
+    Ok(result)
+}
+
+// Service to take an item out of the owner's trash
+pub async fn restore_item_service(
+    collection: &Collection<Item>,
+    item_id: ObjectId,
+    user_id: &str,
+) -> Result<Option<Item>, Error> {
+    let item = item_repository::restore_item(collection, item_id, user_id).await?;
+    if let Some(item) = &item {
+        search_engine::index_document(SearchDocument::new(
+            SearchType::Items,
+            item_id,
+            &item.name,
+            &item.description,
+        ))
+        .await;
+    }
+    Ok(item)
+}
+
+// Service to get a page of the owner's deleted items
+pub async fn get_deleted_items_service(
+    collection: &Collection<Item>,
+    user_id: &str,
+    pagination: &Pagination,
+) -> Result<Paginated<Item>, Error> {
+    item_repository::get_deleted_items(collection, user_id, pagination).await
+}
+
+// Service to permanently remove items deleted before `cutoff`
+pub async fn purge_deleted_items_service(
+    collection: &Collection<Item>,
+    cutoff: &str,
 ) -> Result<DeleteResult, Error> {
-    item_repository::delete_item(collection, item_id).await
+    item_repository::purge_deleted_items(collection, cutoff).await
 }
@@ -0,0 +1,229 @@
+use crate::email::{EmailLog, EmailPurpose};
+use crate::get_config;
+use crate::login::{LoginAttempt, LoginThrottle};
+use crate::mailer::{self, Email};
+use crate::pagination::{Paginated, Pagination};
+use crate::repositories::{email_repository, login_attempt_repository, login_throttle_repository};
+use crate::user::User;
+use crate::utils::helps::{
+    days_ago_rfc3339, now_rfc3339, seconds_ago_rfc3339, seconds_from_now_rfc3339, username_key,
+};
+use crate::utils::signed_token;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::{error::Error, Collection};
+
+pub fn account_key(username: &str) -> String {
+    format!("account:{}", username_key(username))
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+// Wait after `failures` failed logins: nothing up to the threshold, then
+// doubling from the base up to the maximum
+fn backoff_secs(failures: i32, threshold: i32, base_secs: i64, max_secs: i64) -> i64 {
+    if failures < threshold {
+        return 0;
+    }
+    let doublings = (failures - threshold).min(32) as u32;
+    base_secs
+        .saturating_mul(2i64.saturating_pow(doublings))
+        .min(max_secs)
+}
+
+fn seconds_until(until: &str) -> i64 {
+    let millis = DateTime::parse_rfc3339_str(until)
+        .map(|until| until.timestamp_millis() - DateTime::now().timestamp_millis())
+        .unwrap_or_default();
+    (millis + 999) / 1000
+}
+
+// Seconds until logins to the account or from the IP are checked again, when
+// they're refused now
+pub async fn check_throttle_service(
+    throttles: &Collection<LoginThrottle>,
+    keys: &[String],
+) -> Result<Option<i64>, Error> {
+    let now = now_rfc3339();
+    let refused_until = login_throttle_repository::get_throttles(throttles, keys)
+        .await?
+        .iter()
+        .filter_map(|throttle| throttle.refused_until(&now))
+        .max();
+    Ok(refused_until.map(|until| seconds_until(&until).max(1)))
+}
+
+// Count a failed login against the account and the IP and hold off further
+// attempts accordingly. A username that reaches the lockout threshold is
+// locked whether or not an account has it, so the responses don't tell; an
+// existing account's owner is emailed a link to unlock it.
+pub async fn record_failure_service(
+    throttles: &Collection<LoginThrottle>,
+    email_logs: &Collection<EmailLog>,
+    account_key: &str,
+    ip_key: &str,
+    user: Option<&User>,
+) -> Result<(), Error> {
+    let config = get_config();
+    let window_cutoff = seconds_ago_rfc3339(config.login_lockout_minutes * 60);
+
+    record_ip_failure_service(throttles, ip_key).await?;
+
+    let account =
+        login_throttle_repository::record_failure(throttles, account_key, &window_cutoff).await?;
+    if account.failures >= config.login_lockout_threshold {
+        let locked_until = seconds_from_now_rfc3339(config.login_lockout_minutes * 60);
+        login_throttle_repository::set_block(
+            throttles,
+            account_key,
+            &locked_until,
+            Some(&locked_until),
+        )
+        .await?;
+        if let Some(user) = user {
+            send_unlock_email(email_logs, user, account_key, &locked_until).await?;
+        }
+    } else {
+        let delay = backoff_secs(
+            account.failures,
+            config.login_backoff_threshold,
+            config.login_backoff_base_secs,
+            config.login_backoff_max_secs,
+        );
+        if delay > 0 {
+            let blocked_until = seconds_from_now_rfc3339(delay);
+            login_throttle_repository::set_block(throttles, account_key, &blocked_until, None)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// Count a failed login against the IP only, for logins that name no account
+pub async fn record_ip_failure_service(
+    throttles: &Collection<LoginThrottle>,
+    ip_key: &str,
+) -> Result<(), Error> {
+    let config = get_config();
+    let window_cutoff = seconds_ago_rfc3339(config.login_lockout_minutes * 60);
+    let ip = login_throttle_repository::record_failure(throttles, ip_key, &window_cutoff).await?;
+    let delay = backoff_secs(
+        ip.failures,
+        config.login_ip_backoff_threshold,
+        config.login_backoff_base_secs,
+        config.login_backoff_max_secs,
+    );
+    if delay > 0 {
+        login_throttle_repository::set_block(
+            throttles,
+            ip_key,
+            &seconds_from_now_rfc3339(delay),
+            None,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn send_unlock_email(
+    email_logs: &Collection<EmailLog>,
+    user: &User,
+    account_key: &str,
+    locked_until: &str,
+) -> Result<(), Error> {
+    let config = get_config();
+    let allowed = email_repository::claim_send(
+        email_logs,
+        user.id,
+        EmailPurpose::Unlock,
+        &seconds_ago_rfc3339(config.email_resend_cooldown_secs),
+        &days_ago_rfc3339(1),
+        config.email_max_per_day,
+    )
+    .await?;
+    if !allowed || user.email.is_empty() {
+        return Ok(());
+    }
+
+    // Only good for this lockout
+    let token = signed_token::sign(
+        EmailPurpose::Unlock.as_str(),
+        &format!("{} {}", account_key, locked_until),
+        config.login_lockout_minutes * 60,
+    );
+    mailer::send(Email {
+        template: EmailPurpose::Unlock.as_str(),
+        to: user.email.clone(),
+        subject: "Your account has been locked".to_string(),
+        body: format!(
+            "Hi {},\n\nYour account was locked after too many failed login attempts. It unlocks by itself in {} minutes, or right away with this link:\n{}/auth/unlock?token={}\n\nIf the attempts weren't yours, consider changing your password.",
+            user.username, config.login_lockout_minutes, config.app_url, token
+        ),
+    })
+    .await;
+    Ok(())
+}
+
+// A successful login forgives the account's failures, but not the IP's
+pub async fn clear_account_service(
+    throttles: &Collection<LoginThrottle>,
+    account_key: &str,
+) -> Result<(), Error> {
+    login_throttle_repository::clear_throttle(throttles, account_key).await
+}
+
+// Lift a lockout with the link from the unlock email. Returns false for an
+// invalid or expired link, or a lockout that is already over.
+pub async fn unlock_account_service(
+    throttles: &Collection<LoginThrottle>,
+    token: &str,
+) -> Result<bool, Error> {
+    let payload = match signed_token::verify(EmailPurpose::Unlock.as_str(), token) {
+        Some(payload) => payload,
+        None => return Ok(false),
+    };
+    match payload.split_once(' ') {
+        Some((account_key, locked_until)) => {
+            login_throttle_repository::unlock(throttles, account_key, locked_until).await
+        }
+        None => Ok(false),
+    }
+}
+
+pub async fn record_attempt_service(
+    attempts: &Collection<LoginAttempt>,
+    attempt: LoginAttempt,
+) -> Result<(), Error> {
+    login_attempt_repository::record_attempt(attempts, attempt).await
+}
+
+pub async fn get_login_attempts_service(
+    attempts: &Collection<LoginAttempt>,
+    user_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<LoginAttempt>, Error> {
+    login_attempt_repository::get_attempts(attempts, user_id, pagination).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_from_the_threshold_up_to_the_maximum() {
+        let delays: Vec<i64> = (1..=8).map(|failures| backoff_secs(failures, 3, 2, 20)).collect();
+        assert_eq!(delays, [0, 0, 2, 4, 8, 16, 20, 20]);
+    }
+
+    #[test]
+    fn backoff_survives_huge_failure_counts() {
+        assert_eq!(backoff_secs(i32::MAX, 1, 30, 900), 900);
+    }
+
+    #[test]
+    fn keys_fold_account_look_alikes_together() {
+        assert_eq!(account_key("Admin"), account_key("adm1n"));
+        assert_eq!(ip_key("10.0.0.1"), "ip:10.0.0.1");
+    }
+}
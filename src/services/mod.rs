@@ -2,4 +2,17 @@ pub mod auth_service;
 pub mod user_service;
 pub mod post_service;
 pub mod item_service;
-pub mod tag_service;
\ No newline at end of file
+pub mod tag_service;
+pub mod search_service;
+pub mod follow_service;
+pub mod bookmark_service;
+pub mod feed_service;
+pub mod notification_service;
+pub mod entity_service;
+pub mod revision_service;
+pub mod trending_service;
+pub mod tag_subscription_service;
+pub mod login_throttle_service;
+pub mod two_factor_service;
+pub mod passkey_service;
+pub mod access_token_service;
\ No newline at end of file
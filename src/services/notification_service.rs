@@ -0,0 +1,60 @@
+use crate::models::follow::Follow;
+use crate::models::notification::{Notification, NotificationKind};
+use crate::models::user::User;
+use crate::pagination::{Paginated, Pagination};
+use crate::post::Post;
+use crate::repositories::notification_repository;
+use crate::services::post_service;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::error::Error;
+use mongodb::results::UpdateResult;
+use mongodb::Collection;
+
+// Notify users mentioned in a post; authors aren't notified of their own mentions.
+// Only users who can open the post hear about it, so a followers-only post
+// doesn't reveal itself to the non-followers it mentions.
+pub async fn notify_mentions_service(
+    collection: &Collection<Notification>,
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    post_id: ObjectId,
+    post: &Post,
+    mentioned_ids: &[ObjectId],
+) -> Result<(), Error> {
+    let actor_id = post.author_id;
+    let mut recipients = vec![];
+    for id in mentioned_ids.iter().filter(|id| **id != actor_id) {
+        let audience = post_service::get_audience_service(follows, users, *id).await?;
+        if audience.can_see(post) {
+            recipients.push(*id);
+        }
+    }
+    let notifications = recipients
+        .iter()
+        .map(|id| Notification {
+            id: None,
+            user_id: *id,
+            actor_id,
+            kind: NotificationKind::Mention,
+            post_id: Some(post_id),
+            read: false,
+            created_at: DateTime::now(),
+        })
+        .collect();
+    notification_repository::create_notifications(collection, notifications).await
+}
+
+pub async fn get_notifications_service(
+    collection: &Collection<Notification>,
+    user_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<Notification>, Error> {
+    notification_repository::get_notifications(collection, user_id, pagination).await
+}
+
+pub async fn mark_all_read_service(
+    collection: &Collection<Notification>,
+    user_id: ObjectId,
+) -> Result<UpdateResult, Error> {
+    notification_repository::mark_all_read(collection, user_id).await
+}
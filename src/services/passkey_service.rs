@@ -0,0 +1,340 @@
+use crate::get_config;
+use crate::models::passkey::{
+    AuthenticationCredential, CeremonyKind, Passkey, PasskeyRegistrationRequest, PasskeyResponse,
+    WebauthnChallenge, PASSKEY_MAX_PER_USER, PASSKEY_NAME_MAX_LENGTH,
+};
+use crate::models::user::User;
+use crate::repositories::{passkey_repository, webauthn_challenge_repository};
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::{now_rfc3339, seconds_from_now_rfc3339};
+use crate::utils::random_token::generate_token;
+use crate::utils::webauthn;
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+use serde_json::{json, Value};
+
+// Issue a challenge for a ceremony, clearing out ones that were never answered
+async fn issue_challenge(
+    challenges: &Collection<WebauthnChallenge>,
+    kind: CeremonyKind,
+    user_id: Option<ObjectId>,
+) -> Result<String, Error> {
+    webauthn_challenge_repository::delete_expired_challenges(challenges).await?;
+    let challenge = generate_token();
+    webauthn_challenge_repository::create_challenge(
+        challenges,
+        WebauthnChallenge {
+            id: None,
+            challenge: challenge.clone(),
+            kind,
+            user_id,
+            expires_at: seconds_from_now_rfc3339(get_config().webauthn_timeout_secs),
+        },
+    )
+    .await?;
+    Ok(challenge)
+}
+
+fn credential_descriptors(passkeys: &[Passkey]) -> Vec<Value> {
+    passkeys
+        .iter()
+        .map(|passkey| json!({ "type": "public-key", "id": passkey.credential_id }))
+        .collect()
+}
+
+// Options for navigator.credentials.create(), in the JSON form browsers parse
+// with PublicKeyCredential.parseCreationOptionsFromJSON(). The inner error
+// means the account has as many passkeys as it may have.
+pub async fn start_registration_service(
+    passkeys: &Collection<Passkey>,
+    challenges: &Collection<WebauthnChallenge>,
+    user: &User,
+) -> Result<Result<Value, String>, Error> {
+    let existing = passkey_repository::get_user_passkeys(passkeys, user.id).await?;
+    if existing.len() >= PASSKEY_MAX_PER_USER {
+        return Ok(Err(format!(
+            "No more than {} passkeys per account",
+            PASSKEY_MAX_PER_USER
+        )));
+    }
+    let challenge = issue_challenge(challenges, CeremonyKind::Registration, Some(user.id)).await?;
+    let config = get_config();
+    Ok(Ok(json!({
+        "rp": { "id": config.webauthn_rp_id, "name": config.webauthn_rp_name },
+        "user": {
+            "id": webauthn::encode(&user.id.bytes()),
+            "name": user.username,
+            "displayName": user.username
+        },
+        "challenge": challenge,
+        "pubKeyCredParams": [{ "type": "public-key", "alg": webauthn::ES256 }],
+        "timeout": config.webauthn_timeout_secs * 1000,
+        "attestation": "none",
+        "excludeCredentials": credential_descriptors(&existing),
+        "authenticatorSelection": {
+            "residentKey": "preferred",
+            "userVerification": "required"
+        }
+    })))
+}
+
+// Check the browser's answer to a registration challenge and store the new
+// passkey. The inner error is a credential that doesn't check out.
+pub async fn finish_registration_service(
+    passkeys: &Collection<Passkey>,
+    challenges: &Collection<WebauthnChallenge>,
+    user: &User,
+    req: PasskeyRegistrationRequest,
+) -> Result<Result<PasskeyResponse, String>, Error> {
+    let name = req.name.as_deref().map(str::trim).unwrap_or_default();
+    let name = if name.is_empty() { "Passkey" } else { name };
+    if name.chars().count() > PASSKEY_NAME_MAX_LENGTH {
+        return Ok(Err(format!(
+            "Name must be at most {} characters",
+            PASSKEY_NAME_MAX_LENGTH
+        )));
+    }
+
+    let credential = req.credential;
+    let credential = match registered_credential(
+        challenges,
+        user,
+        &credential.response.client_data_json,
+        &credential.response.attestation_object,
+        &credential.id,
+    )
+    .await?
+    {
+        Ok(credential) => credential,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    if passkey_repository::get_user_passkeys(passkeys, user.id)
+        .await?
+        .len()
+        >= PASSKEY_MAX_PER_USER
+    {
+        return Ok(Err(format!(
+            "No more than {} passkeys per account",
+            PASSKEY_MAX_PER_USER
+        )));
+    }
+    let (credential_id, public_key, sign_count) = credential;
+    let passkey = Passkey {
+        id: None,
+        user_id: user.id,
+        credential_id,
+        public_key,
+        sign_count,
+        name: name.to_string(),
+        created_at: now_rfc3339(),
+        last_used_at: None,
+    };
+    match passkey_repository::create_passkey(passkeys, passkey.clone()).await {
+        Ok(id) => Ok(Ok(Passkey::to_passkey(Passkey {
+            id: Some(id),
+            ..passkey
+        }))),
+        Err(err) if is_duplicate_key(&err) => {
+            Ok(Err("This passkey is already registered".to_string()))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Credential id, public key and counter of a registration, once its challenge
+// is used up
+async fn registered_credential(
+    challenges: &Collection<WebauthnChallenge>,
+    user: &User,
+    client_data_json: &str,
+    attestation_object: &str,
+    id: &str,
+) -> Result<Result<(String, String, i64), String>, Error> {
+    let parsed = webauthn::decode(client_data_json)
+        .and_then(|client_data| webauthn::verify_client_data(&client_data, "webauthn.create"));
+    let challenge = match parsed {
+        Ok(challenge) => challenge,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    match webauthn_challenge_repository::consume_challenge(
+        challenges,
+        &challenge,
+        CeremonyKind::Registration,
+    )
+    .await?
+    {
+        Some(issued) if issued.user_id == Some(user.id) => {}
+        _ => return Ok(Err("Unknown or expired challenge".to_string())),
+    }
+
+    let parsed = webauthn::decode(attestation_object)
+        .and_then(|object| webauthn::parse_attestation_object(&object))
+        .and_then(|auth_data| webauthn::parse_authenticator_data(&auth_data));
+    let auth_data = match parsed {
+        Ok(auth_data) => auth_data,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    if !auth_data.user_verified {
+        return Ok(Err("User verification is required".to_string()));
+    }
+    let (credential_id, key) = match auth_data.credential {
+        Some(credential) => credential,
+        None => return Ok(Err("Attestation has no credential".to_string())),
+    };
+    if webauthn::decode(id).ok().as_deref() != Some(credential_id.as_slice()) {
+        return Ok(Err("Credential id doesn't match".to_string()));
+    }
+    let public_key = match webauthn::cose_key_to_sec1(&key) {
+        Ok(public_key) => public_key,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    Ok(Ok((
+        webauthn::encode(&credential_id),
+        webauthn::encode(&public_key),
+        auth_data.sign_count as i64,
+    )))
+}
+
+// Options for navigator.credentials.get(). With a user, the browser is told
+// which of their passkeys to offer; without one it offers any passkey stored
+// for the site. Unknown users get no list, the same as a user without passkeys.
+pub async fn start_authentication_service(
+    passkeys: &Collection<Passkey>,
+    challenges: &Collection<WebauthnChallenge>,
+    user: Option<&User>,
+) -> Result<Value, Error> {
+    let allowed = match user {
+        Some(user) => passkey_repository::get_user_passkeys(passkeys, user.id).await?,
+        None => vec![],
+    };
+    let user_id = user.map(|user| user.id);
+    let challenge = issue_challenge(challenges, CeremonyKind::Authentication, user_id).await?;
+    let config = get_config();
+    Ok(json!({
+        "challenge": challenge,
+        "rpId": config.webauthn_rp_id,
+        "timeout": config.webauthn_timeout_secs * 1000,
+        "allowCredentials": credential_descriptors(&allowed),
+        "userVerification": "required"
+    }))
+}
+
+// Check an assertion against an issued challenge and the stored passkey, and
+// record its use. Returns the passkey when the assertion holds.
+pub async fn verify_assertion_service(
+    passkeys: &Collection<Passkey>,
+    challenges: &Collection<WebauthnChallenge>,
+    credential: &AuthenticationCredential,
+) -> Result<Option<Passkey>, Error> {
+    let response = &credential.response;
+    let (Ok(client_data_json), Ok(authenticator_data), Ok(signature)) = (
+        webauthn::decode(&response.client_data_json),
+        webauthn::decode(&response.authenticator_data),
+        webauthn::decode(&response.signature),
+    ) else {
+        return Ok(None);
+    };
+    let challenge = match webauthn::verify_client_data(&client_data_json, "webauthn.get") {
+        Ok(challenge) => challenge,
+        Err(_) => return Ok(None),
+    };
+    let issued = match webauthn_challenge_repository::consume_challenge(
+        challenges,
+        &challenge,
+        CeremonyKind::Authentication,
+    )
+    .await?
+    {
+        Some(issued) => issued,
+        None => return Ok(None),
+    };
+
+    let credential_id = match webauthn::decode(&credential.id) {
+        Ok(id) => webauthn::encode(&id),
+        Err(_) => return Ok(None),
+    };
+    let passkey = match passkey_repository::find_by_credential_id(passkeys, &credential_id).await? {
+        Some(passkey) => passkey,
+        None => return Ok(None),
+    };
+    if issued
+        .user_id
+        .is_some_and(|user_id| user_id != passkey.user_id)
+    {
+        return Ok(None);
+    }
+    if let Some(user_handle) = &response.user_handle {
+        if webauthn::decode(user_handle).ok().as_deref() != Some(&passkey.user_id.bytes()[..]) {
+            return Ok(None);
+        }
+    }
+
+    let auth_data = match webauthn::parse_authenticator_data(&authenticator_data) {
+        Ok(auth_data) if auth_data.user_verified => auth_data,
+        _ => return Ok(None),
+    };
+    let public_key = match webauthn::decode(&passkey.public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return Ok(None),
+    };
+    if !webauthn::verify_signature(
+        &public_key,
+        &authenticator_data,
+        &client_data_json,
+        &signature,
+    ) {
+        return Ok(None);
+    }
+
+    let sign_count = auth_data.sign_count as i64;
+    if !webauthn::sign_count_advanced(passkey.sign_count, sign_count) {
+        return Ok(None);
+    }
+    if !passkey_repository::record_use(
+        passkeys,
+        passkey.id.unwrap(),
+        passkey.sign_count,
+        sign_count,
+    )
+    .await?
+    {
+        return Ok(None);
+    }
+    Ok(Some(passkey))
+}
+
+pub async fn get_passkeys_service(
+    passkeys: &Collection<Passkey>,
+    user_id: ObjectId,
+) -> Result<Vec<PasskeyResponse>, Error> {
+    let passkeys = passkey_repository::get_user_passkeys(passkeys, user_id).await?;
+    Ok(passkeys.into_iter().map(Passkey::to_passkey).collect())
+}
+
+// Rename one of the user's passkeys. The inner error is an invalid name; None
+// means the user has no such passkey.
+pub async fn rename_passkey_service(
+    passkeys: &Collection<Passkey>,
+    user_id: ObjectId,
+    passkey_id: ObjectId,
+    name: &str,
+) -> Result<Result<Option<PasskeyResponse>, String>, Error> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > PASSKEY_NAME_MAX_LENGTH {
+        return Ok(Err(format!(
+            "Name must be 1 to {} characters",
+            PASSKEY_NAME_MAX_LENGTH
+        )));
+    }
+    let renamed = passkey_repository::rename_passkey(passkeys, user_id, passkey_id, name).await?;
+    Ok(Ok(renamed.map(Passkey::to_passkey)))
+}
+
+pub async fn delete_passkey_service(
+    passkeys: &Collection<Passkey>,
+    user_id: ObjectId,
+    passkey_id: ObjectId,
+) -> Result<bool, Error> {
+    passkey_repository::delete_passkey(passkeys, user_id, passkey_id).await
+}
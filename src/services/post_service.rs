@@ -1,38 +1,398 @@
-use crate::post::{Post, PostRequest, PostResponse};
-use crate::repositories::post_repository;
+use crate::pagination::{Paginated, Pagination};
+use crate::post::{
+    Audience, Post, PostRequest, PostResponse, PostStatus, ThreadNode, ThreadResponse,
+};
+use crate::repost::Repost;
+use crate::models::notification::Notification;
+use crate::models::tag::Tag;
+use crate::models::user::User;
+use crate::models::follow::Follow;
+use crate::repositories::{follow_repository, post_repository, repost_repository, user_repository};
+use crate::models::revision::PostRevision;
+use crate::services::{entity_service, notification_service, revision_service, tag_service};
+use crate::search::{SearchDocument, SearchType};
+use crate::search_engine;
+use mongodb::bson::{doc, oid::ObjectId, Document};
 use mongodb::error::Error;
-use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+use mongodb::results::{InsertOneResult, UpdateResult};
 use mongodb::Collection;
+use std::collections::HashMap;
 
 pub async fn create_post_service(
     collection: &Collection<Post>,
-    new_post: Post,
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    follows: &Collection<Follow>,
+    notifications: &Collection<Notification>,
+    mut new_post: Post,
 ) -> Result<InsertOneResult, Error> {
-    post_repository::create_post(collection, new_post).await
+    let linked =
+        entity_service::link_entities_service(tags, users, new_post.author_id, &new_post.content)
+            .await?;
+    new_post.entities = linked.entities;
+    merge_tag_ids(&mut new_post.tag_ids, &linked.tag_ids);
+
+    let result = post_repository::create_post(collection, new_post.clone()).await?;
+    if new_post.status == PostStatus::Published {
+        new_post.id = result.inserted_id.as_object_id();
+        on_published(collection, tags, users, follows, notifications, &new_post).await?;
+    }
+    Ok(result)
+}
+
+// Side effects of a post becoming public: reply and tag usage counts, mention
+// notifications and the search index. Drafts and scheduled posts get them when published.
+async fn on_published(
+    collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    follows: &Collection<Follow>,
+    notifications: &Collection<Notification>,
+    post: &Post,
+) -> Result<(), Error> {
+    tag_service::refresh_usage_counts_service(tags, collection, &post.tag_ids).await?;
+    if let Some(parent_id) = post.reply_to {
+        post_repository::increment_count(collection, &parent_id, "replies_count", 1).await?;
+    }
+    if let Some(id) = post.id {
+        let mentioned_ids = entity_service::mentioned_ids(&post.entities);
+        notification_service::notify_mentions_service(
+            notifications,
+            follows,
+            users,
+            id,
+            post,
+            &mentioned_ids,
+        )
+        .await?;
+        search_engine::index_document(SearchDocument::new(SearchType::Posts, id, "", &post.content))
+            .await;
+    }
+    Ok(())
+}
+
+// Publish a draft or scheduled post of the author right away
+pub async fn publish_post_service(
+    collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    follows: &Collection<Follow>,
+    notifications: &Collection<Notification>,
+    post_id: ObjectId,
+    author_id: ObjectId,
+) -> Result<Option<Post>, Error> {
+    let filter = doc! { "_id": post_id, "author_id": author_id };
+    let post = post_repository::publish_post(collection, filter).await?;
+    if let Some(post) = &post {
+        on_published(collection, tags, users, follows, notifications, post).await?;
+    }
+    Ok(post)
+}
+
+// Publish every scheduled post that is due, returns how many were published
+pub async fn publish_due_posts_service(
+    collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    follows: &Collection<Follow>,
+    notifications: &Collection<Notification>,
+) -> Result<usize, Error> {
+    let mut published = 0;
+    while let Some(post) =
+        post_repository::publish_post(collection, post_repository::due_posts_filter()).await?
+    {
+        on_published(collection, tags, users, follows, notifications, &post).await?;
+        published += 1;
+    }
+    Ok(published)
+}
+
+pub async fn get_drafts_service(
+    collection: &Collection<Post>,
+    author_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    post_repository::get_drafts(collection, author_id, pagination).await
+}
+
+// Add the tags linked from hashtags to the explicitly chosen ones
+fn merge_tag_ids(tag_ids: &mut Vec<ObjectId>, linked: &[ObjectId]) {
+    for id in linked {
+        if !tag_ids.contains(id) {
+            tag_ids.push(*id);
+        }
+    }
+}
+
+pub async fn find_post_service(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+) -> Result<Option<Post>, Error> {
+    post_repository::find_post_by_id(collection, post_id).await
+}
+
+pub async fn find_visible_post_service(
+    collection: &Collection<Post>,
+    post_id: &ObjectId,
+    audience: &Audience,
+) -> Result<Option<Post>, Error> {
+    post_repository::find_visible_post(collection, post_id, audience).await
+}
+
+// Resolve the relationships that decide which posts `viewer_id` can read
+pub async fn get_audience_service(
+    follows: &Collection<Follow>,
+    users: &Collection<User>,
+    viewer_id: ObjectId,
+) -> Result<Audience, Error> {
+    Ok(Audience {
+        viewer_id,
+        following_ids: follow_repository::get_following_ids(follows, viewer_id).await?,
+        close_friend_of_ids: user_repository::get_close_friend_of_ids(users, viewer_id).await?,
+    })
 }
 
 pub async fn get_post_by_id_service(
     collection: &Collection<Post>,
     post_id: &str,
+    audience: &Audience,
 ) -> Result<Option<PostResponse>, Error> {
-    post_repository::get_post_by_id(collection, post_id).await
+    post_repository::get_post_by_id(collection, post_id, audience).await
 }
 
-pub async fn get_all_posts_service(collection: &Collection<Post>) -> Result<Vec<PostResponse>, Error> {
-    post_repository::get_all_posts(collection).await
+pub async fn get_all_posts_service(
+    collection: &Collection<Post>,
+    filter: Document,
+    audience: &Audience,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    post_repository::get_all_posts(collection, filter, audience, pagination).await
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_post_service(
     collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    users: &Collection<User>,
+    follows: &Collection<Follow>,
+    notifications: &Collection<Notification>,
+    revisions: &Collection<PostRevision>,
     post_id: &str,
-    updated_post: PostRequest,
+    mut updated_post: PostRequest,
+    editor_id: ObjectId,
 ) -> Result<UpdateResult, Error> {
-    post_repository::update_post(collection, post_id, updated_post).await
+    let existing = match ObjectId::parse_str(post_id) {
+        Ok(id) => post_repository::find_post_by_id(collection, &id).await?,
+        Err(_) => return Err(Error::custom("Invalid Post ID")),
+    };
+    let existing = match existing {
+        Some(post) => post,
+        // Nothing to link, the update won't match any post
+        None => {
+            return post_repository::update_post(collection, post_id, updated_post, &[], None, false)
+                .await
+        }
+    };
+    // Published posts keep their status, unpublished ones may be rescheduled or published
+    let published = existing.status == PostStatus::Published;
+    let schedule = match (published, updated_post.status, &updated_post.publish_at) {
+        (true, _, _) | (false, None, None) => None,
+        _ => Some(updated_post.schedule().map_err(Error::custom)?),
+    };
+    let publish_now = matches!(schedule, Some((PostStatus::Published, _)));
+    let schedule = schedule.filter(|(status, _)| *status != PostStatus::Published);
+
+    let linked =
+        entity_service::link_entities_service(tags, users, existing.author_id, &updated_post.content)
+            .await?;
+    merge_tag_ids(&mut updated_post.tags, &linked.tag_ids);
+
+    // Edits of drafts aren't kept, readers never saw them
+    if let (Some(id), true) = (existing.id, published) {
+        revision_service::record_revision_service(revisions, &existing, id, editor_id).await?;
+    }
+
+    let content = updated_post.content.clone();
+    // The post as readers will see it, for deciding who may hear of new mentions
+    let updated = Post {
+        entities: linked.entities.clone(),
+        visibility: updated_post.visibility.unwrap_or(existing.visibility),
+        ..existing.clone()
+    };
+    let mut changed_tags = existing.tag_ids.clone();
+    merge_tag_ids(&mut changed_tags, &updated_post.tags);
+    let result = post_repository::update_post(
+        collection,
+        post_id,
+        updated_post,
+        &linked.entities,
+        schedule,
+        published,
+    )
+    .await?;
+    if let (Some(id), true) = (existing.id, published) {
+        // Only users who weren't mentioned before are notified
+        let previous = entity_service::mentioned_ids(&existing.entities);
+        let new_mentions: Vec<ObjectId> = linked
+            .mentioned_ids
+            .into_iter()
+            .filter(|id| !previous.contains(id))
+            .collect();
+        notification_service::notify_mentions_service(
+            notifications,
+            follows,
+            users,
+            id,
+            &updated,
+            &new_mentions,
+        )
+        .await?;
+        search_engine::index_document(SearchDocument::new(SearchType::Posts, id, "", &content)).await;
+        tag_service::refresh_usage_counts_service(tags, collection, &changed_tags).await?;
+    }
+    if let (Some(id), true) = (existing.id, publish_now) {
+        if let Some(post) = post_repository::publish_post(collection, doc! { "_id": id }).await? {
+            on_published(collection, tags, users, follows, notifications, &post).await?;
+        }
+    }
+    Ok(result)
 }
 
+// Move the post to the trash; it disappears from every read path until restored
 pub async fn delete_post_service(
+    collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    post_id: &str,
+) -> Result<UpdateResult, Error> {
+    let existing = match ObjectId::parse_str(post_id) {
+        Ok(id) => post_repository::find_post_by_id(collection, &id).await?,
+        Err(_) => None,
+    };
+    let result = post_repository::delete_post(collection, post_id).await?;
+    if let (Some(post), 1..) = (existing, result.modified_count) {
+        if post.status != PostStatus::Published {
+            return Ok(result);
+        }
+        if let Some(parent_id) = post.reply_to {
+            post_repository::increment_count(collection, &parent_id, "replies_count", -1).await?;
+        }
+        tag_service::refresh_usage_counts_service(tags, collection, &post.tag_ids).await?;
+        if let Some(id) = post.id {
+            search_engine::remove_document(id).await;
+        }
+    }
+    Ok(result)
+}
+
+pub async fn restore_post_service(
+    collection: &Collection<Post>,
+    tags: &Collection<Tag>,
+    post_id: ObjectId,
+    author_id: ObjectId,
+) -> Result<Option<Post>, Error> {
+    let post = post_repository::restore_post(collection, post_id, author_id).await?;
+    if let Some(post) = post.as_ref().filter(|post| post.status == PostStatus::Published) {
+        if let Some(parent_id) = post.reply_to {
+            post_repository::increment_count(collection, &parent_id, "replies_count", 1).await?;
+        }
+        tag_service::refresh_usage_counts_service(tags, collection, &post.tag_ids).await?;
+        search_engine::index_document(SearchDocument::new(SearchType::Posts, post_id, "", &post.content))
+            .await;
+    }
+    Ok(post)
+}
+
+pub async fn get_deleted_posts_service(
+    collection: &Collection<Post>,
+    author_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostResponse>, Error> {
+    post_repository::get_deleted_posts(collection, author_id, pagination).await
+}
+
+// Permanently remove posts that have been in the trash since before `cutoff`,
+// together with their revisions. Returns how many posts were removed.
+pub async fn purge_deleted_posts_service(
+    collection: &Collection<Post>,
+    revisions: &Collection<PostRevision>,
+    cutoff: &str,
+) -> Result<usize, Error> {
+    let ids = post_repository::purge_deleted_posts(collection, cutoff).await?;
+    if !ids.is_empty() {
+        revision_service::delete_revisions_service(revisions, &ids).await?;
+    }
+    Ok(ids.len())
+}
+
+// The post with the conversation above it and the tree of replies below it
+// Posts the audience can't see are left out, together with the replies below them
+pub async fn get_thread_service(
     collection: &Collection<Post>,
     post_id: &str,
-) -> Result<DeleteResult, Error> {
-    post_repository::delete_post(collection, post_id).await
+    audience: &Audience,
+) -> Result<Option<ThreadResponse>, Error> {
+    let post = match post_repository::get_post_by_id(collection, post_id, audience).await? {
+        Some(post) => post,
+        None => return Ok(None),
+    };
+
+    let ancestor_ids = post_repository::get_ancestor_ids(collection, &post.id).await?;
+    let mut ancestors = post_repository::get_posts_by_ids(collection, &ancestor_ids, audience).await?;
+    ancestors.sort_by_key(|a| ancestor_ids.iter().position(|id| *id == a.id));
+
+    let descendant_ids = post_repository::get_descendant_ids(collection, &post.id).await?;
+    let mut replies_by_parent: HashMap<ObjectId, Vec<PostResponse>> = HashMap::new();
+    for reply in post_repository::get_posts_by_ids(collection, &descendant_ids, audience).await? {
+        if let Some(parent_id) = reply.reply_to {
+            replies_by_parent.entry(parent_id).or_default().push(reply);
+        }
+    }
+
+    Ok(Some(ThreadResponse {
+        ancestors,
+        replies: build_thread(&post.id, &mut replies_by_parent),
+        post,
+    }))
+}
+
+fn build_thread(
+    parent_id: &ObjectId,
+    replies_by_parent: &mut HashMap<ObjectId, Vec<PostResponse>>,
+) -> Vec<ThreadNode> {
+    replies_by_parent
+        .remove(parent_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reply| ThreadNode {
+            replies: build_thread(&reply.id, replies_by_parent),
+            post: reply,
+        })
+        .collect()
+}
+
+// Repost a post; reposting twice is a no-op
+pub async fn repost_post_service(
+    collection: &Collection<Post>,
+    reposts: &Collection<Repost>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<bool, Error> {
+    let created = repost_repository::create_repost(reposts, user_id, post_id).await?;
+    if created {
+        post_repository::increment_count(collection, &post_id, "reposts_count", 1).await?;
+    }
+    Ok(created)
+}
+
+pub async fn undo_repost_service(
+    collection: &Collection<Post>,
+    reposts: &Collection<Repost>,
+    user_id: ObjectId,
+    post_id: ObjectId,
+) -> Result<bool, Error> {
+    let removed = repost_repository::delete_repost(reposts, user_id, post_id).await?;
+    if removed {
+        post_repository::increment_count(collection, &post_id, "reposts_count", -1).await?;
+    }
+    Ok(removed)
 }
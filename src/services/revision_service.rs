@@ -0,0 +1,78 @@
+use crate::models::post::Post;
+use crate::models::revision::{PostRevision, RevisionDiff};
+use crate::pagination::{Paginated, Pagination};
+use crate::repositories::revision_repository;
+use crate::utils::diff::diff_words;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::error::Error;
+use mongodb::results::InsertOneResult;
+use mongodb::Collection;
+
+// Keep the current version of `post` before it gets replaced by an edit
+pub async fn record_revision_service(
+    collection: &Collection<PostRevision>,
+    post: &Post,
+    post_id: ObjectId,
+    edited_by: ObjectId,
+) -> Result<InsertOneResult, Error> {
+    let revision = PostRevision {
+        id: None,
+        post_id,
+        revision: revision_repository::next_revision_number(collection, post_id).await?,
+        edited_by,
+        content: post.content.clone(),
+        media: post.media.clone(),
+        tag_ids: post.tag_ids.clone(),
+        visibility: post.visibility,
+        created_at: DateTime::now(),
+    };
+    revision_repository::create_revision(collection, revision).await
+}
+
+pub async fn get_revisions_service(
+    collection: &Collection<PostRevision>,
+    post_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<PostRevision>, Error> {
+    revision_repository::get_revisions(collection, post_id, pagination).await
+}
+
+pub async fn find_revision_service(
+    collection: &Collection<PostRevision>,
+    post_id: ObjectId,
+    revision: i32,
+) -> Result<Option<PostRevision>, Error> {
+    revision_repository::find_revision(collection, post_id, revision).await
+}
+
+// Content changes made by the edit that replaced `revision`, compared with the
+// following revision or the current post for the latest one
+pub async fn diff_revision_service(
+    collection: &Collection<PostRevision>,
+    post: &Post,
+    post_id: ObjectId,
+    revision: i32,
+) -> Result<Option<RevisionDiff>, Error> {
+    let from = match revision_repository::find_revision(collection, post_id, revision).await? {
+        Some(from) => from,
+        None => return Ok(None),
+    };
+    let next = revision_repository::find_revision(collection, post_id, revision + 1).await?;
+    let (to_revision, to_content) = match &next {
+        Some(next) => (Some(next.revision), next.content.as_str()),
+        None => (None, post.content.as_str()),
+    };
+    Ok(Some(RevisionDiff {
+        from_revision: from.revision,
+        to_revision,
+        chunks: diff_words(&from.content, to_content),
+    }))
+}
+
+pub async fn delete_revisions_service(
+    collection: &Collection<PostRevision>,
+    post_ids: &[ObjectId],
+) -> Result<(), Error> {
+    revision_repository::delete_revisions_of_posts(collection, post_ids).await?;
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use crate::models::search::{query_terms, SearchDocument, SearchResponse, SearchTabs, SearchType};
+use crate::pagination::encode_offset;
+use crate::repositories::search_repository;
+use crate::search_engine::local_index::local_index;
+use crate::search_engine::{search_backend, SearchBackend};
+use crate::post::Audience;
+use crate::utils::helps::not_deleted;
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{error::Error, Database};
+
+// Prepare the configured backend: create MongoDB text indexes, or build the
+// local index from the database the first time it is opened
+pub async fn init_search_service(db: &Database) -> Result<(), Error> {
+    match search_backend() {
+        SearchBackend::MongoDb => search_repository::ensure_text_indexes(db).await,
+        SearchBackend::Local if local_index().is_empty() => rebuild_local_index_service(db).await,
+        SearchBackend::Local => Ok(()),
+    }
+}
+
+pub async fn rebuild_local_index_service(db: &Database) -> Result<(), Error> {
+    let mut documents = vec![];
+    for search_type in SearchType::ALL {
+        documents.extend(search_repository::get_all_documents(db, search_type).await?);
+    }
+    tokio::task::spawn_blocking(move || {
+        let index = local_index();
+        index.clear()?;
+        index.upsert(&documents)
+    })
+    .await
+    .map_err(|err| Error::custom(err.to_string()))?
+    .map_err(|err| Error::custom(err.to_string()))
+}
+
+// Posts are restricted to the ones `audience` may see
+pub async fn search_service(
+    db: &Database,
+    query: &str,
+    search_type: Option<SearchType>,
+    audience: &Audience,
+    offset: u64,
+    limit: i64,
+) -> Result<SearchResponse, Error> {
+    let (hits, tabs) = match search_backend() {
+        SearchBackend::MongoDb => {
+            search_mongodb(db, query, search_type, audience, offset, limit).await?
+        }
+        SearchBackend::Local => {
+            let query = query.to_string();
+            let hits = tokio::task::spawn_blocking(move || local_index().search(&query))
+                .await
+                .map_err(|err| Error::custom(err.to_string()))?
+                .map_err(|err| Error::custom(err.to_string()))?;
+            let hits = filter_visible_posts(db, hits, audience).await?;
+            page_hits(hits, search_type, offset as usize, limit as usize)
+        }
+    };
+
+    let terms = query_terms(query);
+    let next_offset = offset + limit as u64;
+    Ok(SearchResponse {
+        query: query.to_string(),
+        next_cursor: (next_offset < tabs.total(search_type)).then(|| encode_offset(next_offset)),
+        data: hits
+            .iter()
+            .map(|(score, doc)| doc.to_hit(*score, &terms))
+            .collect(),
+        tabs,
+        limit,
+    })
+}
+
+async fn search_mongodb(
+    db: &Database,
+    query: &str,
+    search_type: Option<SearchType>,
+    audience: &Audience,
+    offset: u64,
+    limit: i64,
+) -> Result<(Vec<(f64, SearchDocument)>, SearchTabs), Error> {
+    let filter = |t: SearchType| match t {
+        SearchType::Posts => audience.to_document(),
+        _ => not_deleted(doc! {}),
+    };
+
+    let mut tabs = SearchTabs::default();
+    for t in SearchType::ALL {
+        tabs.set(t, search_repository::count(db, t, query, filter(t)).await?);
+    }
+
+    let hits = match search_type {
+        Some(t) => search_repository::search(db, t, query, filter(t), offset, limit).await?,
+        // The "all" tab merges the best matches of every collection by score
+        None => {
+            let mut hits = vec![];
+            for t in SearchType::ALL {
+                hits.extend(
+                    search_repository::search(db, t, query, filter(t), 0, offset as i64 + limit)
+                        .await?,
+                );
+            }
+            hits.sort_by(|a, b| b.0.total_cmp(&a.0));
+            hits.into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+    };
+    Ok((hits, tabs))
+}
+
+// The local index doesn't know who may read a post, so post hits are checked
+// against the database before anything is counted
+async fn filter_visible_posts(
+    db: &Database,
+    hits: Vec<(f64, SearchDocument)>,
+    audience: &Audience,
+) -> Result<Vec<(f64, SearchDocument)>, Error> {
+    let post_ids: Vec<ObjectId> = hits
+        .iter()
+        .filter(|(_, doc)| doc.search_type == SearchType::Posts)
+        .map(|(_, doc)| doc.id)
+        .collect();
+    if post_ids.is_empty() {
+        return Ok(hits);
+    }
+    let visible = search_repository::filter_post_ids(db, &post_ids, audience.to_document()).await?;
+    Ok(hits
+        .into_iter()
+        .filter(|(_, doc)| doc.search_type != SearchType::Posts || visible.contains(&doc.id))
+        .collect())
+}
+
+// Tab counts of the hits, and the page of the requested tab
+fn page_hits(
+    hits: Vec<(f64, SearchDocument)>,
+    search_type: Option<SearchType>,
+    offset: usize,
+    limit: usize,
+) -> (Vec<(f64, SearchDocument)>, SearchTabs) {
+    let mut tabs = SearchTabs::default();
+    for t in SearchType::ALL {
+        tabs.set(t, hits.iter().filter(|(_, doc)| doc.search_type == t).count() as u64);
+    }
+    let page = hits
+        .into_iter()
+        .filter(|(_, doc)| search_type.is_none_or(|t| doc.search_type == t))
+        .skip(offset)
+        .take(limit)
+        .collect();
+    (page, tabs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits() -> Vec<(f64, SearchDocument)> {
+        [SearchType::Posts, SearchType::Items, SearchType::Posts, SearchType::Posts]
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (i as f64, SearchDocument::new(t, ObjectId::new(), "", "")))
+            .collect()
+    }
+
+    #[test]
+    fn counts_every_tab_and_pages_the_requested_one() {
+        let (page, tabs) = page_hits(hits(), Some(SearchType::Posts), 1, 1);
+        assert_eq!(tabs.total(Some(SearchType::Posts)), 3);
+        assert_eq!(tabs.total(Some(SearchType::Items)), 1);
+        assert_eq!(tabs.total(None), 4);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].0, 2.0);
+    }
+
+    #[test]
+    fn counts_only_what_is_left_after_filtering() {
+        let visible: Vec<_> = hits().into_iter().filter(|(score, _)| *score != 0.0).collect();
+        let (page, tabs) = page_hits(visible, None, 0, 10);
+        assert_eq!(tabs.total(Some(SearchType::Posts)), 2);
+        assert_eq!(page.len(), 3);
+    }
+}
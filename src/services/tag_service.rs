@@ -1,14 +1,32 @@
-use crate::tag::{Tag, TagRequest};
-use crate::tag_repository;
+use crate::pagination::{Paginated, Pagination};
+use crate::tag::{Tag, TagRequest, TagSuggestion, TagTaxonomy};
+use crate::search::{query_terms, SearchDocument, SearchType};
+use crate::search_engine;
+use crate::post::{Audience, EntityKind, Post};
+use crate::repositories::{post_repository, tag_subscription_repository};
+use crate::tag_subscription::TagSubscription;
+use crate::{search_service, tag_repository};
+use crate::utils::entities::extract_entities;
+use crate::utils::helps::{normalize_tag_name, slugify};
+use std::collections::{HashMap, HashSet};
+use mongodb::bson::{oid::ObjectId, Document};
 use mongodb::error::Error;
-use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
-use mongodb::Collection;
+use mongodb::results::{InsertOneResult, UpdateResult};
+use mongodb::{Collection, Database};
 
+// Fails with a duplicate key error when a tag with the same normalized name exists
 pub async fn create_tag_service(
     collection: &Collection<Tag>,
-    new_data: Tag,
+    mut new_data: Tag,
 ) -> Result<InsertOneResult, Error> {
-    tag_repository::create_tag(collection, new_data).await
+    new_data.normalized_name = normalize_tag_name(&new_data.name);
+    new_data.slug = unique_slug(collection, &new_data.name, None).await?;
+    let name = new_data.name.clone();
+    let result = tag_repository::create_tag(collection, new_data).await?;
+    if let Some(id) = result.inserted_id.as_object_id() {
+        search_engine::index_document(SearchDocument::new(SearchType::Tags, id, &name, "")).await;
+    }
+    Ok(result)
 }
 
 pub async fn get_tag_by_id_service(
@@ -18,8 +36,12 @@ pub async fn get_tag_by_id_service(
     tag_repository::get_tag_by_id(collection, obj_id).await
 }
 
-pub async fn get_all_tags_service(collection: &Collection<Tag>) -> Result<Vec<Tag>, Error> {
-    tag_repository::get_all_tags(collection).await
+pub async fn get_all_tags_service(
+    collection: &Collection<Tag>,
+    filter: Document,
+    pagination: &Pagination,
+) -> Result<Paginated<Tag>, Error> {
+    tag_repository::get_all_tags(collection, filter, pagination).await
 }
 
 pub async fn update_tag_service(
@@ -27,12 +49,483 @@ pub async fn update_tag_service(
     post_id: &str,
     updated_data: TagRequest,
 ) -> Result<UpdateResult, Error> {
-    tag_repository::update_tag(collection, post_id, updated_data).await
+    let existing = tag_repository::get_tag_by_id(collection, post_id).await?;
+    let name = updated_data.name.clone();
+    let normalized_name = normalize_tag_name(&name);
+    // Changing only the case keeps the slug
+    let (slug, previous_slug) = match existing {
+        Some(tag) if tag.normalized_name == normalized_name && !tag.slug.is_empty() => {
+            (tag.slug.clone(), tag.slug)
+        }
+        Some(tag) => (unique_slug(collection, &name, tag.id).await?, tag.slug),
+        None => (slugify(&name), String::new()),
+    };
+    let result = tag_repository::update_tag(
+        collection,
+        post_id,
+        updated_data,
+        &normalized_name,
+        &slug,
+        &previous_slug,
+    )
+    .await?;
+    if let (Ok(id), 1..) = (ObjectId::parse_str(post_id), result.matched_count) {
+        search_engine::index_document(SearchDocument::new(SearchType::Tags, id, &name, "")).await;
+    }
+    Ok(result)
 }
 
 pub async fn delete_tag_service(
     collection: &Collection<Tag>,
     obj_id: &str,
-) -> Result<DeleteResult, Error> {
-    tag_repository::delete_tag(collection, obj_id).await
+) -> Result<UpdateResult, Error> {
+    let result = tag_repository::delete_tag(collection, obj_id).await?;
+    if let Ok(id) = ObjectId::parse_str(obj_id) {
+        search_engine::remove_document(id).await;
+    }
+    Ok(result)
+}
+
+pub async fn restore_tag_service(
+    collection: &Collection<Tag>,
+    tag_id: ObjectId,
+    owner_id: ObjectId,
+) -> Result<Option<Tag>, Error> {
+    let tag = tag_repository::restore_tag(collection, tag_id, owner_id).await?;
+    if let Some(tag) = &tag {
+        search_engine::index_document(SearchDocument::new(SearchType::Tags, tag_id, &tag.name, "")).await;
+    }
+    Ok(tag)
+}
+
+pub async fn get_deleted_tags_service(
+    collection: &Collection<Tag>,
+    owner_id: ObjectId,
+    pagination: &Pagination,
+) -> Result<Paginated<Tag>, Error> {
+    tag_repository::get_deleted_tags(collection, owner_id, pagination).await
+}
+
+// Permanently remove tags deleted before `cutoff` and drop them from the posts
+// that still reference them. Returns how many tags were removed.
+pub async fn purge_deleted_tags_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    subscriptions: &Collection<TagSubscription>,
+    cutoff: &str,
+) -> Result<usize, Error> {
+    let ids = tag_repository::purge_deleted_tags(collection, cutoff).await?;
+    if !ids.is_empty() {
+        post_repository::replace_tag_references(posts, &ids, None).await?;
+        tag_repository::relink_taxonomy(collection, &ids, None).await?;
+        tag_subscription_repository::delete_subscriptions_of_tags(subscriptions, &ids).await?;
+    }
+    Ok(ids.len())
+}
+
+// Resolve a slug to its tag. The flag is true when the slug is an old one and
+// clients should be redirected to the tag's current slug.
+pub async fn find_tag_by_slug_service(
+    collection: &Collection<Tag>,
+    slug: &str,
+) -> Result<Option<(Tag, bool)>, Error> {
+    if let Some(tag) = tag_repository::find_tag_by_slug(collection, slug).await? {
+        return Ok(Some((tag, false)));
+    }
+    let tag = tag_repository::find_tag_by_previous_slug(collection, slug).await?;
+    Ok(tag.map(|tag| (tag, true)))
+}
+
+// Move every post of `source` to `target` and remove `source`. Its slugs keep
+// resolving to `target`. The steps aren't one transaction, but each can be
+// repeated: `source` is marked with its target first and only removed last, so
+// a merge that failed halfway is completed by running it again, which
+// `resume_tag_merges_service` does at startup.
+pub async fn merge_tags_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    subscriptions: &Collection<TagSubscription>,
+    source: &Tag,
+    target: &Tag,
+) -> Result<UpdateResult, Error> {
+    let (source_id, target_id) = match (source.id, target.id) {
+        (Some(source_id), Some(target_id)) => (source_id, target_id),
+        _ => return Err(Error::custom("Invalid TAG ID")),
+    };
+    if !tag_repository::mark_merging(collection, source_id, target_id).await? {
+        return Err(Error::custom("The tag is being merged into another tag"));
+    }
+    let result = post_repository::replace_tag_references(posts, &[source_id], Some(target_id)).await?;
+
+    // Children and synonyms of the source move to the target, or to the
+    // target's canonical tag when the target is a synonym of something else
+    let root = match target.canonical_id {
+        Some(canonical_id) if canonical_id != source_id => canonical_id,
+        Some(_) => {
+            tag_repository::set_canonical(collection, target_id, None).await?;
+            target_id
+        }
+        None => target_id,
+    };
+    if tag_repository::get_ancestor_ids(collection, root).await?.contains(&source_id) {
+        tag_repository::set_parent(collection, root, source.parent_id).await?;
+    }
+    tag_repository::relink_taxonomy(collection, &[source_id], Some(root)).await?;
+    tag_subscription_repository::move_subscriptions(subscriptions, source_id, target_id).await?;
+
+    let mut slugs = source.previous_slugs.clone();
+    if !source.slug.is_empty() {
+        slugs.push(source.slug.clone());
+    }
+    tag_repository::add_previous_slugs(collection, target_id, &slugs).await?;
+    refresh_usage_counts_service(collection, posts, &[target_id]).await?;
+    search_engine::remove_document(source_id).await;
+    tag_repository::remove_tag(collection, source_id).await?;
+    Ok(result)
+}
+
+// Finish the merges a crash or failed write left halfway by running them again.
+// A merge whose target is gone meanwhile is called off, keeping the source.
+pub async fn resume_tag_merges_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    subscriptions: &Collection<TagSubscription>,
+) -> Result<(), Error> {
+    for source in tag_repository::get_merging_tags(collection).await? {
+        let (Some(source_id), Some(target_id)) = (source.id, source.merging_into) else {
+            continue;
+        };
+        match tag_repository::get_tag_by_id(collection, &target_id.to_hex()).await? {
+            Some(target) => {
+                merge_tags_service(collection, posts, subscriptions, &source, &target).await?;
+            }
+            None => {
+                tag_repository::clear_merging(collection, source_id).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Slug for `name` that no other live tag uses, numbered when taken
+async fn unique_slug(
+    collection: &Collection<Tag>,
+    name: &str,
+    except: Option<ObjectId>,
+) -> Result<String, Error> {
+    let base = match slugify(name) {
+        slug if slug.is_empty() => "tag".to_string(),
+        slug => slug,
+    };
+    let mut slug = base.clone();
+    let mut n = 1;
+    while tag_repository::slug_exists(collection, &slug, except).await? {
+        n += 1;
+        slug = format!("{}-{}", base, n);
+    }
+    Ok(slug)
+}
+
+// Recount `usage_count` of the tags from the posts carrying them
+pub async fn refresh_usage_counts_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    tag_ids: &[ObjectId],
+) -> Result<(), Error> {
+    if tag_ids.is_empty() {
+        return Ok(());
+    }
+    let counts = post_repository::count_tag_usage(posts, Some(tag_ids)).await?;
+    for id in tag_ids {
+        let count = counts.get(id).copied().unwrap_or_default();
+        tag_repository::set_usage_count(collection, *id, count).await?;
+    }
+    Ok(())
+}
+
+// Bring tags created before names were normalized in line: fill in the
+// normalized name and slug, merge tags that turn out to be duplicates into the
+// oldest one and recount usage. Runs at startup, before the unique indexes
+// are created.
+pub async fn normalize_tags_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    subscriptions: &Collection<TagSubscription>,
+) -> Result<(), Error> {
+    let mut kept: HashMap<String, Tag> = HashMap::new();
+    let mut used_slugs: HashSet<String> = HashSet::new();
+    for mut tag in tag_repository::get_live_tags(collection).await? {
+        let normalized_name = normalize_tag_name(&tag.name);
+        if let Some(target) = kept.get(&normalized_name) {
+            println!("Merging duplicate tag '{}' into '{}'", tag.name, target.name);
+            merge_tags_service(collection, posts, subscriptions, &tag, target).await?;
+            continue;
+        }
+        let Some(id) = tag.id else { continue };
+        let slug_taken = tag.slug.is_empty() || used_slugs.contains(&tag.slug);
+        if slug_taken || tag.normalized_name != normalized_name {
+            if slug_taken {
+                tag.slug = unique_slug(collection, &tag.name, Some(id)).await?;
+            }
+            tag_repository::set_normalized_fields(collection, id, &normalized_name, &tag.slug).await?;
+            tag.normalized_name = normalized_name.clone();
+        }
+        used_slugs.insert(tag.slug.clone());
+        kept.insert(normalized_name, tag);
+    }
+
+    let counts = post_repository::count_tag_usage(posts, None).await?;
+    for tag in kept.values() {
+        let count = tag.id.and_then(|id| counts.get(&id)).copied().unwrap_or_default();
+        if let (Some(id), true) = (tag.id, tag.usage_count != count) {
+            tag_repository::set_usage_count(collection, id, count).await?;
+        }
+    }
+    Ok(())
+}
+
+// Candidates considered before ranking by the prefix completions
+const SUGGEST_CANDIDATES: i64 = 50;
+// Similar posts the content suggestions are drawn from
+const SIMILAR_POSTS: i64 = 50;
+// Terms of the draft used to look up similar posts
+const MAX_CONTENT_TERMS: usize = 32;
+
+fn to_suggestion(tag: Tag, score: f64) -> TagSuggestion {
+    TagSuggestion {
+        usage_count: tag.usage_count,
+        tag: Tag::to_tag(tag),
+        score,
+    }
+}
+
+fn rank(mut suggestions: Vec<TagSuggestion>, limit: usize) -> Vec<TagSuggestion> {
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.name.cmp(&b.tag.name)));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+// Completions for `prefix`, ranked by overall popularity and how often the
+// user picked the tag before. An exact match always comes first.
+pub async fn suggest_tags_service(
+    collection: &Collection<Tag>,
+    posts: &Collection<Post>,
+    user_id: ObjectId,
+    prefix: &str,
+    limit: usize,
+) -> Result<Vec<TagSuggestion>, Error> {
+    let prefix = normalize_tag_name(prefix);
+    let history = post_repository::count_author_tag_usage(posts, user_id).await?;
+    let history_ids: Vec<ObjectId> = history.keys().copied().collect();
+
+    // The user's own tags may be too rare to make the popular candidates
+    let mut candidates: HashMap<ObjectId, Tag> = HashMap::new();
+    let popular = tag_repository::find_tags_by_prefix(collection, &prefix, None, SUGGEST_CANDIDATES).await?;
+    let own = tag_repository::find_tags_by_prefix(collection, &prefix, Some(&history_ids), SUGGEST_CANDIDATES).await?;
+    for tag in popular.into_iter().chain(own) {
+        if let Some(id) = tag.id {
+            candidates.insert(id, tag);
+        }
+    }
+
+    let suggestions = candidates
+        .into_iter()
+        .map(|(id, tag)| {
+            let own_count = history.get(&id).copied().unwrap_or_default();
+            let mut score = (1.0 + tag.usage_count.max(0) as f64).ln()
+                + 2.0 * (1.0 + own_count as f64).ln();
+            if tag.normalized_name == prefix {
+                score += 100.0;
+            }
+            to_suggestion(tag, score)
+        })
+        .collect();
+    Ok(rank(suggestions, limit))
+}
+
+// Tags for a post being composed. Posts similar to the draft, as found by the
+// search backend, vote for their tags by relevance; tags named by a word of the
+// draft get a boost. Tags the draft already has as hashtags are left out.
+pub async fn suggest_tags_for_content_service(
+    db: &Database,
+    content: &str,
+    audience: &Audience,
+    limit: usize,
+) -> Result<Vec<TagSuggestion>, Error> {
+    let collection: Collection<Tag> = db.collection("tags");
+    let posts: Collection<Post> = db.collection("posts");
+
+    let mut terms: Vec<String> = Vec::new();
+    for term in query_terms(content) {
+        if term.chars().count() >= 3 && !terms.contains(&term) && terms.len() < MAX_CONTENT_TERMS {
+            terms.push(term);
+        }
+    }
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let present: HashSet<String> = extract_entities(content)
+        .into_iter()
+        .filter(|entity| entity.kind == EntityKind::Hashtag)
+        .map(|entity| normalize_tag_name(&entity.text))
+        .collect();
+
+    let results = search_service::search_service(
+        db,
+        &terms.join(" "),
+        Some(SearchType::Posts),
+        audience,
+        0,
+        SIMILAR_POSTS,
+    )
+    .await?;
+    let relevance: HashMap<ObjectId, f64> = results
+        .data
+        .iter()
+        .filter_map(|hit| ObjectId::parse_str(&hit.id).ok().map(|id| (id, hit.score)))
+        .collect();
+    let best = relevance.values().copied().fold(0.0, f64::max);
+
+    let mut scores: HashMap<ObjectId, f64> = HashMap::new();
+    let similar: Vec<ObjectId> = relevance.keys().copied().collect();
+    for post in post_repository::find_posts_by_ids(&posts, &similar).await? {
+        let weight = match (post.id, best > 0.0) {
+            (Some(id), true) => relevance.get(&id).copied().unwrap_or_default() / best,
+            _ => 0.0,
+        };
+        for tag_id in post.tag_ids {
+            *scores.entry(tag_id).or_default() += weight;
+        }
+    }
+    for tag in tag_repository::find_tags_by_names(&collection, &terms).await? {
+        if let Some(id) = tag.id {
+            *scores.entry(id).or_default() += 1.0;
+        }
+    }
+
+    let ids: Vec<ObjectId> = scores.keys().copied().collect();
+    let suggestions = tag_repository::get_tags_by_ids(&collection, &ids)
+        .await?
+        .into_iter()
+        .filter(|tag| !present.contains(&tag.normalized_name))
+        .filter_map(|tag| {
+            let score = scores.get(&tag.id?).copied()?;
+            Some(to_suggestion(tag, score))
+        })
+        .collect();
+    Ok(rank(suggestions, limit))
+}
+
+// Ids of the tags whose posts show up when browsing the tag: its synonym group
+// and everything filed below it, with their synonyms too
+pub async fn expand_tag_service(collection: &Collection<Tag>, tag: &Tag) -> Result<Vec<ObjectId>, Error> {
+    let root = match tag.canonical_id.or(tag.id) {
+        Some(id) => id,
+        None => return Err(Error::custom("Invalid TAG ID")),
+    };
+    let mut ids = vec![root];
+    ids.extend(tag_repository::get_descendant_ids(collection, root).await?);
+    let synonyms = tag_repository::get_synonyms(collection, &ids).await?;
+    ids.extend(synonyms.into_iter().filter_map(|synonym| synonym.id));
+    if let Some(id) = tag.id.filter(|id| !ids.contains(id)) {
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+pub async fn get_taxonomy_service(collection: &Collection<Tag>, tag: Tag) -> Result<TagTaxonomy, Error> {
+    let tag_id = match tag.id {
+        Some(id) => id,
+        None => return Err(Error::custom("Invalid TAG ID")),
+    };
+    let canonical = match tag.canonical_id {
+        Some(id) => tag_repository::get_tag_by_id(collection, &id.to_hex()).await?.map(Tag::to_tag),
+        None => None,
+    };
+    let ancestor_ids = tag_repository::get_ancestor_ids(collection, tag_id).await?;
+    let mut ancestors: HashMap<ObjectId, Tag> = tag_repository::get_tags_by_ids(collection, &ancestor_ids)
+        .await?
+        .into_iter()
+        .filter_map(|tag| tag.id.map(|id| (id, tag)))
+        .collect();
+    let children = tag_repository::get_children(collection, tag_id).await?;
+    let synonyms = tag_repository::get_synonyms(collection, &[tag_id]).await?;
+    Ok(TagTaxonomy {
+        tag,
+        canonical,
+        ancestors: ancestor_ids
+            .iter()
+            .filter_map(|id| ancestors.remove(id).map(Tag::to_tag))
+            .collect(),
+        children: children.into_iter().map(Tag::to_tag).collect(),
+        synonyms: synonyms.into_iter().map(Tag::to_tag).collect(),
+    })
+}
+
+// File `tag` under `parent`, or detach it with None. The outer error is a
+// database failure, the inner one a taxonomy rule the change would break.
+pub async fn set_tag_parent_service(
+    collection: &Collection<Tag>,
+    tag: &Tag,
+    parent: Option<&Tag>,
+) -> Result<Result<Tag, String>, Error> {
+    let tag_id = match tag.id {
+        Some(id) => id,
+        None => return Err(Error::custom("Invalid TAG ID")),
+    };
+    if let Some(parent) = parent {
+        let parent_id = match parent.id {
+            Some(id) => id,
+            None => return Err(Error::custom("Invalid TAG ID")),
+        };
+        if parent_id == tag_id {
+            return Ok(Err("A tag can't be its own parent".to_string()));
+        }
+        if tag.canonical_id.is_some() || parent.canonical_id.is_some() {
+            return Ok(Err("Synonyms can't be part of the hierarchy".to_string()));
+        }
+        if tag_repository::get_ancestor_ids(collection, parent_id).await?.contains(&tag_id) {
+            return Ok(Err("A tag can't be filed under one of its descendants".to_string()));
+        }
+    }
+    let parent_id = parent.and_then(|parent| parent.id);
+    match tag_repository::set_parent(collection, tag_id, parent_id).await? {
+        Some(tag) => Ok(Ok(tag)),
+        None => Err(Error::custom("TAG not found")),
+    }
+}
+
+// Make `tag` a synonym of `canonical`, or canonical again with None
+pub async fn set_tag_canonical_service(
+    collection: &Collection<Tag>,
+    tag: &Tag,
+    canonical: Option<&Tag>,
+) -> Result<Result<Tag, String>, Error> {
+    let tag_id = match tag.id {
+        Some(id) => id,
+        None => return Err(Error::custom("Invalid TAG ID")),
+    };
+    if let Some(canonical) = canonical {
+        let canonical_id = match canonical.id {
+            Some(id) => id,
+            None => return Err(Error::custom("Invalid TAG ID")),
+        };
+        if canonical_id == tag_id {
+            return Ok(Err("A tag can't be a synonym of itself".to_string()));
+        }
+        if canonical.canonical_id.is_some() {
+            return Ok(Err("The canonical tag is itself a synonym".to_string()));
+        }
+        if !tag_repository::get_synonyms(collection, &[tag_id]).await?.is_empty() {
+            return Ok(Err("The tag has synonyms of its own".to_string()));
+        }
+        if !tag_repository::get_children(collection, tag_id).await?.is_empty() {
+            return Ok(Err("The tag has child tags".to_string()));
+        }
+    }
+    let canonical_id = canonical.and_then(|canonical| canonical.id);
+    match tag_repository::set_canonical(collection, tag_id, canonical_id).await? {
+        Some(tag) => Ok(Ok(tag)),
+        None => Err(Error::custom("TAG not found")),
+    }
 }
@@ -0,0 +1,76 @@
+use crate::models::tag::Tag;
+use crate::models::tag_subscription::{SubscriptionKind, TagSubscription, TagSubscriptionResponse};
+use crate::pagination::{Paginated, Pagination};
+use crate::repositories::{tag_repository, tag_subscription_repository};
+use crate::services::tag_service;
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// Returns true when the subscription changed
+pub async fn subscribe_tag_service(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    tag_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<bool, Error> {
+    tag_subscription_repository::subscribe(collection, user_id, tag_id, kind).await
+}
+
+pub async fn unsubscribe_tag_service(
+    collection: &Collection<TagSubscription>,
+    user_id: ObjectId,
+    tag_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<bool, Error> {
+    tag_subscription_repository::unsubscribe(collection, user_id, tag_id, kind).await
+}
+
+// Followed or muted tags of the user; subscriptions of trashed tags are skipped
+pub async fn get_subscribed_tags_service(
+    collection: &Collection<TagSubscription>,
+    tags: &Collection<Tag>,
+    user_id: ObjectId,
+    kind: SubscriptionKind,
+    pagination: &Pagination,
+) -> Result<Paginated<TagSubscriptionResponse>, Error> {
+    let page = tag_subscription_repository::get_subscriptions(collection, user_id, kind, pagination).await?;
+    let tag_ids: Vec<ObjectId> = page.data.iter().map(|s| s.tag_id).collect();
+    let found = tag_repository::get_tags_by_ids(tags, &tag_ids).await?;
+    let data = page
+        .data
+        .into_iter()
+        .filter_map(|subscription| {
+            let tag = found.iter().find(|t| t.id == Some(subscription.tag_id))?.clone();
+            Some(TagSubscriptionResponse {
+                tag: Tag::to_tag(tag),
+                kind: subscription.kind,
+                created_at: subscription.created_at.try_to_rfc3339_string().unwrap_or_default(),
+            })
+        })
+        .collect();
+    Ok(Paginated {
+        data,
+        next_cursor: page.next_cursor,
+        limit: page.limit,
+    })
+}
+
+// Tags the user follows or mutes, expanded with their child tags and synonyms
+// the same way browsing a tag is
+pub async fn get_expanded_tag_ids_service(
+    collection: &Collection<TagSubscription>,
+    tags: &Collection<Tag>,
+    user_id: ObjectId,
+    kind: SubscriptionKind,
+) -> Result<Vec<ObjectId>, Error> {
+    let tag_ids = tag_subscription_repository::get_tag_ids(collection, user_id, kind).await?;
+    let mut expanded: Vec<ObjectId> = vec![];
+    for tag in tag_repository::get_tags_by_ids(tags, &tag_ids).await? {
+        for id in tag_service::expand_tag_service(tags, &tag).await? {
+            if !expanded.contains(&id) {
+                expanded.push(id);
+            }
+        }
+    }
+    Ok(expanded)
+}
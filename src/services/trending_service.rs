@@ -0,0 +1,108 @@
+use crate::models::post::{Audience, Post};
+use crate::models::tag::Tag;
+use crate::models::trending::{
+    TrendingEntry, TrendingKind, TrendingPost, TrendingResponse, TrendingSnapshot, TrendingTag,
+    TrendingWindow, TRENDING_SIZE,
+};
+use crate::repositories::{post_repository, tag_repository, trending_repository};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::error::Error;
+use mongodb::Collection;
+use std::collections::HashMap;
+
+// Recompute every ranking and replace the cached snapshots
+pub async fn refresh_trending_service(
+    snapshots: &Collection<TrendingSnapshot>,
+    posts: &Collection<Post>,
+) -> Result<(), Error> {
+    for window in TrendingWindow::ALL {
+        let tags = trending_repository::compute_trending_tags(posts, window, TRENDING_SIZE).await?;
+        save(snapshots, TrendingKind::Tags, window, tags).await?;
+        let top = trending_repository::compute_trending_posts(posts, window, TRENDING_SIZE).await?;
+        save(snapshots, TrendingKind::Posts, window, top).await?;
+    }
+    Ok(())
+}
+
+async fn save(
+    snapshots: &Collection<TrendingSnapshot>,
+    kind: TrendingKind,
+    window: TrendingWindow,
+    entries: Vec<TrendingEntry>,
+) -> Result<(), Error> {
+    let snapshot = TrendingSnapshot {
+        id: TrendingSnapshot::key(kind, window),
+        kind,
+        window,
+        entries,
+        computed_at: DateTime::now(),
+    };
+    trending_repository::save_snapshot(snapshots, snapshot).await
+}
+
+// Cached entries of a ranking, cut to `limit`
+async fn cached_entries(
+    snapshots: &Collection<TrendingSnapshot>,
+    kind: TrendingKind,
+    window: TrendingWindow,
+    limit: usize,
+) -> Result<(Option<String>, Vec<TrendingEntry>), Error> {
+    match trending_repository::find_snapshot(snapshots, kind, window).await? {
+        Some(mut snapshot) => {
+            snapshot.entries.truncate(limit);
+            Ok((snapshot.computed_at.try_to_rfc3339_string().ok(), snapshot.entries))
+        }
+        None => Ok((None, Vec::new())),
+    }
+}
+
+pub async fn get_trending_tags_service(
+    snapshots: &Collection<TrendingSnapshot>,
+    tags: &Collection<Tag>,
+    window: TrendingWindow,
+    limit: usize,
+) -> Result<TrendingResponse<TrendingTag>, Error> {
+    let (computed_at, entries) = cached_entries(snapshots, TrendingKind::Tags, window, limit).await?;
+    let ids: Vec<ObjectId> = entries.iter().map(|entry| entry.id).collect();
+    // Tags trashed since the last run drop out
+    let mut found: HashMap<ObjectId, Tag> = tag_repository::get_tags_by_ids(tags, &ids)
+        .await?
+        .into_iter()
+        .filter_map(|tag| tag.id.map(|id| (id, tag)))
+        .collect();
+    let data = entries
+        .into_iter()
+        .filter_map(|entry| {
+            found.remove(&entry.id).map(|tag| TrendingTag {
+                usage_count: tag.usage_count,
+                tag: Tag::to_tag(tag),
+                score: entry.score,
+            })
+        })
+        .collect();
+    Ok(TrendingResponse { window, computed_at, data })
+}
+
+pub async fn get_trending_posts_service(
+    snapshots: &Collection<TrendingSnapshot>,
+    posts: &Collection<Post>,
+    window: TrendingWindow,
+    limit: usize,
+    audience: &Audience,
+) -> Result<TrendingResponse<TrendingPost>, Error> {
+    let (computed_at, entries) = cached_entries(snapshots, TrendingKind::Posts, window, limit).await?;
+    let ids: Vec<ObjectId> = entries.iter().map(|entry| entry.id).collect();
+    // Posts deleted or made private since the last run drop out here
+    let mut found: HashMap<ObjectId, _> = post_repository::get_posts_by_ids(posts, &ids, audience)
+        .await?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect();
+    let data = entries
+        .into_iter()
+        .filter_map(|entry| {
+            found.remove(&entry.id).map(|post| TrendingPost { post, score: entry.score })
+        })
+        .collect();
+    Ok(TrendingResponse { window, computed_at, data })
+}
@@ -0,0 +1,94 @@
+use crate::models::two_factor::TwoFactorSetupResponse;
+use crate::models::user::User;
+use crate::repositories::user_repository;
+use crate::utils::random_token::hash_token;
+use crate::utils::totp;
+use mongodb::bson::oid::ObjectId;
+use mongodb::{error::Error, Collection};
+
+// Start setting up two-factor authentication with a new secret. The inner
+// error means it is enabled already.
+pub async fn start_setup_service(
+    collection: &Collection<User>,
+    user: &User,
+) -> Result<Result<TwoFactorSetupResponse, String>, Error> {
+    let already_enabled = || Ok(Err("Two-factor authentication is already enabled".to_string()));
+    if user.two_factor.enabled {
+        return already_enabled();
+    }
+    let secret = totp::generate_secret();
+    let otpauth_uri = totp::otpauth_uri(&secret, &user.username).map_err(Error::custom)?;
+    if !user_repository::set_pending_two_factor(collection, user.id, &secret).await? {
+        return already_enabled();
+    }
+    Ok(Ok(TwoFactorSetupResponse { secret, otpauth_uri }))
+}
+
+// Enable two-factor authentication once a code shows the authenticator app has
+// the pending secret. Returns the recovery codes, which aren't shown again.
+pub async fn confirm_setup_service(
+    collection: &Collection<User>,
+    user: &User,
+    code: &str,
+) -> Result<Result<Vec<String>, String>, Error> {
+    let secret = match (&user.two_factor.pending_secret, user.two_factor.enabled) {
+        (Some(secret), false) => secret,
+        _ => return Ok(Err("No two-factor setup in progress".to_string())),
+    };
+    let step = match totp::matching_step(secret, code.trim(), None) {
+        Some(step) => step,
+        None => return Ok(Err("Invalid authentication code".to_string())),
+    };
+    let recovery_codes = totp::generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_token(&totp::normalize_recovery_code(code)))
+        .collect();
+    if !user_repository::enable_two_factor(collection, user.id, secret, step, &hashes).await? {
+        return Ok(Err("Two-factor setup was restarted, confirm the new secret".to_string()));
+    }
+    Ok(Ok(recovery_codes))
+}
+
+// Check a code from the authenticator app or a recovery code, using it up
+pub async fn verify_code_service(
+    collection: &Collection<User>,
+    user: &User,
+    code: &str,
+) -> Result<bool, Error> {
+    let two_factor = &user.two_factor;
+    let secret = match (&two_factor.secret, two_factor.enabled) {
+        (Some(secret), true) => secret,
+        _ => return Ok(false),
+    };
+    let code = code.trim();
+    if totp::is_totp_code(code) {
+        return match totp::matching_step(secret, code, two_factor.last_used_step) {
+            Some(step) => user_repository::use_totp_step(collection, user.id, step).await,
+            None => Ok(false),
+        };
+    }
+    let hash = hash_token(&totp::normalize_recovery_code(code));
+    user_repository::use_recovery_code(collection, user.id, &hash).await
+}
+
+// Turn two-factor authentication off, which takes a valid code
+pub async fn disable_service(
+    collection: &Collection<User>,
+    user: &User,
+    code: &str,
+) -> Result<Result<(), String>, Error> {
+    if !verify_code_service(collection, user, code).await? {
+        return Ok(Err("Invalid authentication code".to_string()));
+    }
+    user_repository::clear_two_factor(collection, user.id).await?;
+    Ok(Ok(()))
+}
+
+// For admins, when a user has lost both their authenticator and recovery codes
+pub async fn reset_two_factor_service(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+) -> Result<bool, Error> {
+    user_repository::clear_two_factor(collection, user_id).await
+}
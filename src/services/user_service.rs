@@ -1,6 +1,12 @@
-use crate::models::user::User;
-use crate::user_repository;
+use crate::models::user::{PrivacyUpdateRequest, ProfileUpdateRequest, User};
+use crate::search::{SearchDocument, SearchType};
+use crate::utils::errors::is_duplicate_key;
+use crate::utils::helps::{days_ago_rfc3339, username_key};
+use crate::utils::uploads;
+use crate::{get_config, search_engine, user_repository};
 use anyhow::Result;
+use mongodb::bson::oid::ObjectId;
+use mongodb::results::UpdateResult;
 use mongodb::{error::Error, Collection};
 
 // Service to get an item by username
@@ -18,3 +24,116 @@ pub async fn get_user_by_id_service(
 ) -> Result<Option<User>, Error> {
     user_repository::get_user_by_id_service(collection, id).await
 }
+
+pub async fn add_close_friend_service(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    friend_id: ObjectId,
+) -> Result<UpdateResult, Error> {
+    user_repository::add_close_friend(collection, user_id, friend_id).await
+}
+
+pub async fn remove_close_friend_service(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    friend_id: ObjectId,
+) -> Result<UpdateResult, Error> {
+    user_repository::remove_close_friend(collection, user_id, friend_id).await
+}
+
+pub async fn get_users_by_ids_service(
+    collection: &Collection<User>,
+    ids: &[ObjectId],
+) -> Result<Vec<User>, Error> {
+    user_repository::get_users_by_ids(collection, ids).await
+}
+
+// Apply a validated profile update. The outer error is a database failure, the
+// inner one a reason to refuse the change.
+pub async fn update_profile_service(
+    collection: &Collection<User>,
+    user: &User,
+    update: ProfileUpdateRequest,
+) -> Result<Result<User, String>, Error> {
+    // Sending the current username again isn't a change
+    let username = update.username.filter(|username| *username != user.username);
+    if let Some(username) = &username {
+        if user_repository::username_taken(collection, username, Some(user.id)).await? {
+            return Ok(Err("Username is already taken".to_string()));
+        }
+    }
+    let cutoff = days_ago_rfc3339(get_config().username_change_cooldown_days);
+    let updated = match user_repository::update_profile(
+        collection,
+        user.id,
+        username.as_deref(),
+        update.bio.as_deref(),
+        &cutoff,
+    )
+    .await
+    {
+        Ok(updated) => updated,
+        // Taken by someone else since the check
+        Err(err) if is_duplicate_key(&err) => return Ok(Err("Username is already taken".to_string())),
+        Err(err) => return Err(err),
+    };
+    match updated {
+        Some(updated) => {
+            let bio = updated.bio.as_deref().unwrap_or_default();
+            search_engine::index_document(SearchDocument::new(SearchType::Users, updated.id, &updated.username, bio))
+                .await;
+            Ok(Ok(updated))
+        }
+        None => Ok(Err(format!(
+            "Username can only be changed once every {} days",
+            get_config().username_change_cooldown_days
+        ))),
+    }
+}
+
+// Use a finished upload as the user's avatar; None removes the avatar
+pub async fn set_avatar_service(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    upload_id: Option<&str>,
+) -> Result<Result<User, String>, Error> {
+    let owner = user_id.to_hex();
+    let avatar = match upload_id {
+        Some(upload_id) => match uploads::claim_avatar(upload_id, &owner).await {
+            Ok(path) => Some(path),
+            Err(reason) => return Ok(Err(reason)),
+        },
+        None => None,
+    };
+    let previous = match user_repository::set_avatar(collection, user_id, avatar.as_deref()).await? {
+        Some(previous) => previous,
+        None => return Err(Error::custom("User not found")),
+    };
+    if let Some(old) = previous.avatar.as_deref().filter(|old| Some(*old) != avatar.as_deref()) {
+        uploads::remove_avatar(old, &owner).await;
+    }
+    Ok(Ok(User { avatar, ..previous }))
+}
+
+pub async fn update_privacy_service(
+    collection: &Collection<User>,
+    user_id: ObjectId,
+    update: &PrivacyUpdateRequest,
+) -> Result<Option<User>, Error> {
+    user_repository::update_privacy(collection, user_id, update.to_document()).await
+}
+
+// Fill in `username_key` for accounts from before it existed. Accounts whose
+// username looks like one already taken are left without one and reported.
+pub async fn backfill_username_keys_service(collection: &Collection<User>) -> Result<(), Error> {
+    for user in user_repository::get_users_without_username_key(collection).await? {
+        match user_repository::set_username_key(collection, user.id, &username_key(&user.username)).await {
+            Ok(_) => {}
+            Err(err) if is_duplicate_key(&err) => {
+                println!("Username '{}' of user {} collides with another account", user.username, user.id)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+// A run of text that is kept, added or removed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiffChunk {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+// Word level diff of `old` against `new`. Concatenating the equal and delete
+// chunks gives back `old`, the equal and insert chunks give `new`.
+pub fn diff_words(old: &str, new: &str) -> Vec<DiffChunk> {
+    let old: Vec<&str> = old.split_inclusive(char::is_whitespace).collect();
+    let new: Vec<&str> = new.split_inclusive(char::is_whitespace).collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut chunks: Vec<DiffChunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let (op, token) = if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            (DiffOp::Equal, old[i - 1])
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            j += 1;
+            (DiffOp::Insert, new[j - 1])
+        } else {
+            i += 1;
+            (DiffOp::Delete, old[i - 1])
+        };
+        match chunks.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(token),
+            _ => chunks.push(DiffChunk {
+                op,
+                text: token.to_string(),
+            }),
+        }
+    }
+    chunks
+}
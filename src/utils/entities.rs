@@ -0,0 +1,56 @@
+use crate::post::EntityKind;
+
+// A #hashtag or @mention found in post content. Offsets are in chars and
+// cover the sigil, `text` doesn't include it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEntity {
+    pub kind: EntityKind,
+    pub start: u32,
+    pub end: u32,
+    pub text: String,
+}
+
+pub fn extract_entities(content: &str) -> Vec<RawEntity> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut entities = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let kind = match chars[i] {
+            '#' => EntityKind::Hashtag,
+            '@' => EntityKind::Mention,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        // A sigil glued to a word is part of it, e.g. an email address or "C#"
+        if i > 0 && is_entity_char(chars[i - 1]) {
+            i += 1;
+            continue;
+        }
+
+        let end = (i + 1..chars.len())
+            .find(|&j| !is_entity_char(chars[j]))
+            .unwrap_or(chars.len());
+        let text: String = chars[i + 1..end].iter().collect();
+        // Hashtags need at least one letter so "#1" isn't a tag
+        let valid = match kind {
+            EntityKind::Hashtag => text.chars().any(char::is_alphabetic),
+            EntityKind::Mention => !text.is_empty(),
+        };
+        if valid {
+            entities.push(RawEntity {
+                kind,
+                start: i as u32,
+                end: end as u32,
+                text,
+            });
+        }
+        i = end.max(i + 1);
+    }
+    entities
+}
+
+fn is_entity_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,11 @@
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+
+// Whether the operation was rejected by a unique index
+pub fn is_duplicate_key(err: &Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match err.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
@@ -0,0 +1,75 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::str::FromStr;
+
+use crate::get_config;
+
+// How new passwords are hashed, from PASSWORD_HASH_ALGORITHM and the cost settings
+enum Hasher {
+    Bcrypt { cost: u32 },
+    Argon2id { params: Params },
+}
+
+fn configured_hasher() -> Result<Hasher, String> {
+    let config = get_config();
+    match config.password_hash_algorithm.as_str() {
+        "bcrypt" => Ok(Hasher::Bcrypt { cost: config.bcrypt_cost }),
+        "argon2id" => Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )
+        .map(|params| Hasher::Argon2id { params })
+        .map_err(|err| format!("Invalid Argon2 parameters: {}", err)),
+        other => Err(format!("Unknown password hash algorithm '{}'", other)),
+    }
+}
+
+fn argon2(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    match configured_hasher()? {
+        Hasher::Bcrypt { cost } => bcrypt::hash(password, cost).map_err(|err| err.to_string()),
+        Hasher::Argon2id { params } => argon2(params)
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .map(|hash| hash.to_string())
+            .map_err(|err| err.to_string()),
+    }
+}
+
+// Checks against either kind of hash, whatever is configured now
+pub fn verify_password(password: &str, hash: &str) -> Result<bool, String> {
+    if hash.starts_with("$argon2") {
+        let parsed = PasswordHash::new(hash).map_err(|err| err.to_string())?;
+        return Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok());
+    }
+    bcrypt::verify(password, hash).map_err(|err| err.to_string())
+}
+
+// Whether the hash was made with another algorithm or other costs than the
+// configured ones
+pub fn needs_rehash(hash: &str) -> bool {
+    let Ok(hasher) = configured_hasher() else {
+        return false;
+    };
+    match hasher {
+        Hasher::Bcrypt { cost } => bcrypt::HashParts::from_str(hash)
+            .map(|parts| parts.get_cost() != cost)
+            .unwrap_or(true),
+        Hasher::Argon2id { params } => {
+            let Ok(parsed) = PasswordHash::new(hash) else {
+                return true;
+            };
+            let current = Params::try_from(&parsed);
+            parsed.algorithm != Algorithm::Argon2id.ident()
+                || !current.is_ok_and(|current| {
+                    current.m_cost() == params.m_cost()
+                        && current.t_cost() == params.t_cost()
+                        && current.p_cost() == params.p_cost()
+                })
+        }
+    }
+}
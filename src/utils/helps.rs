@@ -1,10 +1,11 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime, Document};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
 
 // Serialize Vec<ObjectId> to Vec<String>
 pub fn serialize_object_id_vec_as_string_vec<S>(
-    tags: &Vec<ObjectId>,
+    tags: &[ObjectId],
     serializer: S,
 ) -> Result<S::Ok, S::Error>
 where
@@ -27,3 +28,116 @@ where
         .map(|s| ObjectId::from_str(&s).map_err(serde::de::Error::custom))
         .collect()
 }
+
+// Serialize Option<ObjectId> to Option<String>
+pub fn serialize_option_object_id_as_hex_string<S>(
+    id: &Option<ObjectId>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    id.map(|id| id.to_hex()).serialize(serializer)
+}
+
+// Escape a user supplied string so it can be embedded in a `$regex`
+pub fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Current time in the RFC 3339 format the models store their timestamps in
+pub fn now_rfc3339() -> String {
+    DateTime::now()
+        .try_to_rfc3339_string()
+        .expect("valid timestamp")
+}
+
+// Same format, `days` ago; used for retention windows
+pub fn days_ago_rfc3339(days: i64) -> String {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - days * 24 * 60 * 60 * 1000)
+        .try_to_rfc3339_string()
+        .expect("valid timestamp")
+}
+
+// Same format, `seconds` ago; used for rate limits
+pub fn seconds_ago_rfc3339(seconds: i64) -> String {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - seconds * 1000)
+        .try_to_rfc3339_string()
+        .expect("valid timestamp")
+}
+
+// Same format, `seconds` from now; used for expiry times
+pub fn seconds_from_now_rfc3339(seconds: i64) -> String {
+    seconds_ago_rfc3339(-seconds)
+}
+
+// Exclude soft deleted documents, which are the only ones with a `deleted_at`
+pub fn not_deleted(mut filter: Document) -> Document {
+    filter.insert("deleted_at", Bson::Null);
+    filter
+}
+
+// URL slug of a name: lowercase alphanumeric words joined by dashes
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// Form tag names are compared in: NFKC normalized, lowercased, single spaced
+pub fn normalize_tag_name(name: &str) -> String {
+    name.nfkc()
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Form usernames are compared in for uniqueness: NFKC normalized, lowercased and
+// with look-alike characters folded together, so "Admin", "adm1n" and "ａｄｍｉｎ"
+// all collide
+pub fn username_key(username: &str) -> String {
+    let folded: String = username
+        .nfkc()
+        .collect::<String>()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '5' => 's',
+            c => c,
+        })
+        .collect();
+    folded.replace("rn", "m").replace("vv", "w")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn username_key_folds_look_alikes() {
+        let admin = username_key("admin");
+        for lookalike in ["Admin", "ADMIN", "adm1n", "admln", "ａｄｍｉｎ", "adrnin"] {
+            assert_eq!(username_key(lookalike), admin, "{}", lookalike);
+        }
+        assert_eq!(username_key("vvalter"), username_key("walter"));
+        assert_eq!(username_key("b0b5"), username_key("BOBS"));
+        assert_ne!(username_key("alice"), username_key("alicia"));
+    }
+}
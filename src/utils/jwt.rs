@@ -7,10 +7,15 @@ use crate::get_config;
 pub struct Claims {
     pub sub: String, // User ID
     pub role: String, // User Role
-    pub exp: usize, // Expiration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>, // Expiration, none for access tokens that don't expire
+    #[serde(default)]
+    pub ver: i32, // User's token version, bumped to revoke their tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>, // Set when a personal access token was used
 }
 
-pub fn create_jwt(user_id: &str, role: &str) -> Result<String, jsonwebtoken::errors::Error> {
+pub fn create_jwt(user_id: &str, role: &str, version: i32) -> Result<String, jsonwebtoken::errors::Error> {
     let secret_key = get_config().jwt_secret;
     let expiration = chrono::Utc::now()
         .checked_add_signed(chrono::Duration::days(1))
@@ -20,7 +25,9 @@ pub fn create_jwt(user_id: &str, role: &str) -> Result<String, jsonwebtoken::err
     let claims = Claims {
         sub: user_id.to_owned(),
         role: role.to_owned(),
-        exp: expiration,
+        exp: Some(expiration),
+        ver: version,
+        scopes: None,
     };
 
     encode(&Header::default(), &claims, &EncodingKey::from_secret(secret_key.as_bytes()))
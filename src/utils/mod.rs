@@ -1,3 +1,12 @@
 pub mod jwt;
 pub mod session;
-pub mod helps;
\ No newline at end of file
+pub mod helps;
+pub mod entities;
+pub mod diff;
+pub mod errors;
+pub mod uploads;
+pub mod signed_token;
+pub mod random_token;
+pub mod hashing;
+pub mod totp;
+pub mod webauthn;
\ No newline at end of file
@@ -0,0 +1,133 @@
+use rand::Rng;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::get_config;
+
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+// Codes from the neighbouring steps are accepted too, for clock drift
+const SKEW_STEPS: i64 = 1;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// New base32 encoded secret for an authenticator app
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: Option<String>, account_name: &str) -> Result<TOTP, String> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| format!("{:?}", err))?;
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW_STEPS as u8,
+        STEP_SECS,
+        bytes,
+        issuer,
+        account_name.replace(':', ""),
+    )
+    .map_err(|err| err.to_string())
+}
+
+// otpauth:// URI authenticator apps take, usually as a QR code
+pub fn otpauth_uri(secret: &str, account_name: &str) -> Result<String, String> {
+    let issuer = get_config().totp_issuer.replace(':', "");
+    Ok(totp(secret, Some(issuer), account_name)?.get_url())
+}
+
+// The time step `code` is valid for, if it is. Steps up to `after` are
+// refused, so a code can't be used twice.
+pub fn matching_step(secret: &str, code: &str, after: Option<i64>) -> Option<i64> {
+    matching_step_at(secret, code, after, chrono::Utc::now().timestamp())
+}
+
+fn matching_step_at(secret: &str, code: &str, after: Option<i64>, now: i64) -> Option<i64> {
+    let totp = totp(secret, None, "").ok()?;
+    let current = now / STEP_SECS as i64;
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .filter(|step| after.is_none_or(|after| *step > after))
+        .find(|step| totp.generate(*step as u64 * STEP_SECS) == code)
+}
+
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+// One-time codes for when the authenticator is lost, e.g. "k3x9q-7mfd2"
+pub fn generate_recovery_codes() -> Vec<String> {
+    const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+// Form recovery codes are hashed in, forgiving case and missing dashes
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_lowercase().replace([' ', '-'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn code_at_step(secret: &str, step: i64) -> String {
+        totp(secret, None, "").unwrap().generate(step as u64 * STEP_SECS)
+    }
+
+    #[test]
+    fn accepts_codes_within_the_skew() {
+        let secret = generate_secret();
+        let current = NOW / STEP_SECS as i64;
+        for step in [current - 1, current, current + 1] {
+            let code = code_at_step(&secret, step);
+            assert_eq!(matching_step_at(&secret, &code, None, NOW), Some(step));
+        }
+        let stale = code_at_step(&secret, current - 2);
+        assert_eq!(matching_step_at(&secret, &stale, None, NOW), None);
+        assert_eq!(matching_step_at(&secret, "not a code", None, NOW), None);
+    }
+
+    #[test]
+    fn refuses_steps_already_used() {
+        let secret = generate_secret();
+        let current = NOW / STEP_SECS as i64;
+        let code = code_at_step(&secret, current);
+        let used = matching_step_at(&secret, &code, None, NOW);
+        assert_eq!(matching_step_at(&secret, &code, used, NOW), None);
+
+        // Neither can an older code once a newer one went through
+        let previous = code_at_step(&secret, current - 1);
+        assert_eq!(matching_step_at(&secret, &previous, used, NOW), None);
+        let next = code_at_step(&secret, current + 1);
+        assert_eq!(matching_step_at(&secret, &next, used, NOW), Some(current + 1));
+    }
+
+    #[test]
+    fn recognizes_totp_codes() {
+        assert!(is_totp_code("012345"));
+        assert!(!is_totp_code("12345"));
+        assert!(!is_totp_code("k3x9q-7mfd2"));
+    }
+
+    #[test]
+    fn recovery_codes_survive_retyping() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            let retyped = format!(" {} ", code.to_uppercase().replace('-', " "));
+            assert_eq!(normalize_recovery_code(&retyped), normalize_recovery_code(code));
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// Where `/upload_chunk` reassembles finished uploads as `{user_id}_{upload_id}.{ext}`
+pub const UPLOAD_DIR: &str = "uploads";
+const AVATAR_DIR: &str = "uploads/avatars";
+const AVATAR_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+pub const AVATAR_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
+// Upload ids are picked by clients, so only plain ones may touch the filesystem
+pub fn valid_upload_id(upload_id: &str) -> bool {
+    !upload_id.is_empty()
+        && upload_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Name of a user's upload on disk; it records who uploaded it
+pub fn upload_name(user_id: &str, upload_id: &str) -> String {
+    format!("{}_{}", user_id, upload_id)
+}
+
+// Path of the finished upload with this id, if the user uploaded it
+pub async fn find_upload(upload_id: &str, user_id: &str) -> std::io::Result<Option<PathBuf>> {
+    if !valid_upload_id(upload_id) {
+        return Ok(None);
+    }
+    let prefix = format!("{}.", upload_name(user_id, upload_id));
+    let mut entries = fs::read_dir(UPLOAD_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) && entry.file_type().await?.is_file() {
+            return Ok(Some(entry.path()));
+        }
+    }
+    Ok(None)
+}
+
+// Move a finished upload into the avatar directory, so it can't be claimed
+// twice, and return its new path. Errors are meant for the client.
+pub async fn claim_avatar(upload_id: &str, user_id: &str) -> Result<String, String> {
+    let path = match find_upload(upload_id, user_id).await {
+        Ok(Some(path)) => path,
+        Ok(None) => return Err("Upload not found".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    if !AVATAR_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(format!("Avatar must be one of: {}", AVATAR_EXTENSIONS.join(", ")));
+    }
+    let size = fs::metadata(&path).await.map_err(|err| err.to_string())?.len();
+    if size > AVATAR_MAX_BYTES {
+        return Err(format!("Avatar must be at most {} bytes", AVATAR_MAX_BYTES));
+    }
+
+    fs::create_dir_all(AVATAR_DIR).await.map_err(|err| err.to_string())?;
+    let target = format!("{}/{}_{}.{}", AVATAR_DIR, user_id, upload_id, extension);
+    fs::rename(&path, &target).await.map_err(|err| err.to_string())?;
+    Ok(target)
+}
+
+// Delete an avatar file the user owned; anything else is left alone
+pub async fn remove_avatar(avatar: &str, user_id: &str) {
+    let path = Path::new(avatar);
+    let owned = path.parent() == Some(Path::new(AVATAR_DIR))
+        && path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(&format!("{}_", user_id)));
+    if owned {
+        if let Err(err) = fs::remove_file(path).await {
+            println!("Failed to remove avatar {}: {}", avatar, err);
+        }
+    }
+}
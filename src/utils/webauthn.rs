@@ -0,0 +1,162 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ciborium::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::get_config;
+
+// COSE algorithm identifier of ES256, the only one accepted
+pub const ES256: i64 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+pub fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Browsers send base64url; padding and the standard alphabet are tolerated
+pub fn decode(value: &str) -> Result<Vec<u8>, String> {
+    let value = value
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_");
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| "Invalid base64url value".to_string())
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+// Check the client data of a ceremony and return the challenge it answers, for
+// the caller to match against the ones it issued
+pub fn verify_client_data(client_data_json: &[u8], expected_type: &str) -> Result<String, String> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).map_err(|_| "Invalid client data".to_string())?;
+    if client_data.kind != expected_type {
+        return Err("Unexpected ceremony type".to_string());
+    }
+    if client_data.origin != get_config().webauthn_origin {
+        return Err("Unexpected origin".to_string());
+    }
+    Ok(client_data.challenge)
+}
+
+pub struct AuthenticatorData {
+    pub user_verified: bool,
+    pub sign_count: u32,
+    // Present at registration: credential id and COSE public key
+    pub credential: Option<(Vec<u8>, Value)>,
+}
+
+pub fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, String> {
+    let invalid = || "Invalid authenticator data".to_string();
+    if data.len() < 37 {
+        return Err(invalid());
+    }
+    let rp_id_hash = Sha256::digest(get_config().webauthn_rp_id.as_bytes());
+    if data[..32] != rp_id_hash[..] {
+        return Err("Credential is for another relying party".to_string());
+    }
+    let flags = data[32];
+    if flags & FLAG_USER_PRESENT == 0 {
+        return Err("User presence is required".to_string());
+    }
+    let sign_count = u32::from_be_bytes(data[33..37].try_into().map_err(|_| invalid())?);
+
+    let credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // AAGUID (16 bytes), id length (2 bytes), id, then the CBOR key
+        let rest = data.get(37 + 16..).ok_or_else(invalid)?;
+        let id_length = rest.get(..2).ok_or_else(invalid)?;
+        let id_length = u16::from_be_bytes([id_length[0], id_length[1]]) as usize;
+        let id = rest.get(2..2 + id_length).ok_or_else(invalid)?.to_vec();
+        let mut key_bytes = rest.get(2 + id_length..).ok_or_else(invalid)?;
+        let key: Value = ciborium::de::from_reader(&mut key_bytes).map_err(|_| invalid())?;
+        Some((id, key))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        user_verified: flags & FLAG_USER_VERIFIED != 0,
+        sign_count,
+        credential,
+    })
+}
+
+// Authenticator data from an attestation object. The attestation statement
+// isn't checked, as registrations ask for none.
+pub fn parse_attestation_object(attestation_object: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid attestation object".to_string();
+    let value: Value = ciborium::de::from_reader(attestation_object).map_err(|_| invalid())?;
+    let entries = value.as_map().ok_or_else(invalid)?;
+    entries
+        .iter()
+        .find(|(key, _)| key.as_text() == Some("authData"))
+        .and_then(|(_, value)| value.as_bytes())
+        .cloned()
+        .ok_or_else(invalid)
+}
+
+fn cose_field(key: &Value, label: i64) -> Option<&Value> {
+    key.as_map()?.iter().find_map(|(name, value)| {
+        let name: i128 = name.as_integer()?.into();
+        (name == label as i128).then_some(value)
+    })
+}
+
+// SEC1 encoding of an ES256 COSE key (EC2 key type, P-256 curve)
+pub fn cose_key_to_sec1(key: &Value) -> Result<Vec<u8>, String> {
+    let integer = |label| {
+        cose_field(key, label)
+            .and_then(Value::as_integer)
+            .map(i128::from)
+    };
+    if integer(1) != Some(2) || integer(3) != Some(ES256 as i128) || integer(-1) != Some(1) {
+        return Err("Only ES256 (P-256) passkeys are supported".to_string());
+    }
+    let coordinate = |label| {
+        cose_field(key, label)
+            .and_then(Value::as_bytes)
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or_else(|| "Invalid public key".to_string())
+    };
+    let mut sec1 = vec![0x04];
+    sec1.extend_from_slice(coordinate(-2)?);
+    sec1.extend_from_slice(coordinate(-3)?);
+    VerifyingKey::from_sec1_bytes(&sec1).map_err(|_| "Invalid public key".to_string())?;
+    Ok(sec1)
+}
+
+// A counter that doesn't go up means a copy of the key is in use somewhere.
+// Authenticators without a counter always report zero.
+pub fn sign_count_advanced(stored: i64, received: i64) -> bool {
+    (stored == 0 && received == 0) || received > stored
+}
+
+// Check an assertion signature, made over the authenticator data followed by
+// the hash of the client data
+pub fn verify_signature(
+    public_key: &[u8],
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> bool {
+    let Ok(key) = VerifyingKey::from_sec1_bytes(public_key) else {
+        return false;
+    };
+    let Ok(signature) = Signature::from_der(signature) else {
+        return false;
+    };
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+    key.verify(&message, &signature).is_ok()
+}
@@ -0,0 +1,151 @@
+mod utils;
+
+use server::utils::webauthn::{
+    cose_key_to_sec1, parse_attestation_object, parse_authenticator_data, sign_count_advanced,
+    verify_client_data, verify_signature,
+};
+use utils::{init_env, SoftAuthenticator};
+
+// What a registration stores: the credential id, SEC1 public key and counter
+fn register(
+    authenticator: &SoftAuthenticator,
+    challenge: &str,
+) -> Result<(Vec<u8>, Vec<u8>, u32), String> {
+    let client_data = authenticator.client_data("webauthn.create", challenge);
+    assert_eq!(
+        verify_client_data(&client_data, "webauthn.create")?,
+        challenge
+    );
+    let auth_data = parse_authenticator_data(&parse_attestation_object(
+        &authenticator.attestation_object(),
+    )?)?;
+    assert!(auth_data.user_verified);
+    let (credential_id, key) = auth_data
+        .credential
+        .ok_or("Attestation has no credential")?;
+    Ok((credential_id, cose_key_to_sec1(&key)?, auth_data.sign_count))
+}
+
+// Whether an assertion would log in against what registration stored
+fn log_in(
+    stored: &(Vec<u8>, Vec<u8>, u32),
+    assertion: &(Vec<u8>, Vec<u8>, Vec<u8>),
+    challenge: &str,
+) -> Result<u32, String> {
+    let (auth_data, client_data, signature) = assertion;
+    assert_eq!(verify_client_data(client_data, "webauthn.get")?, challenge);
+    let parsed = parse_authenticator_data(auth_data)?;
+    if !verify_signature(&stored.1, auth_data, client_data, signature) {
+        return Err("Bad signature".to_string());
+    }
+    if !sign_count_advanced(stored.2 as i64, parsed.sign_count as i64) {
+        return Err("Sign count didn't advance".to_string());
+    }
+    Ok(parsed.sign_count)
+}
+
+#[test]
+fn registers_a_passkey() {
+    init_env();
+    let authenticator = SoftAuthenticator::new(1);
+    let (credential_id, public_key, sign_count) = register(&authenticator, "challenge").unwrap();
+    assert_eq!(credential_id, authenticator.credential_id);
+    assert_eq!(public_key.len(), 65);
+    assert_eq!(sign_count, 0);
+}
+
+#[test]
+fn logs_in_with_a_registered_passkey() {
+    init_env();
+    let mut authenticator = SoftAuthenticator::new(2);
+    let mut stored = register(&authenticator, "register").unwrap();
+    for challenge in ["first", "second"] {
+        let assertion = authenticator.assert(challenge);
+        stored.2 = log_in(&stored, &assertion, challenge).unwrap();
+    }
+    assert_eq!(stored.2, 2);
+}
+
+#[test]
+fn rejects_a_replayed_or_decreased_sign_count() {
+    init_env();
+    let mut authenticator = SoftAuthenticator::new(3);
+    let mut stored = register(&authenticator, "register").unwrap();
+    let assertion = authenticator.assert("login");
+    stored.2 = log_in(&stored, &assertion, "login").unwrap();
+
+    assert!(log_in(&stored, &assertion, "login").is_err());
+    authenticator.sign_count = 0;
+    let cloned = authenticator.assert("clone");
+    assert!(log_in(&stored, &cloned, "clone").is_err());
+}
+
+#[test]
+fn accepts_authenticators_without_a_counter() {
+    assert!(sign_count_advanced(0, 0));
+    assert!(sign_count_advanced(0, 1));
+    assert!(!sign_count_advanced(5, 0));
+    assert!(!sign_count_advanced(5, 5));
+}
+
+#[test]
+fn rejects_a_wrong_origin() {
+    init_env();
+    let mut authenticator = SoftAuthenticator::new(4);
+    authenticator.origin = "https://evil.example".to_string();
+    let client_data = authenticator.client_data("webauthn.create", "challenge");
+    assert_eq!(
+        verify_client_data(&client_data, "webauthn.create"),
+        Err("Unexpected origin".to_string())
+    );
+    let client_data = authenticator.client_data("webauthn.get", "challenge");
+    assert!(verify_client_data(&client_data, "webauthn.create").is_err());
+}
+
+#[test]
+fn rejects_a_wrong_rp_id_hash() {
+    init_env();
+    let mut authenticator = SoftAuthenticator::new(5);
+    let stored = register(&authenticator, "register").unwrap();
+    authenticator.rp_id = "evil.example".to_string();
+
+    assert_eq!(
+        register(&authenticator, "register").unwrap_err(),
+        "Credential is for another relying party"
+    );
+    let assertion = authenticator.assert("login");
+    assert_eq!(
+        log_in(&stored, &assertion, "login").unwrap_err(),
+        "Credential is for another relying party"
+    );
+}
+
+#[test]
+fn rejects_a_bad_signature() {
+    init_env();
+    let mut authenticator = SoftAuthenticator::new(6);
+    let stored = register(&authenticator, "register").unwrap();
+
+    let (auth_data, client_data, mut signature) = authenticator.assert("login");
+    let last = signature.len() - 1;
+    signature[last] ^= 0x01;
+    assert!(log_in(
+        &stored,
+        &(auth_data.clone(), client_data.clone(), signature),
+        "login"
+    )
+    .is_err());
+
+    // Signed by another key
+    let mut other = SoftAuthenticator::new(7);
+    other.sign_count = authenticator.sign_count;
+    let forged = other.assert("login");
+    assert!(log_in(&stored, &forged, "login").is_err());
+
+    // Client data swapped after signing
+    let (auth_data, _, signature) = authenticator.assert("login");
+    let swapped = authenticator.client_data("webauthn.get", "other");
+    assert!(!verify_signature(
+        &stored.1, &auth_data, &swapped, &signature
+    ));
+}
@@ -0,0 +1,113 @@
+use ciborium::Value;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use sha2::{Digest, Sha256};
+use std::sync::Once;
+
+use server::utils::webauthn::ES256;
+
+pub const RP_ID: &str = "localhost";
+pub const ORIGIN: &str = "http://localhost:8080";
+
+static ENV: Once = Once::new();
+
+// The settings get_config() insists on, plus the relying party under test
+pub fn init_env() {
+    ENV.call_once(|| {
+        std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::set_var("JWT_EXPIRATION", "3600");
+        std::env::set_var("DB_URL", "mongodb://localhost:27017");
+        std::env::set_var("DB_NAME", "test");
+        std::env::set_var("WEBAUTHN_RP_ID", RP_ID);
+        std::env::set_var("WEBAUTHN_ORIGIN", ORIGIN);
+    });
+}
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// A passkey held in memory, answering ceremonies the way a browser would
+// relay them
+pub struct SoftAuthenticator {
+    pub credential_id: Vec<u8>,
+    pub rp_id: String,
+    pub origin: String,
+    pub sign_count: u32,
+    key: SigningKey,
+}
+
+impl SoftAuthenticator {
+    pub fn new(seed: u8) -> Self {
+        SoftAuthenticator {
+            credential_id: vec![seed; 16],
+            rp_id: RP_ID.to_string(),
+            origin: ORIGIN.to_string(),
+            sign_count: 0,
+            key: SigningKey::from_slice(&[seed; 32]).unwrap(),
+        }
+    }
+
+    pub fn client_data(&self, kind: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": self.origin,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn authenticator_data(&self, flags: u8) -> Vec<u8> {
+        let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+
+    pub fn cose_key(&self) -> Value {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let int = |value: i64| Value::Integer(value.into());
+        Value::Map(vec![
+            (int(1), int(2)),
+            (int(3), int(ES256)),
+            (int(-1), int(1)),
+            (int(-2), Value::Bytes(point.x().unwrap().to_vec())),
+            (int(-3), Value::Bytes(point.y().unwrap().to_vec())),
+        ])
+    }
+
+    // Attestation object of a new credential, with a "none" statement
+    pub fn attestation_object(&self) -> Vec<u8> {
+        let mut auth_data = self
+            .authenticator_data(FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::ser::into_writer(&self.cose_key(), &mut auth_data).unwrap();
+
+        let object = Value::Map(vec![
+            (Value::Text("fmt".into()), Value::Text("none".into())),
+            (Value::Text("attStmt".into()), Value::Map(vec![])),
+            (Value::Text("authData".into()), Value::Bytes(auth_data)),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&object, &mut bytes).unwrap();
+        bytes
+    }
+
+    // Authenticator data, client data and DER signature of an assertion. The
+    // counter goes up first, as on a real authenticator.
+    pub fn assert(&mut self, challenge: &str) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        self.sign_count += 1;
+        let auth_data = self.authenticator_data(FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+        let client_data = self.client_data("webauthn.get", challenge);
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&message);
+        (
+            auth_data,
+            client_data,
+            signature.to_der().as_bytes().to_vec(),
+        )
+    }
+}